@@ -0,0 +1,343 @@
+#version 100
+// Version can't be changed to upper versions because of WebGL.
+
+precision highp float;
+
+
+
+// ---------------------------------------------------------------------------
+// Vector and ray math -------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+struct Ray
+{
+    vec4 o; // Origin.
+    vec4 d; // Direction.
+};
+
+const Ray ray_none = Ray(vec4(0.), vec4(0.));
+
+// Return ray, trat is transformed used matrix. NOTE: Do not forget to normalize new r.d!!! If your `t` depends on it, memorize it somewhere.
+Ray transform(mat4 matrix, Ray r) {
+    return Ray(
+        matrix * r.o,
+        matrix * r.d
+    );
+}
+
+// ---------------------------------------------------------------------------
+// Surface intersection ------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Intersection with some surface.
+struct SurfaceIntersection {
+    bool hit; // Is intersect.
+    float t; // Distance to surface.
+    float u; // X position on surface.
+    float v; // Y position on surface.
+    vec3 n; // Normal at intersection point.
+};
+
+// No intersection.
+const SurfaceIntersection intersection_none = SurfaceIntersection(false, 1e10, 0., 0., vec3(0.));
+
+// ---------------------------------------------------------------------------
+// Color utils ---------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Forms color that next can be alpha-corrected. You should use this function instead of vec3(r, g, b), because of alpha-correction.
+vec3 color(float r, float g, float b) {
+    return vec3(r*r, g*g, b*b);
+}
+
+// Returns how this normal should change color.
+float color_normal(vec3 normal, vec4 direction) {
+    return abs(dot(normalize(direction.xyz), normalize(normal)));
+}
+
+// Returns grid color based on position and start color. Copy-pasted somewhere from shadertoy.
+vec3 color_grid(vec3 start, vec2 uv) {
+    uv /= 8.;
+    uv = uv - vec2(0.125, 0.125);
+    const float fr = 3.14159*8.0;
+    vec3 col = start;
+    col += 0.4*smoothstep(-0.01,0.01,cos(uv.x*fr*0.5)*cos(uv.y*fr*0.5)); 
+    float wi = smoothstep(-1.0,-0.98,cos(uv.x*fr))*smoothstep(-1.0,-0.98,cos(uv.y*fr));
+    col *= wi;
+    
+    return col;
+}
+
+// Adds color `b` to color `a` with coef, that must lie in [0..1]. If coef == 0, then result is `a`, if coef == 1.0, then result is `b`.
+vec3 color_add_weighted(vec3 a, vec3 b, float coef) {
+    return a*(1.0 - coef) + b*coef;
+}
+
+// Result after material processing.
+struct MaterialProcessing {
+    bool is_final; // If this flag set to false, then next ray tracing will be proceed. Useful for: portals, glass, mirrors, etc.
+    vec3 mul_to_color; // If is_final = true, then this color is multiplied to current color, otherwise this is the final color.
+    Ray new_ray; // New ray if is_final = true.
+};
+
+// Shortcut for creating material with is_final = true.
+MaterialProcessing material_final(vec3 color) {
+    return MaterialProcessing(true, color, ray_none);    
+}
+
+// Function to easy write simple material.
+MaterialProcessing material_simple(
+    SurfaceIntersection hit, Ray r,
+    vec3 color, float normal_coef, 
+    bool grid, float grid_scale, float grid_coef
+) {
+    color = color_add_weighted(color, color * color_normal(hit.n, r.d), normal_coef);
+    if (grid) {
+        color = color_add_weighted(color, color_grid(color, vec2(hit.u, hit.v) * grid_scale), grid_coef);
+    }
+    return material_final(color);
+}
+
+// System materials
+#define NOT_INSIDE 0
+
+// Actual predefined materials
+#define DEBUG_RED 2
+#define DEBUG_GREEN 3
+#define DEBUG_BLUE 4
+
+// ---------------------------------------------------------------------------
+// Scene intersection --------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Intersection with material.
+struct SceneIntersection {
+    int material;
+    SurfaceIntersection hit;
+};
+
+bool nearer(SurfaceIntersection result, SurfaceIntersection current) {
+    return current.hit && (current.t > 0.) && (!result.hit || (result.hit && current.t < result.t));
+}
+
+bool nearer(SceneIntersection result, SurfaceIntersection current) {
+    return nearer(result.hit, current);
+}
+
+bool nearer(SceneIntersection result, SceneIntersection current) {
+    return nearer(result, current.hit);
+}
+
+// Distance along normalized ray to axis-aligned box, 0 when ray starts inside it, and -1 when ray misses it.
+float box_bounds(Ray r, vec3 box_min, vec3 box_max) {
+    vec3 inv = 1. / r.d.xyz;
+    vec3 t1 = (box_min - r.o.xyz) * inv;
+    vec3 t2 = (box_max - r.o.xyz) * inv;
+    vec3 t_min = min(t1, t2);
+    vec3 t_max = max(t1, t2);
+    float near = max(max(t_min.x, t_min.y), max(t_min.z, 0.));
+    float far = min(min(t_max.x, t_max.y), t_max.z);
+    if (far < near) {
+        return -1.;
+    }
+    return near;
+}
+
+// Can object with bounding volume at `distance` along transformed ray with length `len` be nearer than current intersection.
+bool bounds_nearer(SceneIntersection i, float distance, float len) {
+    return distance >= 0. && (!i.hit.hit || distance / len < i.hit.t);
+}
+
+// Get capsule normal, thanks iq: https://www.shadertoy.com/view/Xt3SzX
+vec3 cap_normal(vec3 pos, vec3 a, vec3 b, float radius) {
+    vec3  ba = b - a;
+    vec3  pa = pos - a;
+    float h = clamp(dot(pa,ba)/dot(ba,ba),0.0,1.0);
+    return (pa - h*ba)/radius;
+}
+
+// Get intersection with capsule, thanks iq: https://www.shadertoy.com/view/Xt3SzX
+SurfaceIntersection cap(Ray r, vec3 pa, vec3 pb, float radius) {
+    vec3 ro = r.o.xyz;
+    vec3 rd = r.d.xyz;
+    vec3 ba = pb - pa;
+    vec3 oa = ro - pa;
+
+    float baba = dot(ba,ba);
+    float bard = dot(ba,rd);
+    float baoa = dot(ba,oa);
+    float rdoa = dot(rd,oa);
+    float oaoa = dot(oa,oa);
+
+    float a = baba      - bard*bard;
+    float b = baba*rdoa - baoa*bard;
+    float c = baba*oaoa - baoa*baoa - radius*radius*baba;
+    float h = b*b - a*c;
+    if( h>=0.0 ) {
+        float t = (-b-sqrt(h))/a;
+        float y = baoa + t*bard;
+        // body
+        if( y>0.0 && y<baba ) {
+            vec3 pos = ro + rd * t;
+            return SurfaceIntersection(true, t, 0., 0., cap_normal(pos, pa, pb, radius));
+        }
+        // caps
+        vec3 oc = (y<=0.0) ? oa : ro - pb;
+        b = dot(rd,oc);
+        c = dot(oc,oc) - radius*radius;
+        h = b*b - c;
+        if( h>0.0 ) {
+            t = -b - sqrt(h);
+            vec3 pos = ro + rd * t;
+            return SurfaceIntersection(true, t, 0., 0., cap_normal(pos, pa, pb, radius));
+        };
+    }
+    return intersection_none;
+}
+
+// Intersect ray with debug thing
+SceneIntersection debug_intersect(Ray r) {
+    vec3 pa = vec3(0.);
+    float radius = 0.03;
+
+    SurfaceIntersection hit = intersection_none;
+    SceneIntersection i = SceneIntersection(0, hit);
+
+    hit = cap(r, pa, vec3(1., 0., 0.), radius);
+    if (nearer(i, hit)) {
+      i.material = DEBUG_RED;
+      i.hit = hit;
+    }
+
+    hit = cap(r, pa, vec3(0., 1., 0.), radius);
+    if (nearer(i, hit)) {
+      i.material = DEBUG_GREEN;
+      i.hit = hit;
+    }
+
+    hit = cap(r, pa, vec3(0., 0., 1.), radius);
+    if (nearer(i, hit)) {
+      i.material = DEBUG_BLUE;
+      i.hit = hit;
+    }
+
+    return i;
+}
+
+
+// ---------------------------------------------------------------------------
+// User library --------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+const mat4 id_mat = mat4(1e0, 0e0, 0e0, 0e0, 0e0, 1e0, 0e0, 0e0, 0e0, 0e0, 1e0, 0e0, 0e0, 0e0, 0e0, 1e0);
+const mat4 id_mat_inv = mat4(1e0, -0e0, 0e0, -0e0, -0e0, 1e0, -0e0, 0e0, 0e0, -0e0, 1e0, -0e0, -0e0, 0e0, -0e0, 1e0);
+
+
+
+
+
+
+
+
+
+
+SceneIntersection scene_intersect(Ray r) {
+    SceneIntersection i = SceneIntersection(0, intersection_none);
+    SceneIntersection ihit = SceneIntersection(0, intersection_none);
+    SurfaceIntersection hit = intersection_none;
+    vec3 normal = vec3(0.);
+    int inside = NOT_INSIDE;
+    float len = 1.;
+    Ray transformed_ray = ray_none;
+
+transformed_ray = transform(id_mat_inv, r);
+len = length(transformed_ray.d);
+transformed_ray.d = normalize(transformed_ray.d);if (bounds_nearer(i, box_bounds(transformed_ray, vec3(-3e-2, -3e-2, -3e-2), vec3(1.03e0, 1.03e0, 1.03e0)), len)) {
+ihit = debug_intersect(transformed_ray);
+ihit.hit.t /= len;
+if (nearer(i, ihit)) { i = ihit; i.hit.n = normalize((id_mat * vec4(i.hit.n, 0.)).xyz); }
+}
+
+
+
+
+    return i;
+}
+
+MaterialProcessing material_process(Ray r, SceneIntersection i) {
+    SurfaceIntersection hit = i.hit;
+    if (i.material == 0) {
+    } else if (i.material == DEBUG_RED) {
+        return material_simple(hit, r, color(0.9, 0.2, 0.2), 0.5, false, 1., 0.);
+    } else if (i.material == DEBUG_GREEN) {
+        return material_simple(hit, r, color(0.2, 0.9, 0.2), 0.5, false, 1., 0.);
+    } else if (i.material == DEBUG_BLUE) {
+        return material_simple(hit, r, color(0.2, 0.2, 0.9), 0.5, false, 1., 0.);
+
+
+
+    }
+
+    // If there is no material with this number.
+    return material_final(vec3(0.));
+}
+
+// ---------------------------------------------------------------------------
+// Ray tracing ---------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform int _ray_tracing_depth;
+
+vec3 ray_tracing(Ray r) {
+    vec3 current_color = vec3(1.);
+
+    // GLSL ES 1.00 allows only constant loop bounds.
+    for (int j = 0; j < 10000; j++) {
+        if (j > _ray_tracing_depth) {
+            return current_color;
+        }
+
+        SceneIntersection i = scene_intersect(r);
+
+        // Offset ray
+        r.o += r.d * i.hit.t;
+        if (i.hit.hit) {
+            MaterialProcessing m = material_process(r, i);
+            current_color *= m.mul_to_color;
+            if (m.is_final) {
+                return current_color;
+            } else {
+                r = m.new_ray;
+            }
+        } else {
+            return current_color * color(0.6, 0.6, 0.6);
+        }
+    }
+    return current_color;
+}
+
+// ---------------------------------------------------------------------------
+// Draw image ----------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform mat4 _camera;
+uniform float _view_angle;
+
+varying vec2 uv;
+varying vec2 uv_screen;
+
+
+
+
+void main() {
+    vec4 o = _camera * vec4(0., 0., 0., 1.);
+
+    float h = tan(_view_angle / 2.);
+    vec4 d = normalize(_camera * vec4(uv_screen.x * h, uv_screen.y * h, 1.0, 0.));
+
+     
+    Ray r = Ray(o, d);
+
+    gl_FragColor = vec4(sqrt(ray_tracing(r)), 1.);
+
+}
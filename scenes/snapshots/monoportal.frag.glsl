@@ -0,0 +1,557 @@
+#version 100
+// Version can't be changed to upper versions because of WebGL.
+
+precision highp float;
+
+
+    
+// Checks if `x` is in range [a, b].
+bool between(float a, float x, float b) {
+    return a <= x && x <= b;
+}
+
+// Returns square of input.
+float sqr(float a) {
+    return a*a;
+}
+
+// ---------------------------------------------------------------------------
+// Vector and ray math -------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+struct Ray
+{
+    vec4 o; // Origin.
+    vec4 d; // Direction.
+};
+
+const Ray ray_none = Ray(vec4(0.), vec4(0.));
+
+// Returns normal that anti-directed to dir ray, and has length 1.
+vec3 normalize_normal(vec3 normal, vec3 dir) {
+    normal = normalize(normal);
+    if (dot(normal, dir) > 0.) {
+        normal *= -1.;
+    }
+    return normal;
+}
+
+// Is two vectors has same direction.
+bool is_collinear(vec3 a, vec3 b) {
+    return abs(dot(a, b) / (length(a) * length(b)) - 1.) < 0.01;
+}
+
+// Return reflected dir vector, based on normal and current dir.
+vec3 my_reflect(vec3 dir, vec3 normal) {
+     return dir - normal * dot(dir, normal) / dot(normal, normal) * 2.;
+}
+
+// Return ray, trat is transformed used matrix. NOTE: Do not forget to normalize new r.d!!! If your `t` depends on it, memorize it somewhere.
+Ray transform(mat4 matrix, Ray r) {
+    return Ray(
+        matrix * r.o,
+        matrix * r.d
+    );
+}
+
+vec3 get_normal(mat4 matrix) {
+    return matrix[2].xyz;
+}
+
+// ---------------------------------------------------------------------------
+// Surface intersection ------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Intersection with some surface.
+struct SurfaceIntersection {
+    bool hit; // Is intersect.
+    float t; // Distance to surface.
+    float u; // X position on surface.
+    float v; // Y position on surface.
+    vec3 n; // Normal at intersection point.
+};
+
+// No intersection.
+const SurfaceIntersection intersection_none = SurfaceIntersection(false, 1e10, 0., 0., vec3(0.));
+
+// Intersect ray with plane with matrix `inverse(plane)`, and `normal`.
+SurfaceIntersection plane_intersect(Ray r, mat4 plane_inv, vec3 normal) {
+    normal = normalize_normal(normal, r.d.xyz);
+    r = transform(plane_inv, r);
+    float len = length(r.d);
+    r.d = normalize(r.d);
+
+    float t = -r.o.z/r.d.z;
+    if (t < 0.) {
+        return intersection_none;
+    } else {
+        vec4 pos = r.o + r.d * t; 
+        return SurfaceIntersection(true, t / len, pos.x, pos.y, normal);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Color utils ---------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Forms color that next can be alpha-corrected. You should use this function instead of vec3(r, g, b), because of alpha-correction.
+vec3 color(float r, float g, float b) {
+    return vec3(r*r, g*g, b*b);
+}
+
+// Returns how this normal should change color.
+float color_normal(vec3 normal, vec4 direction) {
+    return abs(dot(normalize(direction.xyz), normalize(normal)));
+}
+
+// Returns grid color based on position and start color. Copy-pasted somewhere from shadertoy.
+vec3 color_grid(vec3 start, vec2 uv) {
+    uv /= 8.;
+    uv = uv - vec2(0.125, 0.125);
+    const float fr = 3.14159*8.0;
+    vec3 col = start;
+    col += 0.4*smoothstep(-0.01,0.01,cos(uv.x*fr*0.5)*cos(uv.y*fr*0.5)); 
+    float wi = smoothstep(-1.0,-0.98,cos(uv.x*fr))*smoothstep(-1.0,-0.98,cos(uv.y*fr));
+    col *= wi;
+    
+    return col;
+}
+
+// Adds color `b` to color `a` with coef, that must lie in [0..1]. If coef == 0, then result is `a`, if coef == 1.0, then result is `b`.
+vec3 color_add_weighted(vec3 a, vec3 b, float coef) {
+    return a*(1.0 - coef) + b*coef;
+}
+
+// ---------------------------------------------------------------------------
+// Materials processing ------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform float _offset_after_material; // Normally should equals to 0.0001, but for mobile can be different
+
+// Result after material processing.
+struct MaterialProcessing {
+    bool is_final; // If this flag set to false, then next ray tracing will be proceed. Useful for: portals, glass, mirrors, etc.
+    vec3 mul_to_color; // If is_final = true, then this color is multiplied to current color, otherwise this is the final color.
+    Ray new_ray; // New ray if is_final = true.
+};
+
+// Shortcut for creating material with is_final = true.
+MaterialProcessing material_final(vec3 color) {
+    return MaterialProcessing(true, color, ray_none);    
+}
+
+// Shortcut for creating material with is_final = false.
+MaterialProcessing material_next(vec3 mul_color, Ray new_ray) {
+    return MaterialProcessing(false, mul_color, new_ray);
+}
+
+// Function to easy write simple material.
+MaterialProcessing material_simple(
+    SurfaceIntersection hit, Ray r,
+    vec3 color, float normal_coef, 
+    bool grid, float grid_scale, float grid_coef
+) {
+    color = color_add_weighted(color, color * color_normal(hit.n, r.d), normal_coef);
+    if (grid) {
+        color = color_add_weighted(color, color_grid(color, vec2(hit.u, hit.v) * grid_scale), grid_coef);
+    }
+    return material_final(color);
+}
+
+// Function to easy write reflect material.
+MaterialProcessing material_reflect(
+    SurfaceIntersection hit, Ray r,
+    vec3 add_to_color
+) {
+    r.d = vec4(my_reflect(r.d.xyz, hit.n), 0.);
+    r.o += r.d * _offset_after_material;
+    return material_next(add_to_color, r);
+}
+
+// Function to easy write teleport material.
+MaterialProcessing material_teleport(
+    SurfaceIntersection hit, Ray r,
+    mat4 teleport_matrix
+) {
+    r.o += r.d * _offset_after_material;
+    // todo add add_gray_after_teleportation
+    r = transform(teleport_matrix, r);
+    r.d = normalize(r.d);
+    return material_next(vec3(1.), r);
+}
+
+// System materials
+#define NOT_INSIDE 0
+#define TELEPORT 1
+
+// Actual predefined materials
+#define DEBUG_RED 2
+#define DEBUG_GREEN 3
+#define DEBUG_BLUE 4
+
+// User must use this offset for his materials
+#define USER_MATERIAL_OFFSET 10
+
+// ---------------------------------------------------------------------------
+// Scene intersection --------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Intersection with material.
+struct SceneIntersection {
+    int material;
+    SurfaceIntersection hit;
+};
+
+bool nearer(SurfaceIntersection result, SurfaceIntersection current) {
+    return current.hit && (current.t > 0.) && (!result.hit || (result.hit && current.t < result.t));
+}
+
+bool nearer(SceneIntersection result, SurfaceIntersection current) {
+    return nearer(result.hit, current);
+}
+
+bool nearer(SceneIntersection result, SceneIntersection current) {
+    return nearer(result, current.hit);
+}
+
+// ---------------------------------------------------------------------------
+// Code for current scene ----------------------------------------------------
+// ---------------------------------------------------------------------------
+
+SceneIntersection process_plane_intersection(SceneIntersection i, SurfaceIntersection hit, int inside) {
+    if (inside == NOT_INSIDE) {
+        // Not inside, do nothing
+    } else if (inside == TELEPORT) {
+        // This is wrong code, do nothing
+    } else {
+        i.hit = hit;
+        i.material = inside;
+    }
+    return i;
+}
+
+SceneIntersection process_portal_intersection(SceneIntersection i, SurfaceIntersection hit, int inside, int teleport_material) {
+    if (inside == NOT_INSIDE) {
+        // Not inside, do nothing
+    } else if (inside == TELEPORT) {
+        i.hit = hit;
+        i.material = teleport_material;
+    } else {
+        i.hit = hit;
+        i.material = inside;
+    }
+    return i;
+}
+
+
+// ---------------------------------------------------------------------------
+// User library --------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform mat4 monoportal_a_mat;
+uniform mat4 monoportal_a_mat_inv;
+uniform mat4 monoportal_a_to_monoportal_b_mat_teleport;
+uniform mat4 monoportal_b_mat;
+uniform mat4 monoportal_b_mat_inv;
+uniform mat4 monoportal_b_to_monoportal_a_mat_teleport;
+uniform mat4 rx1_mat;
+uniform mat4 rx1_mat_inv;
+uniform mat4 rx2_mat;
+uniform mat4 rx2_mat_inv;
+uniform mat4 ry1_mat;
+uniform mat4 ry1_mat_inv;
+uniform mat4 ry2_mat;
+uniform mat4 ry2_mat_inv;
+uniform mat4 rz1_mat;
+uniform mat4 rz1_mat_inv;
+uniform mat4 rz2_mat;
+uniform mat4 rz2_mat_inv;
+uniform mat4 tri_mat;
+uniform mat4 tri_mat_inv;
+uniform mat4 tri_teleported_mat;
+uniform mat4 tri_teleported_mat_inv;
+uniform float room_size_u;
+uniform float room_size_minus_u;
+uniform float portal_ellipse_a_u;
+uniform float portal_ellipse_b_u;
+uniform float portal_side_border_size_u;
+uniform float portal_border_size_u;
+uniform float portal_offset_u;
+uniform float portal_rotate_progress_u;
+uniform float portal_rotate_angle_u;
+uniform float portal_black_color_progress_u;
+uniform float triangle_x_u;
+uniform float triangle_y_u;
+uniform float triangle_z_u;
+uniform float triangle_size_u;
+uniform int stage1_u;
+uniform float progress_u;
+uniform int stage2_u;
+uniform int stage3_u;
+uniform int stage4_u;
+uniform int stage5_u;
+uniform int stage6_u;
+uniform int mirror_u;
+uniform int portal_teleport_light_u;
+
+
+uniform sampler2D monoportal_tex;
+
+
+#define room_green_M (USER_MATERIAL_OFFSET + 0)
+#define room_red_M (USER_MATERIAL_OFFSET + 1)
+#define room_gray_M (USER_MATERIAL_OFFSET + 2)
+#define room_black_M (USER_MATERIAL_OFFSET + 3)
+#define portal_orange_M (USER_MATERIAL_OFFSET + 4)
+#define portal_blue_M (USER_MATERIAL_OFFSET + 5)
+#define room_blue_M (USER_MATERIAL_OFFSET + 6)
+#define triangle_black_M (USER_MATERIAL_OFFSET + 7)
+#define triangle_white_M (USER_MATERIAL_OFFSET + 8)
+#define room_green_texture_M (USER_MATERIAL_OFFSET + 9)
+#define mirror_M (USER_MATERIAL_OFFSET + 10)
+#define room_yellow_M (USER_MATERIAL_OFFSET + 11)
+#define gray_grid_M (USER_MATERIAL_OFFSET + 12)
+#define teleport_6_1_M (USER_MATERIAL_OFFSET + 13)
+#define teleport_6_2_M (USER_MATERIAL_OFFSET + 14)
+
+
+int is_inside_square(float x, float y, int material) {
+  if (abs(x) < room_size_u && abs(y) < room_size_u) {
+    return material;
+  } else {
+    return NOT_INSIDE;
+  }
+}
+int is_inside_triangle(float x, float y, float angle, float width, float border, int inner_m, int border_m) {
+  float value = width - abs(x)*angle;
+  if (between(border, y, value - border * angle)) {
+    return inner_m;
+  } else if (between(0., y, value)) {
+    return border_m;
+  } else {
+    return NOT_INSIDE;
+  }
+}
+
+int is_inside_0(vec4 pos, float x, float y) {
+return is_inside_square(x, y, room_blue_M);
+}
+int is_inside_1(vec4 pos, float x, float y) {
+return is_inside_square(x, y, room_green_texture_M);
+}
+int is_inside_2(vec4 pos, float x, float y) {
+return is_inside_square(x, y, room_black_M);
+}
+int is_inside_3(vec4 pos, float x, float y) {
+return is_inside_square(x, y, room_gray_M);
+}
+int is_inside_4(vec4 pos, float x, float y) {
+return is_inside_square(x, y, room_yellow_M);
+}
+int is_inside_5(vec4 pos, float x, float y) {
+return is_inside_square(x, y, room_red_M);
+}
+int is_inside_6(vec4 pos, float x, float y, bool back, bool first) {
+int back_material = portal_orange_M;
+if (first) { back_material = portal_blue_M; }
+
+int teleport_material = TELEPORT;
+if (portal_teleport_light_u == 0) { teleport_material = gray_grid_M; }
+if (back) { teleport_material = back_material; }
+if (mirror_u == 1) { teleport_material = mirror_M; }
+
+float radius = 2.0;
+float radius_sqr = sqr(radius);
+float radius_border_sqr = sqr(radius + portal_border_size_u);
+float pos1 = sqr(x*portal_ellipse_a_u) + sqr(y*portal_ellipse_b_u);
+
+if (pos1 < radius_border_sqr && x > 0.) {
+  if (pos1 > radius_sqr || x < portal_side_border_size_u) {
+    return back_material;
+  } else {
+    return teleport_material;
+  }
+} else {
+  return NOT_INSIDE;
+}
+}
+int is_inside_7(vec4 pos, float x, float y) {
+if ((monoportal_b_mat_inv * pos).z > 0.) return NOT_INSIDE;
+
+return is_inside_triangle(x, y, 2.0, triangle_size_u, 0.02, triangle_white_M, triangle_black_M);
+}
+int is_inside_8(vec4 pos, float x, float y) {
+if (mirror_u == 1) { return NOT_INSIDE; }
+
+if ((monoportal_a_mat_inv * pos).z < 0.) return NOT_INSIDE;
+
+return is_inside_triangle(x, y, 2.0, triangle_size_u, 0.02, triangle_white_M, triangle_black_M);
+}
+
+
+SceneIntersection scene_intersect(Ray r) {
+    SceneIntersection i = SceneIntersection(0, intersection_none);
+    SceneIntersection ihit = SceneIntersection(0, intersection_none);
+    SurfaceIntersection hit = intersection_none;
+    vec3 normal = vec3(0.);
+    int inside = NOT_INSIDE;
+    float len = 1.;
+    Ray transformed_ray = ray_none;
+
+hit = plane_intersect(r, rz1_mat_inv, get_normal(rz1_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_0(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, rz2_mat_inv, get_normal(rz2_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_1(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, ry1_mat_inv, get_normal(ry1_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_2(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, ry2_mat_inv, get_normal(ry2_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_3(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, rx1_mat_inv, get_normal(rx1_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_4(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, rx2_mat_inv, get_normal(rx2_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_5(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+normal = -get_normal(monoportal_a_mat);
+hit = plane_intersect(r, monoportal_a_mat_inv, normal);
+if (nearer(i, hit)) { i = process_portal_intersection(i, hit, is_inside_6(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), true), teleport_6_1_M); }
+
+normal = get_normal(monoportal_b_mat);
+hit = plane_intersect(r, monoportal_b_mat_inv, normal);
+if (nearer(i, hit)) { i = process_portal_intersection(i, hit, is_inside_6(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), false), teleport_6_2_M); }
+
+
+hit = plane_intersect(r, tri_mat_inv, get_normal(tri_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_7(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, tri_teleported_mat_inv, get_normal(tri_teleported_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_8(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+
+
+    return i;
+}
+
+MaterialProcessing material_process(Ray r, SceneIntersection i) {
+    SurfaceIntersection hit = i.hit;
+    if (i.material == 0) {
+    } else if (i.material == DEBUG_RED) {
+        return material_simple(hit, r, color(0.9, 0.2, 0.2), 0.5, false, 1., 0.);
+    } else if (i.material == DEBUG_GREEN) {
+        return material_simple(hit, r, color(0.2, 0.9, 0.2), 0.5, false, 1., 0.);
+    } else if (i.material == DEBUG_BLUE) {
+        return material_simple(hit, r, color(0.2, 0.2, 0.9), 0.5, false, 1., 0.);
+
+} else if (i.material == room_green_M) {
+return material_simple(hit, r, vec3(1.5478948e-1, 7.3873776e-1, 2.186588e-1), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == room_red_M) {
+return material_simple(hit, r, vec3(8.458183e-1, 7.454156e-2, 7.454156e-2), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == room_gray_M) {
+return material_simple(hit, r, vec3(1.8068509e-1, 1.8068509e-1, 1.8068509e-1), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == room_black_M) {
+return material_simple(hit, r, vec3(2.9196177e-2, 2.9196177e-2, 2.9196177e-2), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == portal_orange_M) {
+MaterialProcessing result = material_simple(hit, r, vec3(0.6495146,0.2954198,0.03270938), 5e-1, false, 4e0, 3e-1);
+result.mul_to_color *= (1.0 - portal_black_color_progress_u);
+return result;
+} else if (i.material == portal_blue_M) {
+MaterialProcessing result = material_simple(hit, r, vec3(0.04732297,0.560074,0.68341726), 5e-1, false, 4e0, 3e-1);
+result.mul_to_color *= (1.0 - portal_black_color_progress_u);
+return result;
+} else if (i.material == room_blue_M) {
+return material_simple(hit, r, vec3(1.16810285e-1, 2.6798066e-1, 9.083436e-1), 5e-1, true, 1e0, 2e-1);
+} else if (i.material == triangle_black_M) {
+return material_simple(hit, r, vec3(1.5936032e-2, 1.5936032e-2, 1.5936032e-2), 5e-1, false, 4e0, 3e-1);
+} else if (i.material == triangle_white_M) {
+return material_simple(hit, r, vec3(6.259125e-1, 6.259125e-1, 6.259125e-1), 5e-1, false, 4e0, 3e-1);
+} else if (i.material == room_green_texture_M) {
+MaterialProcessing result = material_simple(hit, r, vec3(0.15478948,0.73873776,0.2186588), 5e-1, true, 1.0, 3e-1);
+result.mul_to_color *= texture2D(monoportal_tex, vec2(room_size_u + hit.u, room_size_u-hit.v) / (room_size_u * 2.0)).rgb;
+return result;
+} else if (i.material == mirror_M) {
+return material_reflect(hit, r, vec3(1e0, 1e0, 1e0));
+} else if (i.material == room_yellow_M) {
+return material_simple(hit, r, vec3(7.647179e-1, 7.024815e-1, 6.1205085e-2), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == gray_grid_M) {
+return material_simple(hit, r, vec3(2.9971308e-1, 2.9971308e-1, 2.9971308e-1), 5e-1, true, 5e0, 3e-1);
+} else if (i.material == teleport_6_1_M) {
+return material_teleport(hit, r, monoportal_a_to_monoportal_b_mat_teleport);} else if (i.material == teleport_6_2_M) {
+return material_teleport(hit, r, monoportal_b_to_monoportal_a_mat_teleport);
+
+    }
+
+    // If there is no material with this number.
+    return material_final(vec3(0.));
+}
+
+// ---------------------------------------------------------------------------
+// Ray tracing ---------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform int _ray_tracing_depth;
+
+vec3 ray_tracing(Ray r) {
+    vec3 current_color = vec3(1.);
+
+    // GLSL ES 1.00 allows only constant loop bounds.
+    for (int j = 0; j < 10000; j++) {
+        if (j > _ray_tracing_depth) {
+            return current_color;
+        }
+
+        SceneIntersection i = scene_intersect(r);
+
+        // Offset ray
+        r.o += r.d * i.hit.t;
+        if (i.hit.hit) {
+            MaterialProcessing m = material_process(r, i);
+            current_color *= m.mul_to_color;
+            if (m.is_final) {
+                return current_color;
+            } else {
+                r = m.new_ray;
+            }
+        } else {
+            return current_color * color(0.6, 0.6, 0.6);
+        }
+    }
+    return current_color;
+}
+
+// ---------------------------------------------------------------------------
+// Draw image ----------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform mat4 _camera;
+uniform float _view_angle;
+
+varying vec2 uv;
+varying vec2 uv_screen;
+
+
+
+
+void main() {
+    vec4 o = _camera * vec4(0., 0., 0., 1.);
+
+    float h = tan(_view_angle / 2.);
+    vec4 d = normalize(_camera * vec4(uv_screen.x * h, uv_screen.y * h, 1.0, 0.));
+
+     
+    Ray r = Ray(o, d);
+
+    gl_FragColor = vec4(sqrt(ray_tracing(r)), 1.);
+
+}
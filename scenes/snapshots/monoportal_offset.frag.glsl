@@ -0,0 +1,606 @@
+#version 100
+// Version can't be changed to upper versions because of WebGL.
+
+precision highp float;
+
+
+    
+// Checks if `x` is in range [a, b].
+bool between(float a, float x, float b) {
+    return a <= x && x <= b;
+}
+
+// ---------------------------------------------------------------------------
+// Vector and ray math -------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+struct Ray
+{
+    vec4 o; // Origin.
+    vec4 d; // Direction.
+};
+
+const Ray ray_none = Ray(vec4(0.), vec4(0.));
+
+// Returns normal that anti-directed to dir ray, and has length 1.
+vec3 normalize_normal(vec3 normal, vec3 dir) {
+    normal = normalize(normal);
+    if (dot(normal, dir) > 0.) {
+        normal *= -1.;
+    }
+    return normal;
+}
+
+// Is two vectors has same direction.
+bool is_collinear(vec3 a, vec3 b) {
+    return abs(dot(a, b) / (length(a) * length(b)) - 1.) < 0.01;
+}
+
+// Return reflected dir vector, based on normal and current dir.
+vec3 my_reflect(vec3 dir, vec3 normal) {
+     return dir - normal * dot(dir, normal) / dot(normal, normal) * 2.;
+}
+
+// Return refracted dir vector, based on normal and current dir.
+vec3 my_refract(vec3 dir, vec3 normal, float refractive_index) {
+    float ri = refractive_index;
+    bool from_outside = dot(normal, dir) > 0.;
+    if (!from_outside) {
+        ri = 1. / ri;
+    } else {
+        normal = -normal;
+    }
+
+    dir = normalize(dir);
+    float c = -dot(normal, dir);
+    float d = 1.0 - ri * ri * (1.0 - c*c);
+    if (d > 0.) {
+        return dir * ri + normal * (ri * c - sqrt(d));
+    } else {
+        return my_reflect(dir, normal);
+    }
+}
+
+// Return ray, trat is transformed used matrix. NOTE: Do not forget to normalize new r.d!!! If your `t` depends on it, memorize it somewhere.
+Ray transform(mat4 matrix, Ray r) {
+    return Ray(
+        matrix * r.o,
+        matrix * r.d
+    );
+}
+
+vec3 get_normal(mat4 matrix) {
+    return matrix[2].xyz;
+}
+
+// ---------------------------------------------------------------------------
+// Surface intersection ------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Intersection with some surface.
+struct SurfaceIntersection {
+    bool hit; // Is intersect.
+    float t; // Distance to surface.
+    float u; // X position on surface.
+    float v; // Y position on surface.
+    vec3 n; // Normal at intersection point.
+};
+
+// No intersection.
+const SurfaceIntersection intersection_none = SurfaceIntersection(false, 1e10, 0., 0., vec3(0.));
+
+// Intersect ray with plane with matrix `inverse(plane)`, and `normal`.
+SurfaceIntersection plane_intersect(Ray r, mat4 plane_inv, vec3 normal) {
+    normal = normalize_normal(normal, r.d.xyz);
+    r = transform(plane_inv, r);
+    float len = length(r.d);
+    r.d = normalize(r.d);
+
+    float t = -r.o.z/r.d.z;
+    if (t < 0.) {
+        return intersection_none;
+    } else {
+        vec4 pos = r.o + r.d * t; 
+        return SurfaceIntersection(true, t / len, pos.x, pos.y, normal);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Color utils ---------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Forms color that next can be alpha-corrected. You should use this function instead of vec3(r, g, b), because of alpha-correction.
+vec3 color(float r, float g, float b) {
+    return vec3(r*r, g*g, b*b);
+}
+
+// Returns how this normal should change color.
+float color_normal(vec3 normal, vec4 direction) {
+    return abs(dot(normalize(direction.xyz), normalize(normal)));
+}
+
+// Returns grid color based on position and start color. Copy-pasted somewhere from shadertoy.
+vec3 color_grid(vec3 start, vec2 uv) {
+    uv /= 8.;
+    uv = uv - vec2(0.125, 0.125);
+    const float fr = 3.14159*8.0;
+    vec3 col = start;
+    col += 0.4*smoothstep(-0.01,0.01,cos(uv.x*fr*0.5)*cos(uv.y*fr*0.5)); 
+    float wi = smoothstep(-1.0,-0.98,cos(uv.x*fr))*smoothstep(-1.0,-0.98,cos(uv.y*fr));
+    col *= wi;
+    
+    return col;
+}
+
+// Adds color `b` to color `a` with coef, that must lie in [0..1]. If coef == 0, then result is `a`, if coef == 1.0, then result is `b`.
+vec3 color_add_weighted(vec3 a, vec3 b, float coef) {
+    return a*(1.0 - coef) + b*coef;
+}
+
+// ---------------------------------------------------------------------------
+// Materials processing ------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform float _offset_after_material; // Normally should equals to 0.0001, but for mobile can be different
+
+// Result after material processing.
+struct MaterialProcessing {
+    bool is_final; // If this flag set to false, then next ray tracing will be proceed. Useful for: portals, glass, mirrors, etc.
+    vec3 mul_to_color; // If is_final = true, then this color is multiplied to current color, otherwise this is the final color.
+    Ray new_ray; // New ray if is_final = true.
+};
+
+// Shortcut for creating material with is_final = true.
+MaterialProcessing material_final(vec3 color) {
+    return MaterialProcessing(true, color, ray_none);    
+}
+
+// Shortcut for creating material with is_final = false.
+MaterialProcessing material_next(vec3 mul_color, Ray new_ray) {
+    return MaterialProcessing(false, mul_color, new_ray);
+}
+
+// Function to easy write simple material.
+MaterialProcessing material_simple(
+    SurfaceIntersection hit, Ray r,
+    vec3 color, float normal_coef, 
+    bool grid, float grid_scale, float grid_coef
+) {
+    color = color_add_weighted(color, color * color_normal(hit.n, r.d), normal_coef);
+    if (grid) {
+        color = color_add_weighted(color, color_grid(color, vec2(hit.u, hit.v) * grid_scale), grid_coef);
+    }
+    return material_final(color);
+}
+
+// Function to easy write refract material.
+MaterialProcessing material_refract(
+    SurfaceIntersection hit, Ray r,
+    vec3 add_to_color, float refractive_index
+) {
+    r.d = vec4(my_refract(r.d.xyz, hit.n, refractive_index), 0.);
+    r.o += r.d * _offset_after_material;
+    return material_next(add_to_color, r);
+}
+
+// Function to easy write teleport material.
+MaterialProcessing material_teleport(
+    SurfaceIntersection hit, Ray r,
+    mat4 teleport_matrix
+) {
+    r.o += r.d * _offset_after_material;
+    // todo add add_gray_after_teleportation
+    r = transform(teleport_matrix, r);
+    r.d = normalize(r.d);
+    return material_next(vec3(1.), r);
+}
+
+// System materials
+#define NOT_INSIDE 0
+#define TELEPORT 1
+
+// Actual predefined materials
+#define DEBUG_RED 2
+#define DEBUG_GREEN 3
+#define DEBUG_BLUE 4
+
+// User must use this offset for his materials
+#define USER_MATERIAL_OFFSET 10
+
+// ---------------------------------------------------------------------------
+// Scene intersection --------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Intersection with material.
+struct SceneIntersection {
+    int material;
+    SurfaceIntersection hit;
+};
+
+bool nearer(SurfaceIntersection result, SurfaceIntersection current) {
+    return current.hit && (current.t > 0.) && (!result.hit || (result.hit && current.t < result.t));
+}
+
+bool nearer(SceneIntersection result, SurfaceIntersection current) {
+    return nearer(result.hit, current);
+}
+
+bool nearer(SceneIntersection result, SceneIntersection current) {
+    return nearer(result, current.hit);
+}
+
+// ---------------------------------------------------------------------------
+// Code for current scene ----------------------------------------------------
+// ---------------------------------------------------------------------------
+
+SceneIntersection process_plane_intersection(SceneIntersection i, SurfaceIntersection hit, int inside) {
+    if (inside == NOT_INSIDE) {
+        // Not inside, do nothing
+    } else if (inside == TELEPORT) {
+        // This is wrong code, do nothing
+    } else {
+        i.hit = hit;
+        i.material = inside;
+    }
+    return i;
+}
+
+SceneIntersection process_portal_intersection(SceneIntersection i, SurfaceIntersection hit, int inside, int teleport_material) {
+    if (inside == NOT_INSIDE) {
+        // Not inside, do nothing
+    } else if (inside == TELEPORT) {
+        i.hit = hit;
+        i.material = teleport_material;
+    } else {
+        i.hit = hit;
+        i.material = inside;
+    }
+    return i;
+}
+
+
+// ---------------------------------------------------------------------------
+// User library --------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+const mat4 p1_mat = mat4(1e0, 0e0, 0e0, 0e0, 0e0, 1e0, 0e0, 0e0, 0e0, 0e0, 1e0, 0e0, 0e0, 0e0, 4e0, 1e0);
+const mat4 p1_mat_inv = mat4(1e0, -0e0, 0e0, -0e0, -0e0, 1e0, -0e0, 0e0, 0e0, -0e0, 1e0, -0e0, -0e0, 0e0, -4e0, 1e0);
+const mat4 p2_mat = mat4(1e0, 0e0, 0e0, 0e0, 0e0, 1e0, 0e0, 0e0, 0e0, 0e0, 1e0, 0e0, 0e0, 0e0, -4e0, 1e0);
+const mat4 p2_mat_inv = mat4(1e0, -0e0, 0e0, -0e0, -0e0, 1e0, -0e0, 0e0, 0e0, -0e0, 1e0, -0e0, -0e0, 0e0, 4e0, 1e0);
+const mat4 p3_mat = mat4(1e0, 0e0, 0e0, 0e0, 0e0, 5.9604645e-8, 9.9999994e-1, 0e0, 0e0, -9.9999994e-1, 5.9604645e-8, 0e0, 0e0, 4e0, 0e0, 1e0);
+const mat4 p3_mat_inv = mat4(1e0, -0e0, 0e0, -0e0, -0e0, 5.960465e-8, -1e0, 0e0, 0e0, 1e0, 5.960465e-8, -0e0, -0e0, -2.384186e-7, 4e0, 1e0);
+const mat4 p4_mat = mat4(1e0, 0e0, 0e0, 0e0, 0e0, 5.9604645e-8, 9.9999994e-1, 0e0, 0e0, -9.9999994e-1, 5.9604645e-8, 0e0, 0e0, -4e0, 0e0, 1e0);
+const mat4 p4_mat_inv = mat4(1e0, -0e0, 0e0, -0e0, -0e0, 5.960465e-8, -1e0, 0e0, 0e0, 1e0, 5.960465e-8, -0e0, -0e0, 2.384186e-7, -4e0, 1e0);
+const mat4 p5_mat = mat4(5.9604645e-8, 0e0, -9.9999994e-1, 0e0, 0e0, 1e0, 0e0, 0e0, 9.9999994e-1, 0e0, 5.9604645e-8, 0e0, 4e0, 0e0, 0e0, 1e0);
+const mat4 p5_mat_inv = mat4(5.960465e-8, -0e0, 1e0, -0e0, -0e0, 1e0, -0e0, 0e0, -1e0, -0e0, 5.960465e-8, -0e0, -2.384186e-7, 0e0, -4e0, 1e0);
+const mat4 p6_mat = mat4(5.9604645e-8, 0e0, -9.9999994e-1, 0e0, 0e0, 1e0, 0e0, 0e0, 9.9999994e-1, 0e0, 5.9604645e-8, 0e0, -4e0, 0e0, 0e0, 1e0);
+const mat4 p6_mat_inv = mat4(5.960465e-8, -0e0, 1e0, -0e0, -0e0, 1e0, -0e0, 0e0, -1e0, -0e0, 5.960465e-8, -0e0, 2.384186e-7, 0e0, 4e0, 1e0);
+const mat4 tri_mat = mat4(-9.999999e-1, 0e0, -8.742278e-8, 0e0, 8.742278e-8, 5.9604645e-8, -9.9999994e-1, 0e0, 0e0, -9.9999994e-1, 5.9604645e-8, 0e0, 1.3e-1, 7.8e-1, 8.5e-1, 1e0);
+const mat4 tri_mat_inv = mat4(-1.0000001e0, 8.742279e-8, 5.210805e-15, -0e0, -5.210805e-15, -5.960465e-8, -1e0, 0e0, -8.742279e-8, -1e0, -5.960465e-8, 0e0, 1.3000007e-1, 8.500001e-1, 7.8000003e-1, 1e0);
+uniform mat4 a2_mat;
+uniform mat4 a2_mat_inv;
+uniform mat4 a2_to_b2_mat_teleport;
+uniform mat4 a_mat;
+uniform mat4 a_mat_inv;
+uniform mat4 a_to_b1_mat_teleport;
+uniform mat4 a_to_b_mat_teleport;
+uniform mat4 b1_mat;
+uniform mat4 b1_mat_inv;
+uniform mat4 b1_to_a_mat_teleport;
+uniform mat4 b2_mat;
+uniform mat4 b2_mat_inv;
+uniform mat4 b2_to_a2_mat_teleport;
+uniform mat4 b_mat;
+uniform mat4 b_mat_inv;
+uniform mat4 b_to_a_mat_teleport;
+uniform float offset_u;
+uniform float height_u;
+uniform float width_u;
+uniform float off_hei_u;
+uniform float h2p_u;
+uniform float h2m_u;
+uniform float off_h2p_u;
+
+
+uniform sampler2D texture_tex;
+
+
+#define black_M (USER_MATERIAL_OFFSET + 0)
+#define green_M (USER_MATERIAL_OFFSET + 1)
+#define red_M (USER_MATERIAL_OFFSET + 2)
+#define gray_M (USER_MATERIAL_OFFSET + 3)
+#define black_solid_M (USER_MATERIAL_OFFSET + 4)
+#define white_solid_M (USER_MATERIAL_OFFSET + 5)
+#define blue_M (USER_MATERIAL_OFFSET + 6)
+#define orange_M (USER_MATERIAL_OFFSET + 7)
+#define sphere_M (USER_MATERIAL_OFFSET + 8)
+#define green2_M (USER_MATERIAL_OFFSET + 9)
+#define teleport_7_1_M (USER_MATERIAL_OFFSET + 10)
+#define teleport_7_2_M (USER_MATERIAL_OFFSET + 11)
+#define teleport_8_1_M (USER_MATERIAL_OFFSET + 12)
+#define teleport_8_2_M (USER_MATERIAL_OFFSET + 13)
+#define teleport_9_1_M (USER_MATERIAL_OFFSET + 14)
+#define teleport_9_2_M (USER_MATERIAL_OFFSET + 15)
+
+
+int is_inside_square(float x, float y, int material) {
+  if (abs(x) < 4. && abs(y) < 4.) {
+    return material;
+  } else {
+    return NOT_INSIDE;
+  }
+}
+
+int is_inside_triangle(float x, float y, float angle, float width, float border, int inner_m, int border_m) {
+  float value = width - abs(x)*angle;
+  if (y > border && abs(y) < value) {
+    return inner_m;
+  } else if (y > 0. && abs(y) < value + border*angle) {
+    return border_m;
+  } else {
+    return NOT_INSIDE;
+  }
+}
+
+int is_inside_0(vec4 pos, float x, float y) {
+return is_inside_square(x, y, green2_M);
+}
+int is_inside_1(vec4 pos, float x, float y) {
+return is_inside_square(x, y, red_M);
+}
+int is_inside_2(vec4 pos, float x, float y) {
+return is_inside_square(x, y, black_M);
+}
+int is_inside_3(vec4 pos, float x, float y) {
+return is_inside_square(x, y, gray_M);
+}
+int is_inside_4(vec4 pos, float x, float y) {
+return is_inside_square(x, y, gray_M);
+}
+int is_inside_5(vec4 pos, float x, float y) {
+return is_inside_square(x, y, black_M);
+}
+int is_inside_6(vec4 pos, float x, float y) {
+if ((b_mat_inv * pos).z > 0.) return NOT_INSIDE;
+
+return is_inside_triangle(x, y, 2.0, 0.6, 0.05, black_solid_M, white_solid_M);
+}
+int is_inside_7(vec4 pos, float x, float y, bool back, bool first) {
+int material = blue_M;
+if (!first) {
+  material = orange_M;
+}
+float h = off_hei_u;
+float border = 0.05;
+
+if (between(0., x, width_u/2.) && between(0., y, height_u-h)) {
+  if (back) {
+    return material;
+  } else {
+    return TELEPORT;
+  }
+} else if (between(width_u/2., x, width_u/2. + border) && between(0., y, height_u-h)) {
+  return material;
+} else {
+  return NOT_INSIDE;
+}
+}
+int is_inside_8(vec4 pos, float x, float y, bool back, bool first) {
+int material = blue_M;
+if (!first) {
+  material = orange_M;
+}
+float h = off_hei_u;
+float border = 0.05;
+
+if (between(0., x, width_u/2.) && between(height_u-h, y, height_u)) {
+  if (back) {
+    return material;
+  } else {
+    return TELEPORT;
+  }
+} else if (between(width_u/2., x, width_u/2. + border) && between(height_u-h, y, height_u)) {
+  return material;
+} else {
+  return NOT_INSIDE;
+}
+}
+int is_inside_9(vec4 pos, float x, float y, bool back, bool first) {
+int material = blue_M;
+if (!first) {
+  material = orange_M;
+}
+float border = 0.05;
+float hh = 1.0;
+float ww = width_u/2. + 1.;
+
+if (between(-ww, x, ww) && between(-hh, y, hh)) {
+  if (back) {
+    return material;
+  } else {
+    return TELEPORT;
+  }
+} else if (between(-ww-border, x, ww+border) && between(-hh-border, y, hh+border)) {
+  return material;
+} else {
+  return NOT_INSIDE;
+}
+}
+
+
+SceneIntersection scene_intersect(Ray r) {
+    SceneIntersection i = SceneIntersection(0, intersection_none);
+    SceneIntersection ihit = SceneIntersection(0, intersection_none);
+    SurfaceIntersection hit = intersection_none;
+    vec3 normal = vec3(0.);
+    int inside = NOT_INSIDE;
+    float len = 1.;
+    Ray transformed_ray = ray_none;
+
+hit = plane_intersect(r, p1_mat_inv, get_normal(p1_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_0(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, p2_mat_inv, get_normal(p2_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_1(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, p3_mat_inv, get_normal(p3_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_2(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, p4_mat_inv, get_normal(p4_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_3(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, p5_mat_inv, get_normal(p5_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_4(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, p6_mat_inv, get_normal(p6_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_5(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+hit = plane_intersect(r, tri_mat_inv, get_normal(tri_mat));
+if (nearer(i, hit)) { i = process_plane_intersection(i, hit, is_inside_6(r.o + r.d * hit.t, hit.u, hit.v)); }
+
+
+normal = -get_normal(a_mat);
+hit = plane_intersect(r, a_mat_inv, normal);
+if (nearer(i, hit)) { i = process_portal_intersection(i, hit, is_inside_7(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), true), teleport_7_1_M); }
+
+normal = get_normal(b_mat);
+hit = plane_intersect(r, b_mat_inv, normal);
+if (nearer(i, hit)) { i = process_portal_intersection(i, hit, is_inside_7(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), false), teleport_7_2_M); }
+
+
+normal = -get_normal(a_mat);
+hit = plane_intersect(r, a_mat_inv, normal);
+if (nearer(i, hit)) { i = process_portal_intersection(i, hit, is_inside_8(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), true), teleport_8_1_M); }
+
+normal = get_normal(b1_mat);
+hit = plane_intersect(r, b1_mat_inv, normal);
+if (nearer(i, hit)) { i = process_portal_intersection(i, hit, is_inside_8(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), false), teleport_8_2_M); }
+
+
+normal = -get_normal(a2_mat);
+hit = plane_intersect(r, a2_mat_inv, normal);
+if (nearer(i, hit)) { i = process_portal_intersection(i, hit, is_inside_9(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), true), teleport_9_1_M); }
+
+normal = get_normal(b2_mat);
+hit = plane_intersect(r, b2_mat_inv, normal);
+if (nearer(i, hit)) { i = process_portal_intersection(i, hit, is_inside_9(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), false), teleport_9_2_M); }
+
+
+
+
+    return i;
+}
+
+MaterialProcessing material_process(Ray r, SceneIntersection i) {
+    SurfaceIntersection hit = i.hit;
+    if (i.material == 0) {
+    } else if (i.material == DEBUG_RED) {
+        return material_simple(hit, r, color(0.9, 0.2, 0.2), 0.5, false, 1., 0.);
+    } else if (i.material == DEBUG_GREEN) {
+        return material_simple(hit, r, color(0.2, 0.9, 0.2), 0.5, false, 1., 0.);
+    } else if (i.material == DEBUG_BLUE) {
+        return material_simple(hit, r, color(0.2, 0.2, 0.9), 0.5, false, 1., 0.);
+
+} else if (i.material == black_M) {
+return material_simple(hit, r, vec3(5.4903064e-2, 5.4903064e-2, 5.4903064e-2), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == green_M) {
+return material_simple(hit, r, vec3(3.0508098e-1, 7.3873776e-1, 2.0860045e-1), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == red_M) {
+return material_simple(hit, r, vec3(5.669161e-1, 3.7726384e-2, 3.7726384e-2), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == gray_M) {
+return material_simple(hit, r, vec3(3.473362e-1, 3.473362e-1, 3.473362e-1), 5e-1, true, 1e0, 3e-1);
+} else if (i.material == black_solid_M) {
+return material_simple(hit, r, vec3(0e0, 0e0, 0e0), 5e-1, false, 4e0, 3e-1);
+} else if (i.material == white_solid_M) {
+return material_simple(hit, r, vec3(7.032436e-1, 7.032436e-1, 7.032436e-1), 5e-1, false, 4e0, 3e-1);
+} else if (i.material == blue_M) {
+return material_simple(hit, r, vec3(5.806269e-2, 7.189187e-1, 9.494929e-1), 5e-1, false, 4e0, 3e-1);
+} else if (i.material == orange_M) {
+return material_simple(hit, r, vec3(8.128055e-1, 1.9476937e-1, 4.093266e-2), 5e-1, false, 4e0, 3e-1);
+} else if (i.material == sphere_M) {
+return material_refract(hit, r, vec3(1e0, 1e0, 1e0), 1.5e0);
+} else if (i.material == green2_M) {
+vec3 texture_color = texture2D(texture_tex, vec2(4.0 - hit.u, 4.0-hit.v) / 8e0).rgb;
+MaterialProcessing result = material_simple(hit, r, vec3(9.21e-2, 7.28e-1, 6.81e-2), 5e-1, true, 1e0, 3e-1);
+
+result.mul_to_color *= texture_color;
+return result;
+} else if (i.material == teleport_7_1_M) {
+return material_teleport(hit, r, a_to_b_mat_teleport);} else if (i.material == teleport_7_2_M) {
+return material_teleport(hit, r, b_to_a_mat_teleport);} else if (i.material == teleport_8_1_M) {
+return material_teleport(hit, r, a_to_b1_mat_teleport);} else if (i.material == teleport_8_2_M) {
+return material_teleport(hit, r, b1_to_a_mat_teleport);} else if (i.material == teleport_9_1_M) {
+return material_teleport(hit, r, a2_to_b2_mat_teleport);} else if (i.material == teleport_9_2_M) {
+return material_teleport(hit, r, b2_to_a2_mat_teleport);
+
+    }
+
+    // If there is no material with this number.
+    return material_final(vec3(0.));
+}
+
+// ---------------------------------------------------------------------------
+// Ray tracing ---------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform int _ray_tracing_depth;
+
+vec3 ray_tracing(Ray r) {
+    vec3 current_color = vec3(1.);
+
+    // GLSL ES 1.00 allows only constant loop bounds.
+    for (int j = 0; j < 10000; j++) {
+        if (j > _ray_tracing_depth) {
+            return current_color;
+        }
+
+        SceneIntersection i = scene_intersect(r);
+
+        // Offset ray
+        r.o += r.d * i.hit.t;
+        if (i.hit.hit) {
+            MaterialProcessing m = material_process(r, i);
+            current_color *= m.mul_to_color;
+            if (m.is_final) {
+                return current_color;
+            } else {
+                r = m.new_ray;
+            }
+        } else {
+            return current_color * color(0.6, 0.6, 0.6);
+        }
+    }
+    return current_color;
+}
+
+// ---------------------------------------------------------------------------
+// Draw image ----------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+uniform mat4 _camera;
+uniform float _view_angle;
+
+varying vec2 uv;
+varying vec2 uv_screen;
+
+
+
+
+void main() {
+    vec4 o = _camera * vec4(0., 0., 0., 1.);
+
+    float h = tan(_view_angle / 2.);
+    vec4 d = normalize(_camera * vec4(uv_screen.x * h, uv_screen.y * h, 1.0, 0.));
+
+     
+    Ray r = Ray(o, d);
+
+    gl_FragColor = vec4(sqrt(ray_tracing(r)), 1.);
+
+}
@@ -0,0 +1,196 @@
+use crate::gui::format::SceneFormat;
+use crate::gui::scene::Scene;
+
+use serde_json::Value;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+/// Seconds between autosaves, this is the most work that can be lost after crash.
+pub const AUTOSAVE_INTERVAL: f64 = 5.;
+
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY: &str = "portal_autosave";
+
+/// Part of scene that is compared to find out if it's changed. Camera moves all the time, so it alone doesn't make scene unsaved.
+fn content(scene: &Scene) -> Value {
+    let mut value = serde_json::to_value(scene).unwrap();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("cam");
+    }
+    value
+}
+
+/// `~/.portal_autosave.json`, or file in temporary directory when home is unknown.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn default_autosave_path() -> PathBuf {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(".portal_autosave.json")
+}
+
+/// Keeps copy of edited scene in a local file on native and in local storage on web. Scene is stored only while it differs from the one that was loaded or saved by user, so stored scene at startup means that previous session was not saved.
+#[derive(Debug)]
+pub struct Autosave {
+    #[cfg(not(target_arch = "wasm32"))]
+    path: PathBuf,
+
+    /// Scene as it was loaded or saved by user.
+    clean: Value,
+
+    /// What is written now, `None` when nothing is written.
+    stored: Option<Value>,
+
+    last_time: f64,
+
+    /// Scene of previous session is not touched until user decides what to do with it.
+    paused: bool,
+}
+
+impl Autosave {
+    pub fn new(#[cfg(not(target_arch = "wasm32"))] path: PathBuf, scene: &Scene) -> Self {
+        let mut result = Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            path,
+            clean: content(scene),
+            stored: None,
+            last_time: 0.,
+            paused: false,
+        };
+        result.stored = result
+            .read()
+            .and_then(|text| Scene::from_text(&text).ok())
+            .map(|scene| content(&scene));
+        result
+    }
+
+    /// Scene of previous session if it's different from `scene` that is opened now. Autosave is paused until `resume` or `discard` is called.
+    pub fn recover(&mut self) -> Option<Result<Scene, String>> {
+        let text = self.read()?;
+        let result = match Scene::from_text(&text) {
+            Ok(scene) if content(&scene) == self.clean => return None,
+            Ok(scene) => Ok(scene),
+            Err(err) => Err(format!("can't read autosaved scene: {}", err)),
+        };
+        self.paused = result.is_ok();
+        Some(result)
+    }
+
+    /// Continues autosaving after recovered scene is opened.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Called when scene is loaded or saved by user, so there is nothing to lose.
+    pub fn set_clean(&mut self, scene: &Scene) {
+        self.clean = content(scene);
+        self.save(scene);
+    }
+
+    /// Saves scene if `AUTOSAVE_INTERVAL` is passed since the last check.
+    pub fn update(&mut self, scene: &Scene, time: f64) {
+        if time - self.last_time >= AUTOSAVE_INTERVAL {
+            self.last_time = time;
+            self.save(scene);
+        }
+    }
+
+    /// Saves scene right now, removes stored one if scene is not changed.
+    pub fn save(&mut self, scene: &Scene) {
+        if self.paused {
+            return;
+        }
+        let content = content(scene);
+        if content == self.clean {
+            self.discard();
+        } else if self.stored.as_ref() != Some(&content) {
+            self.write(&scene.to_text(SceneFormat::Json));
+            self.stored = Some(content);
+        }
+    }
+
+    pub fn discard(&mut self) {
+        self.paused = false;
+        if self.stored.take().is_some() {
+            self.remove();
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read(&self) -> Option<String> {
+        std::fs::read_to_string(&self.path).ok()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write(&self, text: &str) {
+        if let Err(err) = std::fs::write(&self.path, text) {
+            eprintln!("can't autosave to {}: {}", self.path.display(), err);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn remove(&self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("can't remove {}: {}", self.path.display(), err);
+            }
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn read(&self) -> Option<String> {
+        quad_storage::STORAGE.lock().unwrap().get(STORAGE_KEY)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn write(&self, text: &str) {
+        quad_storage::STORAGE.lock().unwrap().set(STORAGE_KEY, text);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn remove(&self) {
+        quad_storage::STORAGE.lock().unwrap().remove(STORAGE_KEY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> Scene {
+        Scene::from_json(include_str!("../scenes/monoportal.json")).unwrap()
+    }
+
+    #[test]
+    fn unsaved_scene_is_recovered() {
+        let path = std::env::temp_dir().join("portal_unsaved_scene_is_recovered.json");
+        let _ = std::fs::remove_file(&path);
+        let mut scene = scene();
+        let mut autosave = Autosave::new(path.clone(), &scene);
+
+        scene.cam.r += 1.;
+        autosave.update(&scene, 10.);
+        assert!(!path.exists());
+
+        scene.uniforms.storage.clear();
+        scene.uniforms.names.clear();
+        scene.uniforms.ids.clear();
+        autosave.update(&scene, 11.);
+        assert!(!path.exists(), "interval is not passed");
+        autosave.update(&scene, 20.);
+        assert!(path.exists());
+
+        let mut autosave = Autosave::new(path.clone(), &self::scene());
+        let recovered = autosave.recover().unwrap().unwrap();
+        assert_eq!(content(&recovered), content(&scene));
+        assert!(Autosave::new(path.clone(), &scene).recover().is_none());
+
+        autosave.update(&self::scene(), 30.);
+        assert!(path.exists(), "paused until user decides");
+        autosave.resume();
+        autosave.set_clean(&scene);
+        assert!(!path.exists());
+    }
+}
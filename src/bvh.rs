@@ -0,0 +1,226 @@
+//! Bounding volume hierarchy of scene objects. It is built on CPU from world-space boxes of objects, and generated shader tests boxes of nodes before testing objects inside them.
+
+use glam::*;
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.min).all() && self.max.cmpge(other.max).all()
+    }
+
+    /// Box that contains this box after transformation by `matrix`.
+    pub fn transformed(&self, matrix: &Mat4) -> Aabb {
+        let mut result: Option<Aabb> = None;
+        for corner in 0..8 {
+            let point = Vec3::new(
+                if corner & 1 == 0 {
+                    self.min.x
+                } else {
+                    self.max.x
+                },
+                if corner & 2 == 0 {
+                    self.min.y
+                } else {
+                    self.max.y
+                },
+                if corner & 4 == 0 {
+                    self.min.z
+                } else {
+                    self.max.z
+                },
+            );
+            let point = matrix.transform_point3(point);
+            let point = Aabb {
+                min: point,
+                max: point,
+            };
+            result = Some(result.map_or(point, |x| x.union(&point)));
+        }
+        result.unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// Index of item that was given to `build`.
+    Leaf(usize),
+    Branch {
+        bounds: Aabb,
+        children: Box<(Node, Node)>,
+    },
+}
+
+impl Node {
+    pub fn bounds(&self, items: &[Aabb]) -> Aabb {
+        match self {
+            Node::Leaf(pos) => items[*pos],
+            Node::Branch { bounds, .. } => *bounds,
+        }
+    }
+}
+
+/// Builds hierarchy by splitting items at median of centers along the longest axis, returns `None` for empty input.
+pub fn build(items: &[Aabb]) -> Option<Node> {
+    let mut indices = (0..items.len()).collect::<Vec<_>>();
+    build_inner(items, &mut indices)
+}
+
+fn build_inner(items: &[Aabb], indices: &mut [usize]) -> Option<Node> {
+    match indices.len() {
+        0 => return None,
+        1 => return Some(Node::Leaf(indices[0])),
+        _ => {}
+    }
+
+    let bounds = indices[1..]
+        .iter()
+        .fold(items[indices[0]], |acc, pos| acc.union(&items[*pos]));
+    let size = bounds.max - bounds.min;
+    let axis = if size.x >= size.y && size.x >= size.z {
+        0
+    } else if size.y >= size.z {
+        1
+    } else {
+        2
+    };
+    indices.sort_by(|a, b| {
+        let a = items[*a].center()[axis];
+        let b = items[*b].center()[axis];
+        a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let (left, right) = indices.split_at_mut(indices.len() / 2);
+    Some(Node::Branch {
+        bounds,
+        children: Box::new((build_inner(items, left)?, build_inner(items, right)?)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(x: f32) -> Aabb {
+        Aabb {
+            min: Vec3::new(x, 0., 0.),
+            max: Vec3::new(x + 1., 1., 1.),
+        }
+    }
+
+    fn check(node: &Node, items: &[Aabb], leafs: &mut Vec<usize>) {
+        match node {
+            Node::Leaf(pos) => leafs.push(*pos),
+            Node::Branch { bounds, children } => {
+                assert!(bounds.contains(&children.0.bounds(items)));
+                assert!(bounds.contains(&children.1.bounds(items)));
+                check(&children.0, items, leafs);
+                check(&children.1, items, leafs);
+            }
+        }
+    }
+
+    #[test]
+    fn every_item_is_in_tree() {
+        assert_eq!(build(&[]), None);
+        assert_eq!(build(&[cube(0.)]), Some(Node::Leaf(0)));
+
+        let items = [5., 1., 3., 0., 4., 2., 6.]
+            .iter()
+            .map(|x| cube(*x))
+            .collect::<Vec<_>>();
+        let tree = build(&items).unwrap();
+        let mut leafs = Vec::new();
+        check(&tree, &items, &mut leafs);
+        leafs.sort();
+        assert_eq!(leafs, (0..items.len()).collect::<Vec<_>>());
+
+        // Items are split by position, so near items are in the same subtree.
+        match &tree {
+            Node::Branch { children, .. } => {
+                let left = children.0.bounds(&items);
+                assert_eq!(left.min.x, 0.);
+                assert_eq!(left.max.x, 3.);
+            }
+            Node::Leaf(_) => panic!("tree of many items must be branch"),
+        }
+    }
+
+    #[test]
+    fn transformed_box() {
+        let matrix = Mat4::from_rotation_translation(
+            Quat::from_rotation_z(std::f32::consts::PI / 2.),
+            Vec3::new(10., 0., 0.),
+        );
+        let result = cube(0.).transformed(&matrix);
+        assert!((result.min - Vec3::new(9., 0., 0.)).abs().max_element() < 1e-5);
+        assert!((result.max - Vec3::new(10., 1., 1.)).abs().max_element() < 1e-5);
+    }
+
+    #[test]
+    fn scene_hierarchy() {
+        use crate::gui::matrix::{Matrix, MatrixComboBox};
+        use crate::gui::object::{MatrixName, Object, ObjectComboBox};
+        use crate::gui::scene::ShaderOptions;
+        use crate::scene_files::embedded_scene;
+
+        let mut scene = embedded_scene("empty").unwrap();
+        let mut origins = vec![Vec3::ZERO];
+        for x in 0..8 {
+            let name = format!("debug_{}", x);
+            let offset = Vec3::new(x as f32 * 3., 0., 0.);
+            let matrix = Matrix::Simple {
+                offset,
+                scale: 1.,
+                rotate: Vec3::default(),
+                mirror: (false, false, false),
+            };
+            scene.matrices.add(name.clone(), MatrixComboBox(matrix));
+            let object = Object::DebugMatrix(MatrixName(name.clone()));
+            scene.objects.add(name, ObjectComboBox(object));
+            origins.push(offset);
+        }
+
+        // Every object is in hierarchy, with box around axes of its matrix.
+        let options = ShaderOptions::default();
+        let items = scene.bvh_items(&options);
+        assert_eq!(items.len(), origins.len());
+        for (pos, aabb) in &items {
+            let axes = Aabb {
+                min: origins[*pos],
+                max: origins[*pos] + Vec3::splat(1.),
+            };
+            assert!(aabb.contains(&axes), "{}: {:?}", pos, aabb);
+        }
+        let boxes = items.iter().map(|(_, aabb)| *aabb).collect::<Vec<_>>();
+        let mut leafs = Vec::new();
+        check(&build(&boxes).unwrap(), &boxes, &mut leafs);
+        leafs.sort();
+        assert_eq!(leafs, (0..boxes.len()).collect::<Vec<_>>());
+        assert!(scene.validate_shader(&options).is_ok());
+
+        // Matrices that can be changed without recompilation are not in hierarchy.
+        let options = ShaderOptions {
+            fold_static_matrices: false,
+            ..Default::default()
+        };
+        assert!(scene.bvh_items(&options).is_empty());
+        assert!(scene.validate_shader(&options).is_ok());
+    }
+}
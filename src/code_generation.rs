@@ -2,40 +2,56 @@ use crate::gui::glsl::LibraryCode;
 use crate::gui::material::Material;
 use crate::gui::matrix::Matrix;
 use crate::gui::object::Object;
+use crate::gui::storage::EntityId;
+use crate::gui::texture::TextureName;
+use crate::gui::uniform::AnyUniform;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use std::ops::Range;
 
+/// Kind of entity and its identifier inside storage. Default value is used for errors that doesn't belong to any entity.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
-pub struct ErrId(pub usize);
+pub struct ErrId(pub usize, pub EntityId);
 
 // Used to find errors source
 pub trait ErrorId {
-    fn identifier(&self, pos: usize) -> ErrId;
+    fn identifier(&self, id: EntityId) -> ErrId;
 }
 
 impl ErrorId for Material {
-    fn identifier(&self, pos: usize) -> ErrId {
-        ErrId(1000 + pos)
+    fn identifier(&self, id: EntityId) -> ErrId {
+        ErrId(1, id)
     }
 }
 
 impl ErrorId for Object {
-    fn identifier(&self, pos: usize) -> ErrId {
-        ErrId(2000 + pos)
+    fn identifier(&self, id: EntityId) -> ErrId {
+        ErrId(2, id)
     }
 }
 
 impl ErrorId for LibraryCode {
-    fn identifier(&self, pos: usize) -> ErrId {
-        ErrId(3000 + pos)
+    fn identifier(&self, id: EntityId) -> ErrId {
+        ErrId(3, id)
     }
 }
 
 impl ErrorId for Matrix {
-    fn identifier(&self, pos: usize) -> ErrId {
-        ErrId(4000 + pos)
+    fn identifier(&self, id: EntityId) -> ErrId {
+        ErrId(4, id)
+    }
+}
+
+impl ErrorId for AnyUniform {
+    fn identifier(&self, id: EntityId) -> ErrId {
+        ErrId(5, id)
+    }
+}
+
+impl ErrorId for TextureName {
+    fn identifier(&self, id: EntityId) -> ErrId {
+        ErrId(6, id)
     }
 }
 
@@ -69,10 +85,36 @@ impl LineNumbersByKey {
     }
 }
 
+/// Lines of generated code and entities they are generated for. Unlike `LineNumbersByKey`, one entity can have many ranges, and ranges don't have meaningful local line numbers.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct SourceMap(pub Vec<(Range<usize>, ErrId)>);
+
+impl SourceMap {
+    pub fn offset(&mut self, lines: usize) {
+        self.0
+            .iter_mut()
+            .for_each(|(line, _)| *line = line.start + lines..line.end + lines);
+    }
+
+    pub fn extend(&mut self, other: SourceMap) {
+        self.0.extend(other.0);
+    }
+
+    /// Innermost entity that generated this line.
+    pub fn get_identifier(&self, line_no: usize) -> Option<ErrId> {
+        self.0
+            .iter()
+            .filter(|(range, _)| range.contains(&line_no))
+            .min_by_key(|(range, _)| range.len())
+            .map(|(_, id)| *id)
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct StringStorage {
     pub storage: String,
     pub line_numbers: LineNumbersByKey,
+    pub source_map: SourceMap,
     current_line_no: usize,
 }
 
@@ -82,6 +124,7 @@ impl Default for StringStorage {
             storage: Default::default(),
             current_line_no: 1,
             line_numbers: Default::default(),
+            source_map: Default::default(),
         }
     }
 }
@@ -99,30 +142,305 @@ impl StringStorage {
         self.line_numbers.add(identifier, start..end + 1);
     }
 
+    /// Lines that are added by `f` are marked in source map as generated for `identifier`.
+    pub fn add_generated<T>(&mut self, identifier: ErrId, f: impl FnOnce(&mut Self) -> T) -> T {
+        let start = self.current_line_no;
+        let len = self.storage.len();
+        let result = f(self);
+        if self.storage.len() > len {
+            let end = if self.storage.ends_with('\n') {
+                self.current_line_no
+            } else {
+                self.current_line_no + 1
+            };
+            self.source_map.0.push((start..end, identifier));
+        }
+        result
+    }
+
     pub fn add_string_storage(&mut self, mut other: StringStorage) {
         other.line_numbers.offset(self.current_line_no - 1);
+        other.source_map.offset(self.current_line_no - 1);
         self.add_string(other.storage);
         self.line_numbers.extend(other.line_numbers);
+        self.source_map.extend(other.source_map);
+    }
+
+    /// Entity that is responsible for line of generated code, and local line number if line is written by user.
+    pub fn get_identifier(&self, line_no: usize) -> Option<(ErrId, Option<usize>)> {
+        self.line_numbers
+            .get_identifier(line_no)
+            .map(|(id, local_line_no)| (id, Some(local_line_no)))
+            .or_else(|| self.source_map.get_identifier(line_no).map(|id| (id, None)))
     }
 }
 
-pub fn apply_template(
-    template: &str,
-    mut storages: BTreeMap<String, StringStorage>,
-) -> StringStorage {
-    let mut result = StringStorage::default();
-    for (is_name, s) in template
-        .split("//%")
-        .enumerate()
-        .map(|(pos, s)| (pos % 2 == 1, s))
-    {
-        if is_name {
-            result.add_string_storage(storages.remove(s).expect(s));
+/// Shader template: text with placeholders `//%name//%` that are replaced by storages, and conditional sections `//%if flag//%`, `//%else//%`, `//%endif//%`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template<'a> {
+    parts: Vec<TemplatePart<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart<'a> {
+    Text(&'a str),
+    Storage {
+        name: &'a str,
+        line: usize,
+    },
+    Condition {
+        flag: &'a str,
+        line: usize,
+        then: Vec<TemplatePart<'a>>,
+        otherwise: Vec<TemplatePart<'a>>,
+    },
+}
+
+/// Lines are counted in template from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    UnclosedDirective { line: usize },
+    InvalidDirective { directive: String, line: usize },
+    UnexpectedDirective { directive: String, line: usize },
+    UnclosedCondition { flag: String, line: usize },
+    MissingStorage { name: String, line: usize },
+    DuplicateStorage { name: String, line: usize },
+    UnusedStorage { name: String },
+    MissingFlag { flag: String, line: usize },
+    UnusedFlag { flag: String },
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use TemplateError::*;
+        match self {
+            UnclosedDirective { line } => write!(f, "line {}: `//%` is not closed", line),
+            InvalidDirective { directive, line } => {
+                write!(f, "line {}: invalid directive `{}`", line, directive)
+            }
+            UnexpectedDirective { directive, line } => {
+                write!(f, "line {}: `{}` without matching `if`", line, directive)
+            }
+            UnclosedCondition { flag, line } => {
+                write!(f, "line {}: `if {}` is not closed by `endif`", line, flag)
+            }
+            MissingStorage { name, line } => {
+                write!(f, "line {}: no storage for placeholder `{}`", line, name)
+            }
+            DuplicateStorage { name, line } => {
+                write!(f, "line {}: placeholder `{}` is used twice", line, name)
+            }
+            UnusedStorage { name } => write!(f, "storage `{}` has no placeholder", name),
+            MissingFlag { flag, line } => write!(f, "line {}: flag `{}` is not set", line, flag),
+            UnusedFlag { flag } => write!(f, "flag `{}` is not used in template", flag),
+        }
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct OpenCondition<'a> {
+    flag: &'a str,
+    line: usize,
+    then: Vec<TemplatePart<'a>>,
+    otherwise: Option<Vec<TemplatePart<'a>>>,
+}
+
+impl<'a> Template<'a> {
+    pub fn parse(text: &'a str) -> Result<Self, TemplateError> {
+        let mut root = Vec::new();
+        let mut open: Vec<OpenCondition> = Vec::new();
+        let mut line = 1;
+        let mut is_directive = false;
+        for s in text.split("//%") {
+            let current = match open.last_mut() {
+                Some(condition) => condition.otherwise.as_mut().unwrap_or(&mut condition.then),
+                None => &mut root,
+            };
+            if !is_directive {
+                current.push(TemplatePart::Text(s));
+            } else if let Some(flag) = s.strip_prefix("if ") {
+                if !is_identifier(flag) {
+                    return Err(TemplateError::InvalidDirective {
+                        directive: s.to_owned(),
+                        line,
+                    });
+                }
+                open.push(OpenCondition {
+                    flag,
+                    line,
+                    then: Vec::new(),
+                    otherwise: None,
+                });
+            } else if s == "else" {
+                match open.last_mut() {
+                    Some(condition) if condition.otherwise.is_none() => {
+                        condition.otherwise = Some(Vec::new())
+                    }
+                    _ => {
+                        return Err(TemplateError::UnexpectedDirective {
+                            directive: s.to_owned(),
+                            line,
+                        })
+                    }
+                }
+            } else if s == "endif" {
+                let condition = open.pop().ok_or_else(|| TemplateError::UnexpectedDirective {
+                    directive: s.to_owned(),
+                    line,
+                })?;
+                let part = TemplatePart::Condition {
+                    flag: condition.flag,
+                    line: condition.line,
+                    then: condition.then,
+                    otherwise: condition.otherwise.unwrap_or_default(),
+                };
+                match open.last_mut() {
+                    Some(condition) => condition
+                        .otherwise
+                        .as_mut()
+                        .unwrap_or(&mut condition.then)
+                        .push(part),
+                    None => root.push(part),
+                }
+            } else if is_identifier(s) {
+                current.push(TemplatePart::Storage { name: s, line });
+            } else {
+                return Err(TemplateError::InvalidDirective {
+                    directive: s.to_owned(),
+                    line,
+                });
+            }
+            line += s.matches('\n').count();
+            is_directive = !is_directive;
+        }
+
+        // Text always goes after the last directive, so ending with directive means that the last
+        // `//%` has no pair.
+        if !is_directive {
+            return Err(TemplateError::UnclosedDirective { line });
+        }
+        if let Some(condition) = open.pop() {
+            return Err(TemplateError::UnclosedCondition {
+                flag: condition.flag.to_owned(),
+                line: condition.line,
+            });
+        }
+        Ok(Self { parts: root })
+    }
+
+    fn visit(parts: &[TemplatePart<'a>], f: &mut impl FnMut(&TemplatePart<'a>)) {
+        for part in parts {
+            f(part);
+            if let TemplatePart::Condition {
+                then, otherwise, ..
+            } = part
+            {
+                Self::visit(then, f);
+                Self::visit(otherwise, f);
+            }
+        }
+    }
+
+    /// Placeholders and flags from all sections, including disabled ones.
+    fn names(&self) -> (BTreeSet<&'a str>, BTreeSet<&'a str>) {
+        let mut storages = BTreeSet::new();
+        let mut flags = BTreeSet::new();
+        Self::visit(&self.parts, &mut |part| match part {
+            TemplatePart::Storage { name, .. } => {
+                storages.insert(*name);
+            }
+            TemplatePart::Condition { flag, .. } => {
+                flags.insert(*flag);
+            }
+            TemplatePart::Text(_) => {}
+        });
+        (storages, flags)
+    }
+
+    /// Every storage must have a placeholder and every flag must be used, so typos are found. Storages of disabled sections can be omitted.
+    pub fn apply(
+        &self,
+        mut storages: BTreeMap<String, StringStorage>,
+        flags: &BTreeMap<&str, bool>,
+    ) -> Result<StringStorage, Vec<TemplateError>> {
+        let mut errors = Vec::new();
+        let (used_storages, used_flags) = self.names();
+        for name in storages.keys() {
+            if !used_storages.contains(&name[..]) {
+                errors.push(TemplateError::UnusedStorage { name: name.clone() });
+            }
+        }
+        for flag in flags.keys() {
+            if !used_flags.contains(flag) {
+                errors.push(TemplateError::UnusedFlag {
+                    flag: flag.to_string(),
+                });
+            }
+        }
+
+        let mut result = StringStorage::default();
+        let mut applied = BTreeSet::new();
+        let mut stack: Vec<&[TemplatePart]> = vec![&self.parts];
+        while let Some(parts) = stack.pop() {
+            let (part, rest) = match parts.split_first() {
+                Some(x) => x,
+                None => continue,
+            };
+            stack.push(rest);
+            match part {
+                TemplatePart::Text(text) => result.add_string(text),
+                TemplatePart::Storage { name, line } => match storages.remove(*name) {
+                    Some(storage) => {
+                        applied.insert(*name);
+                        result.add_string_storage(storage);
+                    }
+                    None => errors.push(if applied.contains(name) {
+                        TemplateError::DuplicateStorage {
+                            name: name.to_string(),
+                            line: *line,
+                        }
+                    } else {
+                        TemplateError::MissingStorage {
+                            name: name.to_string(),
+                            line: *line,
+                        }
+                    }),
+                },
+                TemplatePart::Condition {
+                    flag,
+                    line,
+                    then,
+                    otherwise,
+                } => match flags.get(flag) {
+                    Some(true) => stack.push(then),
+                    Some(false) => stack.push(otherwise),
+                    None => errors.push(TemplateError::MissingFlag {
+                        flag: flag.to_string(),
+                        line: *line,
+                    }),
+                },
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(result)
         } else {
-            result.add_string(s);
+            Err(errors)
         }
     }
-    result
+}
+
+pub fn apply_template(
+    template: &str,
+    storages: BTreeMap<String, StringStorage>,
+    flags: &BTreeMap<&str, bool>,
+) -> Result<StringStorage, Vec<TemplateError>> {
+    Template::parse(template)
+        .map_err(|err| vec![err])?
+        .apply(storages, flags)
 }
 
 #[cfg(test)]
@@ -133,27 +451,29 @@ mod tests_string_storage {
     fn test() {
         let mut s1 = StringStorage::default();
         s1.add_string("1\n2\n3\n");
-        s1.add_identifier_string(ErrId(1), "\n4\n5\n");
+        s1.add_identifier_string(ErrId(1, EntityId(0)), "\n4\n5\n");
 
         assert_eq!(
             s1,
             StringStorage {
                 storage: "1\n2\n3\n\n4\n5\n".to_owned(),
                 current_line_no: 7,
-                line_numbers: LineNumbersByKey(vec![(ErrId(1), 4..8)].into_iter().collect()),
+                line_numbers: LineNumbersByKey(vec![(ErrId(1, EntityId(0)), 4..8)].into_iter().collect()),
+                source_map: SourceMap::default(),
             }
         );
 
         let mut s2 = StringStorage::default();
         s2.add_string("a\nb");
-        s2.add_identifier_string(ErrId(2), "c\nd");
+        s2.add_identifier_string(ErrId(2, EntityId(0)), "c\nd");
 
         assert_eq!(
             s2,
             StringStorage {
                 storage: "a\nbc\nd".to_owned(),
                 current_line_no: 3,
-                line_numbers: LineNumbersByKey(vec![(ErrId(2), 2..4)].into_iter().collect()),
+                line_numbers: LineNumbersByKey(vec![(ErrId(2, EntityId(0)), 2..4)].into_iter().collect()),
+                source_map: SourceMap::default(),
             }
         );
 
@@ -161,7 +481,8 @@ mod tests_string_storage {
             .into_iter()
             .collect::<BTreeMap<_, _>>();
 
-        let s = apply_template("abc\n//%s2//%\n\ne\nf\n//%s1//%\n9", storages);
+        let s = apply_template("abc\n//%s2//%\n\ne\nf\n//%s1//%\n9", storages, &BTreeMap::new())
+            .unwrap();
 
         assert_eq!(
             s,
@@ -169,11 +490,221 @@ mod tests_string_storage {
                 storage: "abc\na\nbc\nd\n\ne\nf\n1\n2\n3\n\n4\n5\n\n9".to_owned(),
                 current_line_no: 15,
                 line_numbers: LineNumbersByKey(
-                    vec![(ErrId(1), 11..15), (ErrId(2), 3..5)]
+                    vec![(ErrId(1, EntityId(0)), 11..15), (ErrId(2, EntityId(0)), 3..5)]
                         .into_iter()
                         .collect()
                 ),
+                source_map: SourceMap::default(),
             }
         );
     }
+
+    #[test]
+    fn source_map() {
+        let object = ErrId(2, EntityId(0));
+        let code = ErrId(3, EntityId(1));
+        let mut s = StringStorage::default();
+        s.add_string("a\n");
+        s.add_generated(object, |s| {
+            s.add_string("int f() {\n");
+            s.add_identifier_string(code, "b\nc");
+            s.add_string("\n}\n");
+        });
+        s.add_generated(code, |_| {});
+        s.add_string("d\n");
+
+        let storages = vec![("s".to_owned(), s)].into_iter().collect();
+        let s = apply_template("x\n//%s//%", storages, &BTreeMap::new()).unwrap();
+        assert_eq!(s.storage, "x\na\nint f() {\nb\nc\n}\nd\n");
+        let ids = (1..=8).map(|line| s.get_identifier(line)).collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                None,
+                None,
+                Some((object, None)),
+                Some((code, Some(1))),
+                Some((code, Some(2))),
+                Some((object, None)),
+                None,
+                None,
+            ]
+        );
+    }
+
+    fn storage(text: &str) -> StringStorage {
+        let mut result = StringStorage::default();
+        result.add_string(text);
+        result
+    }
+
+    fn storages(names: &[&str]) -> BTreeMap<String, StringStorage> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), storage(&name.to_uppercase())))
+            .collect()
+    }
+
+    #[test]
+    fn conditions() {
+        let template = concat!(
+            "a\n//%if x//%\n//%s1//%\n//%if y//%Y//%endif//%",
+            "//%else//%\n//%s2//%//%endif//%\nb"
+        );
+        let flags = |x, y| vec![("x", x), ("y", y)].into_iter().collect();
+        let apply = |names: &[&str], x, y| {
+            apply_template(template, storages(names), &flags(x, y)).map(|s| s.storage)
+        };
+        assert_eq!(apply(&["s1"], true, true), Ok("a\n\nS1\nY\nb".to_owned()));
+        assert_eq!(apply(&["s1"], true, false), Ok("a\n\nS1\n\nb".to_owned()));
+        assert_eq!(apply(&["s2"], false, true), Ok("a\n\nS2\nb".to_owned()));
+        assert_eq!(
+            apply(&["s1"], false, false),
+            Err(vec![TemplateError::MissingStorage {
+                name: "s2".to_owned(),
+                line: 5,
+            }])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        use TemplateError::*;
+        let no_flags = BTreeMap::new();
+        let apply = |template, names: &[&str], flags: &BTreeMap<&str, bool>| {
+            apply_template(template, storages(names), flags).map(|s| s.storage)
+        };
+
+        assert_eq!(
+            apply("a\n//%s1//%\n//%s1//%", &["s1", "s3"], &no_flags),
+            Err(vec![
+                UnusedStorage {
+                    name: "s3".to_owned()
+                },
+                DuplicateStorage {
+                    name: "s1".to_owned(),
+                    line: 3
+                },
+            ])
+        );
+        assert_eq!(
+            apply("//%if x//%//%endif//%", &[], &no_flags),
+            Err(vec![MissingFlag {
+                flag: "x".to_owned(),
+                line: 1
+            }])
+        );
+        let flags = vec![("z", true)].into_iter().collect();
+        assert_eq!(
+            apply("", &[], &flags),
+            Err(vec![UnusedFlag {
+                flag: "z".to_owned()
+            }])
+        );
+
+        let parse = |template| Template::parse(template).map(|_| ());
+        assert_eq!(parse("a\n//%s1"), Err(UnclosedDirective { line: 2 }));
+        assert_eq!(
+            parse("//%s 1//%"),
+            Err(InvalidDirective {
+                directive: "s 1".to_owned(),
+                line: 1
+            })
+        );
+        assert_eq!(
+            parse("\n//%if x//%//%else//%//%else//%"),
+            Err(UnexpectedDirective {
+                directive: "else".to_owned(),
+                line: 2
+            })
+        );
+        assert_eq!(
+            parse("//%endif//%"),
+            Err(UnexpectedDirective {
+                directive: "endif".to_owned(),
+                line: 1
+            })
+        );
+        assert_eq!(
+            parse("//%if x//%\n//%if y//%//%endif//%"),
+            Err(UnclosedCondition {
+                flag: "x".to_owned(),
+                line: 1
+            })
+        );
+    }
+}
+
+/// Snapshot tests of generated code for every scene in `scenes/`. When code generation is changed intentionally, run `UPDATE_SNAPSHOTS=1 cargo test snapshots` and review changes of `scenes/snapshots/` in diff.
+#[cfg(test)]
+mod snapshots {
+    use crate::gui::scene::{Scene, ShaderOptions};
+    use crate::scene_files::*;
+
+    use std::path::Path;
+
+    fn uniforms(scene: &Scene) -> String {
+        scene
+            .uniforms(&ShaderOptions::default())
+            .iter()
+            .map(|(name, kind)| format!("{:?} {}\n", kind, name))
+            .collect()
+    }
+
+    /// Returns description of difference, or writes `actual` when in update mode.
+    fn check(path: &Path, actual: &str, update: bool) -> Option<String> {
+        let expected = std::fs::read_to_string(path)
+            .ok()
+            .map(|x| x.replace("\r\n", "\n"));
+        if expected.as_deref() == Some(actual) {
+            return None;
+        }
+        if update {
+            std::fs::write(path, actual).unwrap();
+            return None;
+        }
+        let expected = match expected {
+            Some(expected) => expected,
+            None => return Some(format!("{}: snapshot is missing", path.display())),
+        };
+        let line = expected
+            .lines()
+            .zip(actual.lines())
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+        Some(format!(
+            "{}: differs at line {}\n  expected: {:?}\n  actual:   {:?}",
+            path.display(),
+            line + 1,
+            expected.lines().nth(line).unwrap_or_default(),
+            actual.lines().nth(line).unwrap_or_default(),
+        ))
+    }
+
+    #[test]
+    fn snapshots() {
+        let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let dir = root.join("snapshots");
+        if update {
+            std::fs::create_dir_all(&dir).unwrap();
+        }
+
+        let mut failures = Vec::new();
+        for file in scan_scenes_dir(&root).unwrap() {
+            let scene = parse_scene(&file).unwrap();
+            let code = scene
+                .generate_shader_code(&ShaderOptions::default())
+                .unwrap_or_else(|errors| panic!("{}: {:?}", file.id, errors));
+            let glsl = dir.join(format!("{}.frag.glsl", file.id));
+            let uniforms_path = dir.join(format!("{}.uniforms.txt", file.id));
+            failures.extend(check(&glsl, &code.storage, update));
+            failures.extend(check(&uniforms_path, &uniforms(&scene), update));
+        }
+        assert!(
+            failures.is_empty(),
+            "{}\nrun `UPDATE_SNAPSHOTS=1 cargo test snapshots` if changes are intended",
+            failures.join("\n")
+        );
+    }
 }
@@ -0,0 +1,1474 @@
+//! Reference CPU implementation of `frag.glsl` and `library.glsl`. Every function here mirrors GLSL function with the same name, so when you change shader code, change this code too.
+
+use crate::gui::material::Material;
+use crate::gui::object::{MatrixName, Object, ObjectType, Primitive};
+use crate::gui::scene::Scene;
+use crate::gui::storage::GetEnum;
+use crate::gui::uniform::FormulasCache;
+
+use glam::*;
+
+use std::collections::BTreeMap;
+
+// System materials
+pub const NOT_INSIDE: i32 = 0;
+pub const TELEPORT: i32 = 1;
+
+// Actual predefined materials
+pub const DEBUG_RED: i32 = 2;
+pub const DEBUG_GREEN: i32 = 3;
+pub const DEBUG_BLUE: i32 = 4;
+
+pub const USER_MATERIAL_OFFSET: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub o: Vec4,
+    pub d: Vec4,
+}
+
+pub const RAY_NONE: Ray = Ray {
+    o: const_vec4!([0., 0., 0., 0.]),
+    d: const_vec4!([0., 0., 0., 0.]),
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceIntersection {
+    pub hit: bool,
+    pub t: f32,
+    pub u: f32,
+    pub v: f32,
+    pub n: Vec3,
+}
+
+pub const INTERSECTION_NONE: SurfaceIntersection = SurfaceIntersection {
+    hit: false,
+    t: 1e10,
+    u: 0.,
+    v: 0.,
+    n: const_vec3!([0., 0., 0.]),
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneIntersection {
+    pub material: i32,
+    pub hit: SurfaceIntersection,
+}
+
+pub const SCENE_INTERSECTION_NONE: SceneIntersection = SceneIntersection {
+    material: 0,
+    hit: INTERSECTION_NONE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialProcessing {
+    pub is_final: bool,
+    pub mul_to_color: Vec3,
+    pub new_ray: Ray,
+}
+
+/// Arguments of `is_inside` function of flat object. `back` and `first` are provided only for portals.
+#[derive(Debug, Clone, Copy)]
+pub struct IsInsideArgs {
+    pub pos: Vec4,
+    pub x: f32,
+    pub y: f32,
+    pub back: bool,
+    pub first: bool,
+}
+
+pub type IsInsideFn = Box<dyn Fn(&IsInsideArgs) -> i32>;
+
+/// User GLSL code that can't be executed on CPU.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Unsupported {
+    IsInside { object: String },
+    Intersect { object: String },
+    Material { material: String },
+}
+
+impl std::fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unsupported::IsInside { object } => write!(f, "`is_inside` of object `{}`", object),
+            Unsupported::Intersect { object } => write!(f, "`intersect` of object `{}`", object),
+            Unsupported::Material { material } => write!(f, "code of material `{}`", material),
+        }
+    }
+}
+
+/// Values of uniforms that starts with `_`.
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub camera: Mat4,
+    pub view_angle: f32,
+    pub use_panini_projection: bool,
+    pub panini_param: f32,
+    pub ray_tracing_depth: i32,
+    pub offset_after_material: f32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            camera: Mat4::IDENTITY,
+            view_angle: std::f32::consts::PI / 2.,
+            use_panini_projection: false,
+            panini_param: 1.0,
+            ray_tracing_depth: 100,
+            offset_after_material: 0.005,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MatrixPair {
+    normal: Mat4,
+    inverse: Mat4,
+}
+
+#[derive(Debug, Clone)]
+enum CpuObject {
+    Debug(MatrixPair),
+    Flat {
+        name: String,
+        matrix: MatrixPair,
+    },
+    FlatPortal {
+        name: String,
+        first: MatrixPair,
+        second: MatrixPair,
+        first_material: i32,
+        second_material: i32,
+    },
+    Complex {
+        name: String,
+    },
+    /// Values of parameters are in order of `Primitive::parameters`.
+    Primitive {
+        matrix: MatrixPair,
+        shape: Primitive,
+        params: Vec<f32>,
+        material: i32,
+    },
+    MatrixError,
+}
+
+#[derive(Debug, Clone)]
+enum CpuMaterial {
+    Simple {
+        color: Vec3,
+        normal_coef: f32,
+        grid: bool,
+        grid_scale: f32,
+        grid_coef: f32,
+    },
+    Reflect {
+        add_to_color: Vec3,
+    },
+    Refract {
+        refractive_index: f32,
+        add_to_color: Vec3,
+    },
+    Teleport(Option<Mat4>),
+    Complex {
+        name: String,
+    },
+}
+
+pub struct CpuRender {
+    objects: Vec<CpuObject>,
+    materials: Vec<CpuMaterial>,
+    material_names: BTreeMap<String, i32>,
+    is_inside: BTreeMap<String, IsInsideFn>,
+}
+
+impl CpuRender {
+    /// `formulas_cache` must contain all scene formulas, so call `Scene::init` before.
+    pub fn new(scene: &Scene, formulas_cache: &FormulasCache) -> Self {
+        let get_matrix = |name: &MatrixName| -> Option<MatrixPair> {
+            match scene.matrices.get(&name.0, &scene.uniforms, formulas_cache) {
+                GetEnum::Ok(normal) => Some(MatrixPair {
+                    normal,
+                    inverse: normal.inverse(),
+                }),
+                _ => None,
+            }
+        };
+
+        let mut materials = Vec::new();
+        let mut material_names = BTreeMap::new();
+        for (name, material) in scene.materials.iter() {
+            material_names.insert(name.clone(), USER_MATERIAL_OFFSET + materials.len() as i32);
+            use Material::*;
+            materials.push(match &material.0 {
+                Simple {
+                    color,
+                    normal_coef,
+                    grid,
+                    grid_scale,
+                    grid_coef,
+                } => CpuMaterial::Simple {
+                    color: Vec3::from(*color),
+                    normal_coef: *normal_coef,
+                    grid: *grid,
+                    grid_scale: *grid_scale,
+                    grid_coef: *grid_coef,
+                },
+                Reflect { add_to_color } => CpuMaterial::Reflect {
+                    add_to_color: Vec3::from(*add_to_color),
+                },
+                Refract {
+                    refractive_index,
+                    add_to_color,
+                } => CpuMaterial::Refract {
+                    refractive_index: *refractive_index,
+                    add_to_color: Vec3::from(*add_to_color),
+                },
+                Complex { .. } => CpuMaterial::Complex { name: name.clone() },
+            });
+        }
+
+        let mut objects = Vec::new();
+        for (name, object) in scene.objects.iter() {
+            use Object::*;
+            use ObjectType::*;
+            let kind = match &object.0 {
+                DebugMatrix(_) | Primitive { .. } => None,
+                Flat { kind, .. } | Complex { kind, .. } => Some(kind),
+            };
+
+            // Teleport materials are defined for every portal, even for broken one, to keep numbers the same as in shader.
+            let teleports = if let Some(Portal(a, b)) = kind {
+                let (ma, mb) = (get_matrix(a), get_matrix(b));
+                let teleport = |from: &Option<MatrixPair>, to: &Option<MatrixPair>| {
+                    from.as_ref()
+                        .zip(to.as_ref())
+                        .map(|(from, to)| to.normal * from.inverse)
+                };
+                let first = USER_MATERIAL_OFFSET + materials.len() as i32;
+                materials.push(CpuMaterial::Teleport(teleport(&ma, &mb)));
+                materials.push(CpuMaterial::Teleport(teleport(&mb, &ma)));
+                Some((first, first + 1, ma, mb))
+            } else {
+                None
+            };
+
+            objects.push(match (&object.0, teleports) {
+                (DebugMatrix(matrix), _) => get_matrix(matrix)
+                    .map(CpuObject::Debug)
+                    .unwrap_or(CpuObject::MatrixError),
+                (
+                    Flat {
+                        kind: Simple(matrix),
+                        ..
+                    },
+                    _,
+                ) => get_matrix(matrix)
+                    .map(|matrix| CpuObject::Flat {
+                        name: name.clone(),
+                        matrix,
+                    })
+                    .unwrap_or(CpuObject::MatrixError),
+                (
+                    Flat { .. },
+                    Some((first_material, second_material, Some(first), Some(second))),
+                ) => CpuObject::FlatPortal {
+                    name: name.clone(),
+                    first,
+                    second,
+                    first_material,
+                    second_material,
+                },
+                (Flat { .. }, _) => CpuObject::MatrixError,
+                (Complex { .. }, _) => CpuObject::Complex { name: name.clone() },
+                // Shader with unknown material is not compiled, so such primitive is not rendered too.
+                (
+                    Primitive {
+                        matrix,
+                        shape,
+                        material,
+                    },
+                    _,
+                ) => match (get_matrix(matrix), material_names.get(&material.0)) {
+                    (Some(matrix), Some(material)) => CpuObject::Primitive {
+                        matrix,
+                        shape: shape.clone(),
+                        params: shape
+                            .parameters()
+                            .into_iter()
+                            .map(|(_, _, param)| param.get(&scene.uniforms, formulas_cache) as f32)
+                            .collect(),
+                        material: *material,
+                    },
+                    _ => CpuObject::MatrixError,
+                },
+            });
+        }
+
+        CpuRender {
+            objects,
+            materials,
+            material_names,
+            is_inside: BTreeMap::new(),
+        }
+    }
+
+    /// Rust replacement for GLSL `is_inside` code of flat object with name `object`.
+    pub fn set_is_inside(&mut self, object: &str, f: impl Fn(&IsInsideArgs) -> i32 + 'static) {
+        self.is_inside.insert(object.to_owned(), Box::new(f));
+    }
+
+    /// Number of user material, the same as `name_M` define in shader.
+    pub fn material(&self, name: &str) -> Option<i32> {
+        self.material_names.get(name).copied()
+    }
+
+    /// Returns all user code that will be skipped while rendering: objects are not intersected, materials returns black color.
+    pub fn unsupported(&self) -> Vec<Unsupported> {
+        let mut result = Vec::new();
+        for object in &self.objects {
+            match object {
+                CpuObject::Flat { name, .. } | CpuObject::FlatPortal { name, .. } => {
+                    if !self.is_inside.contains_key(name) {
+                        result.push(Unsupported::IsInside {
+                            object: name.clone(),
+                        });
+                    }
+                }
+                CpuObject::Complex { name } => result.push(Unsupported::Intersect {
+                    object: name.clone(),
+                }),
+                CpuObject::Debug(_) | CpuObject::Primitive { .. } | CpuObject::MatrixError => {}
+            }
+        }
+        for material in &self.materials {
+            if let CpuMaterial::Complex { name } = material {
+                result.push(Unsupported::Material {
+                    material: name.clone(),
+                });
+            }
+        }
+        result
+    }
+
+    fn call_is_inside(&self, name: &str, args: IsInsideArgs) -> i32 {
+        match self.is_inside.get(name) {
+            Some(f) => f(&args),
+            None => NOT_INSIDE,
+        }
+    }
+
+    pub fn scene_intersect(&self, r: Ray) -> SceneIntersection {
+        let mut i = SCENE_INTERSECTION_NONE;
+        for object in &self.objects {
+            match object {
+                CpuObject::Debug(matrix) => {
+                    let mut transformed_ray = transform(matrix.inverse, r);
+                    let len = transformed_ray.d.length();
+                    transformed_ray.d = transformed_ray.d.normalize();
+                    let mut ihit = debug_intersect(transformed_ray);
+                    ihit.hit.t /= len;
+                    if nearer(&i.hit, &ihit.hit) {
+                        i = ihit;
+                        i.hit.n = (matrix.normal * i.hit.n.extend(0.)).truncate().normalize();
+                    }
+                }
+                CpuObject::Primitive {
+                    matrix,
+                    shape,
+                    params,
+                    material,
+                } => {
+                    let mut transformed_ray = transform(matrix.inverse, r);
+                    let len = transformed_ray.d.length();
+                    transformed_ray.d = transformed_ray.d.normalize();
+                    let mut ihit = SceneIntersection {
+                        material: *material,
+                        hit: primitive_intersect(shape, params, transformed_ray),
+                    };
+                    ihit.hit.t /= len;
+                    if nearer(&i.hit, &ihit.hit) {
+                        i = ihit;
+                        i.hit.n = (matrix.normal * i.hit.n.extend(0.)).truncate().normalize();
+                    }
+                }
+                CpuObject::Flat { name, matrix } => {
+                    let hit = plane_intersect(r, matrix.inverse, get_normal(matrix.normal));
+                    if nearer(&i.hit, &hit) {
+                        let inside = self.call_is_inside(
+                            name,
+                            IsInsideArgs {
+                                pos: r.o + r.d * hit.t,
+                                x: hit.u,
+                                y: hit.v,
+                                back: false,
+                                first: false,
+                            },
+                        );
+                        i = process_plane_intersection(i, hit, inside);
+                    }
+                }
+                CpuObject::FlatPortal {
+                    name,
+                    first,
+                    second,
+                    first_material,
+                    second_material,
+                } => {
+                    for (matrix, is_first, material) in [
+                        (first, true, *first_material),
+                        (second, false, *second_material),
+                    ]
+                    .iter()
+                    {
+                        let normal = if *is_first {
+                            -get_normal(matrix.normal)
+                        } else {
+                            get_normal(matrix.normal)
+                        };
+                        let hit = plane_intersect(r, matrix.inverse, normal);
+                        if nearer(&i.hit, &hit) {
+                            let inside = self.call_is_inside(
+                                name,
+                                IsInsideArgs {
+                                    pos: r.o + r.d * hit.t,
+                                    x: hit.u,
+                                    y: hit.v,
+                                    back: is_collinear(hit.n, normal),
+                                    first: *is_first,
+                                },
+                            );
+                            i = process_portal_intersection(i, hit, inside, *material);
+                        }
+                    }
+                }
+                CpuObject::Complex { .. } | CpuObject::MatrixError => {}
+            }
+        }
+        i
+    }
+
+    pub fn material_process(
+        &self,
+        r: Ray,
+        i: SceneIntersection,
+        settings: &RenderSettings,
+    ) -> MaterialProcessing {
+        let hit = i.hit;
+        match i.material {
+            DEBUG_RED => material_simple(hit, r, color(0.9, 0.2, 0.2), 0.5, false, 1., 0.),
+            DEBUG_GREEN => material_simple(hit, r, color(0.2, 0.9, 0.2), 0.5, false, 1., 0.),
+            DEBUG_BLUE => material_simple(hit, r, color(0.2, 0.2, 0.9), 0.5, false, 1., 0.),
+            material if material >= USER_MATERIAL_OFFSET => {
+                let offset = settings.offset_after_material;
+                match self
+                    .materials
+                    .get((material - USER_MATERIAL_OFFSET) as usize)
+                {
+                    Some(CpuMaterial::Simple {
+                        color,
+                        normal_coef,
+                        grid,
+                        grid_scale,
+                        grid_coef,
+                    }) => material_simple(
+                        hit,
+                        r,
+                        *color,
+                        *normal_coef,
+                        *grid,
+                        *grid_scale,
+                        *grid_coef,
+                    ),
+                    Some(CpuMaterial::Reflect { add_to_color }) => {
+                        material_reflect(hit, r, *add_to_color, offset)
+                    }
+                    Some(CpuMaterial::Refract {
+                        refractive_index,
+                        add_to_color,
+                    }) => material_refract(hit, r, *add_to_color, *refractive_index, offset),
+                    Some(CpuMaterial::Teleport(Some(matrix))) => {
+                        material_teleport(hit, r, *matrix, offset)
+                    }
+                    Some(CpuMaterial::Teleport(None))
+                    | Some(CpuMaterial::Complex { .. })
+                    | None => material_final(Vec3::ZERO),
+                }
+            }
+            // If there is no material with this number.
+            _ => material_final(Vec3::ZERO),
+        }
+    }
+
+    pub fn ray_tracing(&self, mut r: Ray, settings: &RenderSettings) -> Vec3 {
+        let mut current_color = Vec3::ONE;
+        for j in 0..10000 {
+            if j > settings.ray_tracing_depth {
+                return current_color;
+            }
+            let i = self.scene_intersect(r);
+
+            // Offset ray
+            r.o += r.d * i.hit.t;
+            if i.hit.hit {
+                let m = self.material_process(r, i, settings);
+                current_color *= m.mul_to_color;
+                if m.is_final {
+                    return current_color;
+                } else {
+                    r = m.new_ray;
+                }
+            } else {
+                return current_color * color(0.6, 0.6, 0.6);
+            }
+        }
+        current_color
+    }
+
+    /// Color of pixel at position `x`, `y` from the top left corner, as `gl_FragColor`.
+    pub fn render_pixel(&self, x: f32, y: f32, settings: &RenderSettings) -> Vec3 {
+        let resolution = Vec2::new(settings.width as f32, settings.height as f32);
+        let uv_screen = (Vec2::new(x, y) - resolution / 2.) / resolution.x.min(resolution.y) * 2.;
+
+        let camera = settings.camera;
+        let o = camera * Vec4::new(0., 0., 0., 1.);
+        let d = if settings.use_panini_projection {
+            (camera
+                * panini_projection(uv_screen, settings.view_angle, settings.panini_param)
+                    .extend(0.))
+            .normalize()
+        } else {
+            let h = (settings.view_angle / 2.).tan();
+            (camera * Vec4::new(uv_screen.x * h, uv_screen.y * h, 1.0, 0.)).normalize()
+        };
+
+        let c = self.ray_tracing(Ray { o, d }, settings);
+        Vec3::new(c.x.sqrt(), c.y.sqrt(), c.z.sqrt())
+    }
+
+    /// Returns `width * height` colors, row by row from the top.
+    pub fn render(&self, settings: &RenderSettings) -> Vec<Vec3> {
+        let mut result = Vec::with_capacity(settings.width * settings.height);
+        for y in 0..settings.height {
+            for x in 0..settings.width {
+                result.push(self.render_pixel(x as f32 + 0.5, y as f32 + 0.5, settings));
+            }
+        }
+        result
+    }
+}
+
+/// Converts linear colors from `CpuRender::render` to 8-bit RGBA bytes.
+pub fn to_rgba8(pixels: &[Vec3]) -> Vec<u8> {
+    let to_byte = |x: f32| (clamp(x, 0., 1.) * 255.).round() as u8;
+    pixels
+        .iter()
+        .flat_map(|c| vec![to_byte(c.x), to_byte(c.y), to_byte(c.z), 255])
+        .collect()
+}
+
+fn sqr(a: f32) -> f32 {
+    a * a
+}
+
+fn clamp(x: f32, min: f32, max: f32) -> f32 {
+    x.max(min).min(max)
+}
+
+fn between(a: f32, x: f32, b: f32) -> bool {
+    a <= x && x <= b
+}
+
+/// GLSL `sign`, unlike `f32::signum` it returns 0 for 0.
+fn sign(x: f32) -> f32 {
+    if x > 0. {
+        1.
+    } else if x < 0. {
+        -1.
+    } else {
+        0.
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+fn normalize_normal(normal: Vec3, dir: Vec3) -> Vec3 {
+    let normal = normal.normalize();
+    if normal.dot(dir) > 0. {
+        -normal
+    } else {
+        normal
+    }
+}
+
+fn is_collinear(a: Vec3, b: Vec3) -> bool {
+    (a.dot(b) / (a.length() * b.length()) - 1.).abs() < 0.01
+}
+
+fn my_reflect(dir: Vec3, normal: Vec3) -> Vec3 {
+    dir - normal * dir.dot(normal) / normal.dot(normal) * 2.
+}
+
+fn my_refract(dir: Vec3, mut normal: Vec3, refractive_index: f32) -> Vec3 {
+    let mut ri = refractive_index;
+    let from_outside = normal.dot(dir) > 0.;
+    if !from_outside {
+        ri = 1. / ri;
+    } else {
+        normal = -normal;
+    }
+
+    let dir = dir.normalize();
+    let c = -normal.dot(dir);
+    let d = 1.0 - ri * ri * (1.0 - c * c);
+    if d > 0. {
+        dir * ri + normal * (ri * c - d.sqrt())
+    } else {
+        my_reflect(dir, normal)
+    }
+}
+
+fn transform(matrix: Mat4, r: Ray) -> Ray {
+    Ray {
+        o: matrix * r.o,
+        d: matrix * r.d,
+    }
+}
+
+fn get_normal(matrix: Mat4) -> Vec3 {
+    matrix.z_axis.truncate()
+}
+
+fn plane_intersect(r: Ray, plane_inv: Mat4, normal: Vec3) -> SurfaceIntersection {
+    let normal = normalize_normal(normal, r.d.truncate());
+    let mut r = transform(plane_inv, r);
+    let len = r.d.length();
+    r.d = r.d.normalize();
+
+    let t = -r.o.z / r.d.z;
+    if t < 0. {
+        INTERSECTION_NONE
+    } else {
+        let pos = r.o + r.d * t;
+        SurfaceIntersection {
+            hit: true,
+            t: t / len,
+            u: pos.x,
+            v: pos.y,
+            n: normal,
+        }
+    }
+}
+
+fn color(r: f32, g: f32, b: f32) -> Vec3 {
+    Vec3::new(r * r, g * g, b * b)
+}
+
+fn color_normal(normal: Vec3, direction: Vec4) -> f32 {
+    direction
+        .truncate()
+        .normalize()
+        .dot(normal.normalize())
+        .abs()
+}
+
+#[allow(clippy::approx_constant)]
+fn color_grid(start: Vec3, uv: Vec2) -> Vec3 {
+    let uv = uv / 8. - Vec2::new(0.125, 0.125);
+    let fr = 3.14159 * 8.0;
+    let mut col = start;
+    col += Vec3::splat(
+        0.4 * smoothstep(
+            -0.01,
+            0.01,
+            (uv.x * fr * 0.5).cos() * (uv.y * fr * 0.5).cos(),
+        ),
+    );
+    let wi =
+        smoothstep(-1.0, -0.98, (uv.x * fr).cos()) * smoothstep(-1.0, -0.98, (uv.y * fr).cos());
+    col *= wi;
+
+    col
+}
+
+fn color_add_weighted(a: Vec3, b: Vec3, coef: f32) -> Vec3 {
+    a * (1.0 - coef) + b * coef
+}
+
+fn material_final(color: Vec3) -> MaterialProcessing {
+    MaterialProcessing {
+        is_final: true,
+        mul_to_color: color,
+        new_ray: RAY_NONE,
+    }
+}
+
+fn material_next(mul_color: Vec3, new_ray: Ray) -> MaterialProcessing {
+    MaterialProcessing {
+        is_final: false,
+        mul_to_color: mul_color,
+        new_ray,
+    }
+}
+
+fn material_simple(
+    hit: SurfaceIntersection,
+    r: Ray,
+    color: Vec3,
+    normal_coef: f32,
+    grid: bool,
+    grid_scale: f32,
+    grid_coef: f32,
+) -> MaterialProcessing {
+    let mut color = color_add_weighted(color, color * color_normal(hit.n, r.d), normal_coef);
+    if grid {
+        color = color_add_weighted(
+            color,
+            color_grid(color, Vec2::new(hit.u, hit.v) * grid_scale),
+            grid_coef,
+        );
+    }
+    material_final(color)
+}
+
+fn material_reflect(
+    hit: SurfaceIntersection,
+    mut r: Ray,
+    add_to_color: Vec3,
+    offset_after_material: f32,
+) -> MaterialProcessing {
+    r.d = my_reflect(r.d.truncate(), hit.n).extend(0.);
+    r.o += r.d * offset_after_material;
+    material_next(add_to_color, r)
+}
+
+fn material_refract(
+    hit: SurfaceIntersection,
+    mut r: Ray,
+    add_to_color: Vec3,
+    refractive_index: f32,
+    offset_after_material: f32,
+) -> MaterialProcessing {
+    r.d = my_refract(r.d.truncate(), hit.n, refractive_index).extend(0.);
+    r.o += r.d * offset_after_material;
+    material_next(add_to_color, r)
+}
+
+fn material_teleport(
+    _hit: SurfaceIntersection,
+    mut r: Ray,
+    teleport_matrix: Mat4,
+    offset_after_material: f32,
+) -> MaterialProcessing {
+    r.o += r.d * offset_after_material;
+    r = transform(teleport_matrix, r);
+    r.d = r.d.normalize();
+    material_next(Vec3::ONE, r)
+}
+
+fn nearer(result: &SurfaceIntersection, current: &SurfaceIntersection) -> bool {
+    current.hit && (current.t > 0.) && (!result.hit || current.t < result.t)
+}
+
+fn cap_normal(pos: Vec3, a: Vec3, b: Vec3, radius: f32) -> Vec3 {
+    let ba = b - a;
+    let pa = pos - a;
+    let h = clamp(pa.dot(ba) / ba.dot(ba), 0.0, 1.0);
+    (pa - h * ba) / radius
+}
+
+fn cap(r: Ray, pa: Vec3, pb: Vec3, radius: f32) -> SurfaceIntersection {
+    let ro = r.o.truncate();
+    let rd = r.d.truncate();
+    let ba = pb - pa;
+    let oa = ro - pa;
+
+    let baba = ba.dot(ba);
+    let bard = ba.dot(rd);
+    let baoa = ba.dot(oa);
+    let rdoa = rd.dot(oa);
+    let oaoa = oa.dot(oa);
+
+    let a = baba - bard * bard;
+    let mut b = baba * rdoa - baoa * bard;
+    let mut c = baba * oaoa - baoa * baoa - radius * radius * baba;
+    let mut h = b * b - a * c;
+    if h >= 0.0 {
+        let mut t = (-b - h.sqrt()) / a;
+        let y = baoa + t * bard;
+        // body
+        if y > 0.0 && y < baba {
+            let pos = ro + rd * t;
+            return SurfaceIntersection {
+                hit: true,
+                t,
+                u: 0.,
+                v: 0.,
+                n: cap_normal(pos, pa, pb, radius),
+            };
+        }
+        // caps
+        let oc = if y <= 0.0 { oa } else { ro - pb };
+        b = rd.dot(oc);
+        c = oc.dot(oc) - radius * radius;
+        h = b * b - c;
+        if h > 0.0 {
+            t = -b - h.sqrt();
+            let pos = ro + rd * t;
+            return SurfaceIntersection {
+                hit: true,
+                t,
+                u: 0.,
+                v: 0.,
+                n: cap_normal(pos, pa, pb, radius),
+            };
+        }
+    }
+    INTERSECTION_NONE
+}
+
+fn debug_intersect(r: Ray) -> SceneIntersection {
+    let pa = Vec3::ZERO;
+    let radius = 0.03;
+
+    let mut i = SCENE_INTERSECTION_NONE;
+
+    for (to, material) in [
+        (Vec3::new(1., 0., 0.), DEBUG_RED),
+        (Vec3::new(0., 1., 0.), DEBUG_GREEN),
+        (Vec3::new(0., 0., 1.), DEBUG_BLUE),
+    ]
+    .iter()
+    {
+        let hit = cap(r, pa, *to, radius);
+        if nearer(&i.hit, &hit) {
+            i.material = *material;
+            i.hit = hit;
+        }
+    }
+
+    i
+}
+
+/// Mirrors `SurfaceIntersection(true, t, u, v, n)` constructor.
+fn surface_hit(t: f32, u: f32, v: f32, n: Vec3) -> SurfaceIntersection {
+    SurfaceIntersection {
+        hit: true,
+        t,
+        u,
+        v,
+        n,
+    }
+}
+
+fn sphere_intersect(r: Ray, radius: f32) -> SurfaceIntersection {
+    let o = r.o.truncate();
+    let d = r.d.truncate();
+    let b = o.dot(d);
+    let mut h = b * b - o.dot(o) + radius * radius;
+    if h < 0. {
+        return INTERSECTION_NONE;
+    }
+    h = h.sqrt();
+    let mut t = -b - h;
+    if t < 0. {
+        t = -b + h;
+    }
+    if t < 0. {
+        return INTERSECTION_NONE;
+    }
+    let pos = o + d * t;
+    let u = pos.y.atan2(pos.x);
+    let v = Vec2::new(pos.x, pos.y).length().atan2(pos.z);
+    surface_hit(t, u, v, pos.normalize())
+}
+
+fn box_intersect(r: Ray, size: Vec3) -> SurfaceIntersection {
+    let inv = Vec3::ONE / r.d.truncate();
+    let t1 = (-size - r.o.truncate()) * inv;
+    let t2 = (size - r.o.truncate()) * inv;
+    let t_min = t1.min(t2);
+    let t_max = t1.max(t2);
+    let near = t_min.x.max(t_min.y).max(t_min.z);
+    let far = t_max.x.min(t_max.y).min(t_max.z);
+    if far < near || far < 0. {
+        return INTERSECTION_NONE;
+    }
+    let t = if near < 0. { far } else { near };
+    let pos = r.o.truncate() + r.d.truncate() * t;
+    let p = (pos / size).abs();
+    if p.x >= p.y && p.x >= p.z {
+        surface_hit(t, pos.y, pos.z, Vec3::new(sign(pos.x), 0., 0.))
+    } else if p.y >= p.z {
+        surface_hit(t, pos.x, pos.z, Vec3::new(0., sign(pos.y), 0.))
+    } else {
+        surface_hit(t, pos.x, pos.y, Vec3::new(0., 0., sign(pos.z)))
+    }
+}
+
+fn disk_intersect(r: Ray, radius: f32) -> SurfaceIntersection {
+    let t = -r.o.z / r.d.z;
+    let pos = r.o + r.d * t;
+    if t < 0. || sqr(pos.x) + sqr(pos.y) > radius * radius {
+        return INTERSECTION_NONE;
+    }
+    surface_hit(t, pos.x, pos.y, Vec3::Z)
+}
+
+fn cylinder_side(r: Ray, t: f32, height: f32) -> SurfaceIntersection {
+    let pos = r.o + r.d * t;
+    if t < 0. || !between(0., pos.z, height) {
+        return INTERSECTION_NONE;
+    }
+    let n = Vec3::new(pos.x, pos.y, 0.).normalize();
+    surface_hit(t, pos.y.atan2(pos.x), pos.z, n)
+}
+
+fn cylinder_intersect(r: Ray, radius: f32, height: f32) -> SurfaceIntersection {
+    let mut result = INTERSECTION_NONE;
+    let mut hit = disk_intersect(r, radius);
+    hit.n = -hit.n;
+    if nearer(&result, &hit) {
+        result = hit;
+    }
+    let top = Ray {
+        o: r.o - Vec4::new(0., 0., height, 0.),
+        d: r.d,
+    };
+    hit = disk_intersect(top, radius);
+    if nearer(&result, &hit) {
+        result = hit;
+    }
+
+    let o = Vec2::new(r.o.x, r.o.y);
+    let d = Vec2::new(r.d.x, r.d.y);
+    let a = d.dot(d);
+    let b = o.dot(d);
+    let mut h = b * b - a * (o.dot(o) - radius * radius);
+    if a > 0. && h >= 0. {
+        h = h.sqrt();
+        for t in [(-b - h) / a, (-b + h) / a].iter() {
+            hit = cylinder_side(r, *t, height);
+            if nearer(&result, &hit) {
+                result = hit;
+            }
+        }
+    }
+    result
+}
+
+fn cone_side(r: Ray, t: f32, radius: f32, height: f32) -> SurfaceIntersection {
+    let pos = r.o + r.d * t;
+    if t < 0. || !between(0., pos.z, height) {
+        return INTERSECTION_NONE;
+    }
+    let n = Vec3::new(pos.x, pos.y, sqr(radius / height) * (height - pos.z)).normalize();
+    surface_hit(t, pos.y.atan2(pos.x), pos.z, n)
+}
+
+fn cone_intersect(r: Ray, radius: f32, height: f32) -> SurfaceIntersection {
+    let mut result = INTERSECTION_NONE;
+    let mut hit = disk_intersect(r, radius);
+    hit.n = -hit.n;
+    if nearer(&result, &hit) {
+        result = hit;
+    }
+
+    let k = sqr(radius / height);
+    let o = Vec3::new(r.o.x, r.o.y, height - r.o.z);
+    let d = Vec3::new(r.d.x, r.d.y, -r.d.z);
+    let a = sqr(d.x) + sqr(d.y) - k * d.z * d.z;
+    let b = o.x * d.x + o.y * d.y - k * o.z * d.z;
+    let c = sqr(o.x) + sqr(o.y) - k * o.z * o.z;
+    let mut h = b * b - a * c;
+    if a != 0. && h >= 0. {
+        h = h.sqrt();
+        for t in [(-b - h) / a, (-b + h) / a].iter() {
+            hit = cone_side(r, *t, radius, height);
+            if nearer(&result, &hit) {
+                result = hit;
+            }
+        }
+    }
+    result
+}
+
+#[allow(non_snake_case, clippy::many_single_char_names)]
+fn torus_distance(r: Ray, major_radius: f32, minor_radius: f32) -> f32 {
+    let ro = r.o.truncate();
+    let rd = r.d.truncate();
+    let mut po = 1.;
+    let Ra2 = major_radius * major_radius;
+    let ra2 = minor_radius * minor_radius;
+    let m = ro.dot(ro);
+    let n = ro.dot(rd);
+
+    // Bounding sphere.
+    let mut h = n * n - m + sqr(major_radius + minor_radius);
+    if h < 0. {
+        return -1.;
+    }
+
+    // Quartic equation.
+    let k = (m - ra2 - Ra2) / 2.;
+    let mut k3 = n;
+    let mut k2 = n * n + Ra2 * rd.z * rd.z + k;
+    let mut k1 = k * n + Ra2 * ro.z * rd.z;
+    let mut k0 = k * k + Ra2 * ro.z * ro.z - Ra2 * ra2;
+
+    // Prevent |c1| from being too close to zero.
+    if (k3 * (k3 * k3 - k2) + k1).abs() < 0.01 {
+        po = -1.;
+        std::mem::swap(&mut k1, &mut k3);
+        k0 = 1. / k0;
+        k1 *= k0;
+        k2 *= k0;
+        k3 *= k0;
+    }
+
+    let mut c2 = 2. * k2 - 3. * k3 * k3;
+    let mut c1 = k3 * (k3 * k3 - k2) + k1;
+    let mut c0 = k3 * (k3 * (-3. * k3 * k3 + 4. * k2) - 8. * k1) + 4. * k0;
+    c2 /= 3.;
+    c1 *= 2.;
+    c0 /= 3.;
+
+    let Q = c2 * c2 + c0;
+    let R = 3. * c0 * c2 - c2 * c2 * c2 - c1 * c1;
+    h = R * R - Q * Q * Q;
+    let mut z = if h < 0. {
+        // 4 intersections.
+        let sQ = Q.sqrt();
+        2. * sQ * ((R / (sQ * Q)).acos() / 3.).cos()
+    } else {
+        // 2 intersections.
+        let sQ = (h.sqrt() + R.abs()).powf(1. / 3.);
+        sign(R) * (sQ + Q / sQ).abs()
+    };
+    z = c2 - z;
+
+    let mut d1 = z - 3. * c2;
+    let mut d2 = z * z - 3. * c0;
+    if d1.abs() < 1.0e-4 {
+        if d2 < 0. {
+            return -1.;
+        }
+        d2 = d2.sqrt();
+    } else {
+        if d1 < 0. {
+            return -1.;
+        }
+        d1 = (d1 / 2.).sqrt();
+        d2 = c1 / d1;
+    }
+
+    let mut result = 1e20_f32;
+    for (d1, d2) in [(-d1, d2), (d1, -d2)].iter() {
+        h = d1 * d1 - z + d2;
+        if h > 0. {
+            h = h.sqrt();
+            for t in [d1 - h - k3, d1 + h - k3].iter() {
+                let t = if po < 0. { 2. / t } else { *t };
+                if t > 0. {
+                    result = result.min(t);
+                }
+            }
+        }
+    }
+    if result < 1e20 {
+        result
+    } else {
+        -1.
+    }
+}
+
+fn torus_intersect(r: Ray, major_radius: f32, minor_radius: f32) -> SurfaceIntersection {
+    let t = torus_distance(r, major_radius, minor_radius);
+    if t < 0. {
+        return INTERSECTION_NONE;
+    }
+    let pos = r.o.truncate() + r.d.truncate() * t;
+    let n = pos
+        * (Vec3::splat(pos.dot(pos) - minor_radius * minor_radius)
+            - major_radius * major_radius * Vec3::new(1., 1., -1.));
+    let xy = Vec2::new(pos.x, pos.y).length();
+    surface_hit(
+        t,
+        pos.y.atan2(pos.x),
+        pos.z.atan2(xy - major_radius),
+        n.normalize(),
+    )
+}
+
+/// Calls GLSL function from `Primitive::intersect_code` with values of parameters.
+fn primitive_intersect(shape: &Primitive, params: &[f32], r: Ray) -> SurfaceIntersection {
+    use Primitive::*;
+    match shape {
+        Sphere { .. } => sphere_intersect(r, params[0]),
+        Box { .. } => box_intersect(r, Vec3::new(params[0], params[1], params[2])),
+        Cylinder { .. } => cylinder_intersect(r, params[0], params[1]),
+        Cone { .. } => cone_intersect(r, params[0], params[1]),
+        Torus { .. } => torus_intersect(r, params[0], params[1]),
+        Disk { .. } => disk_intersect(r, params[0]),
+    }
+}
+
+fn process_plane_intersection(
+    mut i: SceneIntersection,
+    hit: SurfaceIntersection,
+    inside: i32,
+) -> SceneIntersection {
+    if inside == NOT_INSIDE {
+        // Not inside, do nothing
+    } else if inside == TELEPORT {
+        // This is wrong code, do nothing
+    } else {
+        i.hit = hit;
+        i.material = inside;
+    }
+    i
+}
+
+fn process_portal_intersection(
+    mut i: SceneIntersection,
+    hit: SurfaceIntersection,
+    inside: i32,
+    teleport_material: i32,
+) -> SceneIntersection {
+    if inside == NOT_INSIDE {
+        // Not inside, do nothing
+    } else if inside == TELEPORT {
+        i.hit = hit;
+        i.material = teleport_material;
+    } else {
+        i.hit = hit;
+        i.material = inside;
+    }
+    i
+}
+
+// Thanks https://www.shadertoy.com/view/Wt3fzB
+fn panini_projection(mut tc: Vec2, fov: f32, d: f32) -> Vec3 {
+    let d2 = d * d;
+
+    {
+        let fo = std::f32::consts::FRAC_PI_2 - fov * 0.5;
+
+        let f = fo.cos() / fo.sin();
+        let f2 = f * f;
+
+        let b =
+            ((sqr(d + d2) * (f2 + f2 * f2)).max(0.0).sqrt() - (d * f + f)) / (d2 + d2 * f2 - 1.0);
+
+        tc *= b;
+    }
+
+    let h = tc.x;
+    let v = tc.y;
+
+    let h2 = h * h;
+
+    let k = h2 / sqr(d + 1.0);
+    let k2 = k * k;
+
+    let discr = (k2 * d2 - (k + 1.0) * (k * d2 - 1.0)).max(0.0);
+
+    let cos_phi = (-k * d + discr.sqrt()) / (k + 1.0);
+    let s = (d + 1.0) / (d + cos_phi);
+    let tan_theta = v / s;
+
+    let mut sin_phi = (1.0 - sqr(cos_phi)).max(0.0).sqrt();
+    if tc.x < 0.0 {
+        sin_phi *= -1.0;
+    }
+
+    let s = 1. / (1.0 + sqr(tan_theta)).sqrt();
+
+    Vec3::new(sin_phi, tan_theta, cos_phi) * s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::common::Data;
+    use crate::gui::glsl::{GlslCode, IsInsideCode};
+    use crate::gui::material::MaterialComboBox;
+    use crate::gui::matrix::{Matrix, MatrixComboBox};
+    use crate::gui::object::{MaterialName, ObjectComboBox};
+    use crate::gui::uniform::*;
+    use crate::scene_files::embedded_scene;
+
+    fn load(mut scene: Scene) -> (Scene, Data) {
+        let mut data = Data::default();
+        scene.init(&mut data);
+        (scene, data)
+    }
+
+    /// Empty scene without debug object, and with red material.
+    fn red_scene() -> Scene {
+        let mut scene = embedded_scene("empty").unwrap();
+        scene.objects.remove(0);
+        let red = Material::Simple {
+            color: [1., 0., 0.],
+            normal_coef: 0.,
+            grid: false,
+            grid_scale: 1.,
+            grid_coef: 0.,
+        };
+        scene.materials.add("red".to_owned(), MaterialComboBox(red));
+        scene
+    }
+
+    fn add_matrix(scene: &mut Scene, name: &str, offset: Vec3) {
+        let matrix = Matrix::Simple {
+            offset,
+            scale: 1.,
+            rotate: Vec3::ZERO,
+            mirror: (false, false, false),
+        };
+        scene.matrices.add(name.to_owned(), MatrixComboBox(matrix));
+    }
+
+    fn ray(o: Vec3, d: Vec3) -> Ray {
+        Ray {
+            o: o.extend(1.),
+            d: d.normalize().extend(0.),
+        }
+    }
+
+    #[test]
+    fn debug_matrix() {
+        let (scene, data) = load(embedded_scene("empty").unwrap());
+        let render = CpuRender::new(&scene, &data.formulas_cache);
+        assert!(render.unsupported().is_empty());
+
+        let i = render.scene_intersect(ray(Vec3::new(0.5, 0., -3.), Vec3::new(0., 0., 1.)));
+        assert_eq!(i.material, DEBUG_RED);
+        assert!((i.hit.t - 2.97).abs() < 1e-3);
+
+        let i = render.scene_intersect(ray(Vec3::new(0., 0.5, -3.), Vec3::new(0., 0., 1.)));
+        assert_eq!(i.material, DEBUG_GREEN);
+
+        let i = render.scene_intersect(ray(Vec3::new(0., 0., -3.), Vec3::new(0., 1., 0.)));
+        assert!(!i.hit.hit);
+
+        let settings = RenderSettings::default();
+        let background = render.ray_tracing(
+            ray(Vec3::new(0., 0., -3.), Vec3::new(0., 1., 0.)),
+            &settings,
+        );
+        assert_eq!(background, color(0.6, 0.6, 0.6));
+    }
+
+    #[test]
+    fn flat_portal() {
+        let mut scene = red_scene();
+        add_matrix(&mut scene, "a", Vec3::ZERO);
+        add_matrix(&mut scene, "b", Vec3::new(10., 0., 0.));
+        add_matrix(&mut scene, "wall", Vec3::new(10., 0., 5.));
+        let flat = |kind, code: &str| {
+            ObjectComboBox(Object::Flat {
+                kind,
+                is_inside: IsInsideCode(GlslCode(code.to_owned())),
+            })
+        };
+        let portal = ObjectType::Portal(MatrixName("a".to_owned()), MatrixName("b".to_owned()));
+        let wall = ObjectType::Simple(MatrixName("wall".to_owned()));
+        scene
+            .objects
+            .add("portal".to_owned(), flat(portal, "return TELEPORT;"));
+        scene
+            .objects
+            .add("wall".to_owned(), flat(wall, "return red_M;"));
+        let (scene, data) = load(scene);
+        let mut render = CpuRender::new(&scene, &data.formulas_cache);
+        assert_eq!(
+            render.unsupported(),
+            vec![
+                Unsupported::IsInside {
+                    object: "portal".to_owned()
+                },
+                Unsupported::IsInside {
+                    object: "wall".to_owned()
+                },
+            ]
+        );
+
+        let red = render.material("red").unwrap();
+        render.set_is_inside("portal", |args| {
+            if args.x.abs() < 1. && args.y.abs() < 1. {
+                TELEPORT
+            } else {
+                NOT_INSIDE
+            }
+        });
+        render.set_is_inside("wall", move |args| {
+            if args.x.abs() < 1. && args.y.abs() < 1. {
+                red
+            } else {
+                NOT_INSIDE
+            }
+        });
+        assert!(render.unsupported().is_empty());
+
+        let settings = RenderSettings::default();
+
+        // Through the portal ray gets to the wall near the second portal.
+        let through = ray(Vec3::new(0., 0., -1.), Vec3::new(0., 0., 1.));
+        assert_eq!(render.scene_intersect(through).material, red + 1);
+        assert_eq!(
+            render.ray_tracing(through, &settings),
+            Vec3::new(1., 0., 0.)
+        );
+
+        // Without portal the wall is not visible.
+        let around = ray(Vec3::new(2., 0., -1.), Vec3::new(0., 0., 1.));
+        assert!(!render.scene_intersect(around).hit.hit);
+    }
+
+    #[test]
+    fn primitives() {
+        let mut scene = red_scene();
+        let size = AnyUniform::Float {
+            min: Some(0.),
+            max: None,
+            value: 0.5,
+        };
+        scene
+            .uniforms
+            .add("size".to_owned(), AnyUniformComboBox(size));
+        let shapes = vec![
+            Primitive::Sphere {
+                radius: ParametrizeOrNot::Yes(FormulaName("size".to_owned())),
+            },
+            Primitive::Box {
+                size: TVec3 {
+                    x: ParametrizeOrNot::No(1.),
+                    y: ParametrizeOrNot::No(2.),
+                    z: ParametrizeOrNot::No(0.5),
+                },
+            },
+            Primitive::Cylinder {
+                radius: ParametrizeOrNot::No(1.),
+                height: ParametrizeOrNot::No(2.),
+            },
+            Primitive::Cone {
+                radius: ParametrizeOrNot::No(1.),
+                height: ParametrizeOrNot::No(2.),
+            },
+            Primitive::Torus {
+                major_radius: ParametrizeOrNot::No(1.),
+                minor_radius: ParametrizeOrNot::No(0.25),
+            },
+            Primitive::Disk {
+                radius: ParametrizeOrNot::No(1.),
+            },
+        ];
+        for (i, shape) in shapes.into_iter().enumerate() {
+            let name = format!("primitive_{}", i);
+            add_matrix(&mut scene, &name, Vec3::new(10. * i as f32, 0., 0.));
+            let object = Object::Primitive {
+                matrix: MatrixName(name.clone()),
+                shape,
+                material: MaterialName("red".to_owned()),
+            };
+            scene.objects.add(name, ObjectComboBox(object));
+        }
+        let (scene, data) = load(scene);
+        let render = CpuRender::new(&scene, &data.formulas_cache);
+        assert!(render.unsupported().is_empty());
+        let red = render.material("red").unwrap();
+
+        let check = |o: Vec3, d: Vec3, t: f32, n: Vec3| {
+            let i = render.scene_intersect(ray(o, d));
+            assert_eq!(i.material, red, "{:?} {:?}", o, d);
+            assert!((i.hit.t - t).abs() < 1e-3, "{:?} {:?}: {:?}", o, d, i.hit);
+            assert!(
+                (i.hit.n - n.normalize()).length() < 1e-3,
+                "{:?} {:?}: {:?}",
+                o,
+                d,
+                i.hit
+            );
+        };
+        let miss = |o: Vec3, d: Vec3| {
+            let i = render.scene_intersect(ray(o, d));
+            assert!(!i.hit.hit, "{:?} {:?}: {:?}", o, d, i.hit);
+        };
+
+        // Radius of sphere is taken from uniform, ray from inside hits the far side.
+        check(Vec3::new(0., 0., -5.), Vec3::Z, 4.5, -Vec3::Z);
+        check(Vec3::ZERO, Vec3::Z, 0.5, Vec3::Z);
+        miss(Vec3::new(0.6, 0., -5.), Vec3::Z);
+
+        check(Vec3::new(10., 1.5, -5.), Vec3::Z, 4.5, -Vec3::Z);
+        check(Vec3::new(15., 0., 0.), -Vec3::X, 4., Vec3::X);
+        miss(Vec3::new(10., 2.5, -5.), Vec3::Z);
+
+        check(Vec3::new(20., 0., -5.), Vec3::Z, 5., -Vec3::Z);
+        check(Vec3::new(20., 0., 5.), -Vec3::Z, 3., Vec3::Z);
+        check(Vec3::new(15., 0., 1.), Vec3::X, 4., -Vec3::X);
+
+        check(Vec3::new(30.5, 0., -5.), Vec3::Z, 5., -Vec3::Z);
+        check(
+            Vec3::new(25., 0., 1.),
+            Vec3::X,
+            4.5,
+            Vec3::new(-0.5, 0., 0.25),
+        );
+        miss(Vec3::new(25., 0., 2.5), Vec3::X);
+
+        check(Vec3::new(35., 0., 0.), Vec3::X, 3.75, -Vec3::X);
+        check(Vec3::new(41., 0., -5.), Vec3::Z, 4.75, -Vec3::Z);
+        check(Vec3::new(41., 0., 0.), Vec3::Z, 0.25, Vec3::Z);
+        miss(Vec3::new(40., 0., -5.), Vec3::Z);
+
+        check(Vec3::new(50.5, 0., -5.), Vec3::Z, 5., Vec3::Z);
+        miss(Vec3::new(51.5, 0., -5.), Vec3::Z);
+
+        // Oblique rays hit surface of torus, the last one is directed to the top of tube.
+        for (o, d) in [
+            (Vec3::new(35., 0.3, 0.1), Vec3::new(1., 0.05, 0.02)),
+            (Vec3::new(40.2, -3., 2.), Vec3::new(0.1, 1., -0.6)),
+            (Vec3::new(42., 3., 3.), Vec3::new(-1.4, -2.2, -2.75)),
+        ]
+        .iter()
+        {
+            let i = render.scene_intersect(ray(*o, *d));
+            assert!(i.hit.hit, "{:?} {:?}", o, d);
+            let pos = *o + d.normalize() * i.hit.t - Vec3::new(40., 0., 0.);
+            let distance = sqr(Vec2::new(pos.x, pos.y).length() - 1.) + sqr(pos.z);
+            assert!(
+                (distance - sqr(0.25)).abs() < 1e-3,
+                "{:?} {:?}: {:?}",
+                o,
+                d,
+                pos
+            );
+        }
+    }
+
+    #[test]
+    fn unsupported_code() {
+        let (scene, data) = load(embedded_scene("room").unwrap());
+        let render = CpuRender::new(&scene, &data.formulas_cache);
+        let unsupported = render.unsupported();
+        assert!(unsupported.contains(&Unsupported::IsInside {
+            object: "rx1".to_owned()
+        }));
+        assert!(unsupported.contains(&Unsupported::Material {
+            material: "room_green_texture".to_owned()
+        }));
+        assert!(!unsupported.contains(&Unsupported::Material {
+            material: "room_green".to_owned()
+        }));
+    }
+}
@@ -0,0 +1,327 @@
+//! Removes unused parts of GLSL library. Code is split into top-level items, and item is kept only when something it defines is used by other code.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Top-level GLSL item: function, struct, declaration or preprocessor line, together with comments before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item<'a> {
+    pub text: &'a str,
+    pub defines: Vec<&'a str>,
+    pub uses: BTreeSet<&'a str>,
+
+    /// Items like `#version` or `precision` that don't define anything, but are needed anyway.
+    pub always: bool,
+}
+
+/// Replaces comments by spaces, so positions in code remain the same.
+pub fn strip_comments(code: &str) -> String {
+    let mut result = String::with_capacity(code.len());
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                result.push(' ');
+                while let Some(c) = chars.peek() {
+                    if *c == '\n' {
+                        break;
+                    }
+                    result.push(' ');
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                result.push(' ');
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    result.push(if c == '\n' { '\n' } else { ' ' });
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Identifiers with their byte positions, numbers like `1e10` are skipped.
+fn tokens(code: &str) -> Vec<(usize, &str)> {
+    let mut result = Vec::new();
+    let mut chars = code.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let mut end = start + c.len_utf8();
+            while let Some((pos, c)) = chars.peek() {
+                if c.is_ascii_alphanumeric() || *c == '_' {
+                    end = pos + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if !c.is_ascii_digit() {
+                result.push((start, &code[start..end]));
+            }
+        }
+    }
+    result
+}
+
+/// Identifiers that are used in code, without comments.
+pub fn identifiers(code: &str) -> BTreeSet<String> {
+    tokens(&strip_comments(code))
+        .into_iter()
+        .map(|(_, x)| x.to_owned())
+        .collect()
+}
+
+fn item<'a>(text: &'a str) -> Item<'a> {
+    let stripped = strip_comments(text);
+    let tokens = tokens(&stripped);
+    let word = |pos: usize| tokens.get(pos).map(|(_, x)| *x);
+    let original = |(pos, x): (usize, &str)| &text[pos..pos + x.len()];
+
+    let mut defines = Vec::new();
+    let mut always = false;
+    if stripped.trim_start().starts_with('#') {
+        if word(0) == Some("define") {
+            defines.extend(tokens.get(1).copied().map(original));
+        } else {
+            always = true;
+        }
+    } else if word(0) == Some("precision") {
+        always = true;
+    } else if word(0) == Some("struct") {
+        defines.extend(tokens.get(1).copied().map(original));
+    } else if let Some(end) = stripped.find(&['(', '=', ';', '[', '{'][..]) {
+        defines.extend(
+            tokens
+                .iter()
+                .copied()
+                .take_while(|(pos, _)| *pos < end)
+                .last()
+                .map(original),
+        );
+    }
+    always |= defines.is_empty() && !tokens.is_empty();
+
+    let uses = tokens
+        .iter()
+        .copied()
+        .map(original)
+        .filter(|x| !defines.contains(x))
+        .collect();
+    Item {
+        text,
+        defines,
+        uses,
+        always,
+    }
+}
+
+/// End of item after `end`: spaces and comment until the end of line are included.
+fn end_of_line(code: &str, end: usize) -> usize {
+    let rest = &code[end..];
+    let line_end = rest.find('\n').map(|x| x + 1).unwrap_or(rest.len());
+    let line = &rest[..line_end];
+    if line.trim().is_empty() || line.trim_start().starts_with("//") {
+        end + line_end
+    } else {
+        end
+    }
+}
+
+/// Splits code into top-level items. Text after the last item, that contains only comments, is dropped.
+pub fn split_items(code: &str) -> Vec<Item<'_>> {
+    let stripped = strip_comments(code);
+    let bytes = stripped.as_bytes();
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut has_code = false;
+    let mut depth = 0usize;
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let mut end = None;
+        match bytes[pos] {
+            b'#' if depth == 0 && !has_code => {
+                // Preprocessor line can be continued by `\` at the end.
+                let mut line_end = pos;
+                loop {
+                    line_end += stripped[line_end..]
+                        .find('\n')
+                        .unwrap_or(stripped.len() - line_end);
+                    if line_end >= stripped.len() || !stripped[..line_end].ends_with('\\') {
+                        break;
+                    }
+                    line_end += 1;
+                }
+                end = Some(line_end);
+            }
+            b'{' => {
+                depth += 1;
+                has_code = true;
+            }
+            b'}' => {
+                depth = depth.saturating_sub(1);
+                let is_struct =
+                    tokens(&stripped[start..pos]).first().map(|(_, x)| *x) == Some("struct");
+                if depth == 0 && !is_struct {
+                    end = Some(pos + 1);
+                }
+            }
+            b';' if depth == 0 => end = Some(pos + 1),
+            c if !c.is_ascii_whitespace() => has_code = true,
+            _ => {}
+        }
+        match end {
+            Some(end) => {
+                let end = end_of_line(code, end);
+                result.push(item(&code[start..end]));
+                start = end;
+                pos = end;
+                has_code = false;
+            }
+            None => pos += 1,
+        }
+    }
+    result
+}
+
+/// Returns which groups of items are needed for code that uses `roots` identifiers. Group is kept or removed as a whole.
+pub fn reachable(groups: &[&[Item]], roots: &BTreeSet<String>) -> Vec<bool> {
+    let mut defined_in: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (pos, group) in groups.iter().enumerate() {
+        for name in group.iter().flat_map(|item| &item.defines) {
+            defined_in.entry(name).or_default().push(pos);
+        }
+    }
+
+    let mut result = vec![false; groups.len()];
+    let mut queue: Vec<usize> = groups
+        .iter()
+        .enumerate()
+        .filter(|(_, group)| group.iter().any(|item| item.always))
+        .map(|(pos, _)| pos)
+        .collect();
+    queue.extend(
+        roots
+            .iter()
+            .filter_map(|name| defined_in.get(&name[..]))
+            .flatten(),
+    );
+    while let Some(pos) = queue.pop() {
+        if result[pos] {
+            continue;
+        }
+        result[pos] = true;
+        for name in groups[pos].iter().flat_map(|item| &item.uses) {
+            queue.extend(defined_in.get(name).into_iter().flatten());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "#version 100
+precision highp float;
+
+#define PI acos(-1.)
+
+// Ray with origin and direction.
+struct Ray
+{
+    vec4 o; // origin
+    vec4 d;
+};
+
+const Ray ray_none = Ray(vec4(0.), vec4(1e10));
+uniform float _offset; // offset
+
+/* Multiline
+   comment. */
+float sqr(float a) {
+    if (a > 0.) { return a * a; }
+    return a * a;
+}
+
+float unused(float a) { return sqr(a) * PI; }
+vec4 start(Ray r) {
+    return r.o + r.d * _offset;
+}
+// end
+";
+
+    #[test]
+    fn items() {
+        let items = split_items(CODE);
+        let defines = items
+            .iter()
+            .map(|x| (x.defines.clone(), x.always))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            defines,
+            vec![
+                (vec![], true),
+                (vec![], true),
+                (vec!["PI"], false),
+                (vec!["Ray"], false),
+                (vec!["ray_none"], false),
+                (vec!["_offset"], false),
+                (vec!["sqr"], false),
+                (vec!["unused"], false),
+                (vec!["start"], false),
+            ]
+        );
+        assert_eq!(items[3].text, "\n// Ray with origin and direction.\nstruct Ray\n{\n    vec4 o; // origin\n    vec4 d;\n};\n");
+        assert_eq!(items[5].text, "uniform float _offset; // offset\n");
+        assert!(items[6].text.starts_with("\n/* Multiline"));
+        assert!(items[7].uses.contains("PI"));
+        assert!(!items[4].uses.contains("e10"));
+        assert_eq!(
+            items.iter().map(|x| x.text).collect::<String>() + "// end\n",
+            CODE
+        );
+    }
+
+    #[test]
+    fn unused_items_are_removed() {
+        let items = split_items(CODE);
+        let groups = items.iter().map(std::slice::from_ref).collect::<Vec<_>>();
+        let roots = identifiers("void main() { gl_FragColor = start(ray_none); }");
+        assert_eq!(
+            reachable(&groups, &roots),
+            vec![true, true, false, true, true, true, false, false, true]
+        );
+
+        let roots = identifiers("// unused(1.)");
+        assert_eq!(
+            reachable(&groups, &roots),
+            vec![true, true, false, false, false, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn generated_shader() {
+        use crate::gui::scene::ShaderOptions;
+        use crate::scene_files::*;
+
+        let code = |id: &str| {
+            embedded_scene(id)
+                .unwrap()
+                .generate_shader_code(&ShaderOptions::default())
+                .unwrap()
+                .storage
+        };
+        for id in &["empty", "monoportal"] {
+            assert!(code(id).starts_with("#version 100\n"));
+            assert!(code(id).contains("precision highp float;"));
+        }
+        assert!(!code("empty").contains("process_portal_intersection("));
+        assert!(code("monoportal").contains("process_portal_intersection("));
+    }
+}
@@ -1,3 +1,5 @@
+//%prelude//%
+
 //%predefined_library//%
 
 // ---------------------------------------------------------------------------
@@ -54,10 +56,15 @@ uniform int _ray_tracing_depth;
 
 vec3 ray_tracing(Ray r) {
     vec3 current_color = vec3(1.);
+//%if glsl_es_100//%
+    // GLSL ES 1.00 allows only constant loop bounds.
     for (int j = 0; j < 10000; j++) {
         if (j > _ray_tracing_depth) {
             return current_color;
         }
+//%else//%
+    for (int j = 0; j <= _ray_tracing_depth; j++) {
+//%endif//%
         SceneIntersection i = scene_intersect(r);
 
         // Offset ray
@@ -83,10 +90,17 @@ vec3 ray_tracing(Ray r) {
 
 uniform mat4 _camera;
 uniform float _view_angle;
-uniform int _use_panini_projection;
-uniform float _panini_param;
+//%if glsl_es_100//%
 varying vec2 uv;
 varying vec2 uv_screen;
+//%else//%
+in vec2 uv;
+in vec2 uv_screen;
+out vec4 frag_color;
+//%endif//%
+
+//%if panini//%
+uniform float _panini_param;
 
 const float Pi = 3.14159265359;
 const float Pi2 = Pi * 2.0;
@@ -134,17 +148,21 @@ vec3 PaniniProjection(vec2 tc, float fov, float d)
     
     return vec3(sinPhi, tanTheta, cosPhi) * s;
 }
+//%endif//%
 
 void main() {
     vec4 o = _camera * vec4(0., 0., 0., 1.);
-    vec4 d;
-    if (_use_panini_projection == 1) {
-        d = normalize(_camera * vec4(PaniniProjection(vec2(uv_screen.x, uv_screen.y), _view_angle, _panini_param), 0.));
-    } else {
-        float h = tan(_view_angle / 2.);
-        d = normalize(_camera * vec4(uv_screen.x * h, uv_screen.y * h, 1.0, 0.));
-    }
+//%if panini//%
+    vec4 d = normalize(_camera * vec4(PaniniProjection(vec2(uv_screen.x, uv_screen.y), _view_angle, _panini_param), 0.));
+//%else//%
+    float h = tan(_view_angle / 2.);
+    vec4 d = normalize(_camera * vec4(uv_screen.x * h, uv_screen.y * h, 1.0, 0.));
+//%endif//%
      
     Ray r = Ray(o, d);
+//%if glsl_es_100//%
     gl_FragColor = vec4(sqrt(ray_tracing(r)), 1.);
+//%else//%
+    frag_color = vec4(sqrt(ray_tracing(r)), 1.);
+//%endif//%
 }
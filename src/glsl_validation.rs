@@ -0,0 +1,146 @@
+//! Offline checks of generated GLSL code by naga, so errors can be found without GPU. Naga parses only GLSL 4.50 for Vulkan, so shader is checked in this dialect, see `to_vulkan_glsl`. Errors that are specific to other dialects, like loops with non-constant bounds in GLSL ES 1.00, are still found only by GPU driver.
+
+use naga::valid::ModuleInfo;
+use naga::Module;
+
+/// Line of code for error, `usize::MAX` when error has no location.
+fn line_no(span: naga::Span, code: &str) -> usize {
+    if span.is_defined() {
+        span.location(code).line_number as usize
+    } else {
+        usize::MAX
+    }
+}
+
+/// Error with all its sources, because naga puts details of validation errors in sources.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut result = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        result += &format!(": {}", error);
+        source = error.source();
+    }
+    result
+}
+
+/// Parses and validates fragment shader in GLSL 4.50 for Vulkan. Errors are returned with line numbers, line numbers start from 1.
+pub fn parse_glsl(code: &str) -> Result<(Module, ModuleInfo), Vec<(usize, String)>> {
+    use naga::front::glsl::{Frontend, Options};
+    use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+    let module = Frontend::default()
+        .parse(&Options::from(naga::ShaderStage::Fragment), code)
+        .map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| (line_no(error.meta, code), error.kind.to_string()))
+                .collect::<Vec<_>>()
+        })?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|error| {
+            let line_no = error
+                .location(code)
+                .map(|location| location.line_number as usize)
+                .unwrap_or(usize::MAX);
+            vec![(line_no, error_chain(error.as_inner()))]
+        })?;
+    Ok((module, info))
+}
+
+/// Returns errors with line numbers, line numbers start from 1.
+pub fn validate_glsl(code: &str) -> Vec<(usize, String)> {
+    parse_glsl(code).err().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_code() {
+        let code = "#version 450
+#define PI acos(-1.)
+#define SQR(x) ((x) * (x))
+struct Ray { vec4 o; vec4 d; };
+layout(set = 0, binding = 0) uniform Uniforms { float a_u; float b_u; };
+layout(location = 0) out vec4 color;
+float f(Ray r, inout float t) {
+    float x = 1.5e-3, y = SQR(PI);
+    for (int i = 0; i < 10; i++) { t += r.o.x * float(i); }
+    return x + y + t + a_u + b_u;
+}
+void main() {
+    float t = 0.;
+    color = vec4(f(Ray(vec4(0.), vec4(1.)), t));
+}";
+        assert_eq!(validate_glsl(code), vec![]);
+    }
+
+    #[test]
+    fn errors() {
+        let code = "#version 450
+layout(location = 0) out vec4 color;
+float f(float a) {
+    float b = a + c;
+    return b;
+}
+void main() {
+    color = vec4(f(1.));
+}";
+        assert_eq!(
+            validate_glsl(code),
+            vec![(4, "Unknown variable: c".to_owned())]
+        );
+
+        let code = code
+            .replace("a + c", "a")
+            .replace("return b;", "return (b;");
+        assert_eq!(validate_glsl(&code).first().map(|x| x.0), Some(5));
+    }
+
+    #[test]
+    fn embedded_scenes_are_valid() {
+        use crate::gui::scene::{Scene, ShaderOptions};
+        use crate::scene_files::*;
+
+        for file in embedded_scenes() {
+            let scene: Scene = parse_scene(&file).unwrap();
+            for panini in &[false, true] {
+                for pack_uniforms in &[false, true] {
+                    let options = ShaderOptions {
+                        panini: *panini,
+                        pack_uniforms: *pack_uniforms,
+                        ..Default::default()
+                    };
+                    if let Err((_, message, _)) = scene.validate_shader(&options) {
+                        panic!("{}, {:?}: {}", file.id, options, message);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn errors_are_attributed() {
+        use crate::code_generation::ErrorId;
+        use crate::gui::scene::ShaderOptions;
+        use crate::scene_files::*;
+
+        let mut scene = embedded_scene("monoportal").unwrap();
+        let code = &mut scene.library.storage[0].0 .0;
+        code.push_str("\nfloat broken() {\n    return unknown_name;\n}");
+        let lines = code.lines().count();
+
+        let (_, _, errors) = scene
+            .validate_shader(&ShaderOptions::default())
+            .unwrap_err();
+        let library = &scene.library.storage[0];
+        let id = library.identifier(scene.library.ids[0]);
+        assert_eq!(errors.0[&id].len(), 1);
+        assert_eq!(errors.0[&id][0].0, lines - 1);
+        assert!(errors.0[&id][0].1.contains("unknown_name"));
+        assert_eq!(errors.0.len(), 1);
+        assert!(scene.error_source(id).starts_with("library `"));
+    }
+}
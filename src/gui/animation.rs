@@ -8,6 +8,7 @@ use egui::*;
 use glam::*;
 
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 use crate::megatuple;
 
@@ -19,16 +20,17 @@ pub enum Animation<T> {
     ChangedAndToUser(T),
 }
 
+/// Elements absent in this stage are the same as `Animation::Remains`.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AnimationStage {
-    pub uniforms: Vec<Animation<AnyUniform>>,
-    pub matrices: Vec<Animation<Matrix>>,
+    pub uniforms: BTreeMap<EntityId, Animation<AnyUniform>>,
+    pub matrices: BTreeMap<EntityId, Animation<Matrix>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GlobalUserUniforms {
-    pub uniforms: Vec<bool>,
-    pub matrices: Vec<bool>,
+    pub uniforms: BTreeSet<EntityId>,
+    pub matrices: BTreeSet<EntityId>,
 }
 
 impl<T: Default> ComboBoxChoosable for Animation<T> {
@@ -58,23 +60,38 @@ impl<T: Default> ComboBoxChoosable for Animation<T> {
     }
 }
 
+fn egui_ids_set<T>(
+    ui: &mut Ui,
+    set: &mut BTreeSet<EntityId>,
+    elements: &StorageWithNames<T>,
+) -> bool {
+    let mut changed = false;
+    set.retain(|id| elements.ids.contains(id));
+    for (id, name) in elements.ids.iter().zip(elements.names.iter()) {
+        let mut enabled = set.contains(id);
+        if check_changed(&mut enabled, |enabled| drop(ui.checkbox(enabled, name))) {
+            if enabled {
+                set.insert(*id);
+            } else {
+                set.remove(id);
+            }
+            changed = true;
+        }
+    }
+    changed
+}
+
 impl GlobalUserUniforms {
     pub fn egui(
         &mut self,
         ui: &mut Ui,
-        matrices_names: &mut Vec<String>,
-        formulas_names: &mut Vec<String>,
+        matrices: &StorageWithNames<MatrixComboBox>,
+        uniforms: &StorageWithNames<AnyUniformComboBox>,
     ) -> WhatChanged {
         let mut changed = false;
-        self.uniforms.resize(formulas_names.len(), false);
-        for (enabled, name) in self.uniforms.iter_mut().zip(formulas_names.iter()) {
-            changed |= check_changed(enabled, |enabled| drop(ui.checkbox(enabled, name)));
-        }
+        changed |= egui_ids_set(ui, &mut self.uniforms, uniforms);
         ui.separator();
-        self.matrices.resize(matrices_names.len(), false);
-        for (enabled, name) in self.matrices.iter_mut().zip(matrices_names.iter()) {
-            changed |= check_changed(enabled, |enabled| drop(ui.checkbox(enabled, name)));
-        }
+        changed |= egui_ids_set(ui, &mut self.matrices, matrices);
         WhatChanged::from_uniform(changed)
     }
 }
@@ -99,22 +116,22 @@ impl StorageElem for AnimationStage {
     fn egui(
         &mut self,
         ui: &mut Ui,
-        mut glob_pos: usize,
+        id: EntityId,
         megapattern!(matrices, uniforms, global_uniforms): &mut Self::Input,
         _: &[String],
     ) -> WhatChanged {
         let mut changed = WhatChanged::default();
-        self.uniforms
-            .resize(uniforms.names.len(), Animation::Remains);
-        for (pos, (((anim, global), name), uniform)) in self
-            .uniforms
-            .iter_mut()
-            .zip(global_uniforms.uniforms.iter())
+        let mut glob_pos = id.0 as usize;
+        self.uniforms.retain(|id, _| uniforms.ids.contains(id));
+        for (pos, ((id, name), uniform)) in uniforms
+            .ids
+            .iter()
             .zip(uniforms.names.iter())
             .zip(uniforms.storage.iter())
             .enumerate()
         {
-            if *global {
+            let anim = self.uniforms.entry(*id).or_insert(Animation::Remains);
+            if global_uniforms.uniforms.contains(id) {
                 ui.horizontal(|ui| {
                     egui_label(ui, name, 60.);
                     ui.label("Global uniform");
@@ -138,17 +155,16 @@ impl StorageElem for AnimationStage {
 
         ui.separator();
         glob_pos += self.uniforms.len() * 2;
-        self.matrices
-            .resize(matrices.names.len(), Animation::Remains);
-        for (pos, (((anim, global), name), matrix)) in self
-            .matrices
-            .iter_mut()
-            .zip(global_uniforms.matrices.iter())
+        self.matrices.retain(|id, _| matrices.ids.contains(id));
+        for (pos, ((id, name), matrix)) in matrices
+            .ids
+            .iter()
             .zip(matrices.names.iter())
             .zip(matrices.storage.iter())
             .enumerate()
         {
-            if *global {
+            let anim = self.matrices.entry(*id).or_insert(Animation::Remains);
+            if global_uniforms.matrices.contains(id) {
                 ui.horizontal(|ui| {
                     egui_label(ui, name, 60.);
                     ui.label("Global uniform");
@@ -172,7 +188,7 @@ impl StorageElem for AnimationStage {
         changed
     }
 
-    fn errors_count(&self, _: usize, _: &Self::Input, _: &[String]) -> usize {
+    fn errors_count(&self, _: EntityId, _: &Self::Input, _: &[String]) -> usize {
         0
     }
 }
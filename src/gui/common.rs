@@ -1,11 +1,14 @@
 use crate::code_generation::ErrId;
 use crate::code_generation::ErrorId;
-use crate::gui::object::MatrixName;
+use crate::gui::format::SceneFormat;
+use crate::gui::rename::RenameWindow;
+use crate::gui::scene::ShaderOptions;
+use crate::gui::storage::EntityId;
 use crate::gui::uniform::FormulasCache;
 use egui::*;
 use glam::*;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use std::f32::consts::PI;
 
@@ -22,7 +25,10 @@ pub fn rad2deg(rad: f32) -> f32 {
 }
 
 #[derive(Debug, Default)]
-pub struct MatrixRecursionError(pub BTreeMap<MatrixName, bool>);
+pub struct MatrixRecursionError(pub BTreeMap<EntityId, bool>);
+
+/// Line number of error in code that is generated for entity, not written by user.
+pub const GENERATED_LINE: usize = 0;
 
 #[derive(Debug, Default)]
 pub struct ShaderErrors(pub BTreeMap<ErrId, Vec<(usize, String)>>);
@@ -34,29 +40,37 @@ impl ShaderErrors {
     pub fn get_errors<'a, T: ErrorId>(
         &'a self,
         t: &T,
-        pos: usize,
+        id: EntityId,
     ) -> Option<&'a [(usize, String)]> {
-        self.0.get(&t.identifier(pos)).map(|x| &x[..])
+        self.0.get(&t.identifier(id)).map(|x| &x[..])
     }
 }
 
 #[derive(Debug, Default)]
 pub struct Data {
     pub to_export: Option<String>,
+    pub export_format: SceneFormat,
     pub errors: ShaderErrors,
     pub matrix_recursion_error: MatrixRecursionError,
     pub show_error_window: bool,
     pub show_glsl_library: bool,
     pub show_compiled_code: Option<String>,
-    pub description_en_edit: bool,
-    pub description_ru_edit: bool,
+    pub shader_options: ShaderOptions,
+    pub description_edit: BTreeSet<String>,
+    pub new_locale: String,
 
     pub formulas_cache: FormulasCache,
 
     pub reload_textures: bool,
     pub texture_errors: TextureErrors,
 
-    pub read_ru: bool,
+    /// Texture of uniforms when they are packed, it is reused while its size is the same.
+    pub packed_uniforms_texture: Option<macroquad::prelude::miniquad::Texture>,
+
+    /// Language of description in control window, closest available one is shown.
+    pub locale: String,
+
+    pub rename: RenameWindow,
 }
 
 pub fn add_line_numbers(s: &str) -> String {
@@ -241,6 +255,12 @@ pub fn egui_errors(ui: &mut Ui, errors: &[(usize, String)]) {
                         .text_color(COLOR_ERROR)
                         .monospace(),
                 );
+            } else if *line_no == GENERATED_LINE {
+                ui.add(
+                    Label::new("ERR:generated: ")
+                        .text_color(COLOR_ERROR)
+                        .monospace(),
+                );
             } else {
                 ui.add(
                     Label::new(format!("ERR:{}: ", line_no))
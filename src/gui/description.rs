@@ -0,0 +1,144 @@
+use egui::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Language of descriptions that is shown when there is no better match.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Scene description in different languages. Keys are locale codes like `en`, `ru` or `pt-BR`, values are easy mark texts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Descriptions(pub BTreeMap<String, String>);
+
+/// `pt-BR` → `pt`.
+fn language(locale: &str) -> &str {
+    locale.split(&['-', '_'][..]).next().unwrap_or(locale)
+}
+
+/// Known languages are shown by name, others by their code.
+pub fn locale_name(locale: &str) -> String {
+    let name = match language(locale) {
+        "en" => "English",
+        "ru" => "Russian",
+        "de" => "German",
+        "fr" => "French",
+        "es" => "Spanish",
+        "it" => "Italian",
+        "pt" => "Portuguese",
+        "uk" => "Ukrainian",
+        "zh" => "Chinese",
+        "ja" => "Japanese",
+        _ => return locale.to_owned(),
+    };
+    if locale == language(locale) {
+        name.to_owned()
+    } else {
+        format!("{} ({})", name, locale)
+    }
+}
+
+fn is_valid_locale(locale: &str) -> bool {
+    !locale.is_empty()
+        && locale
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl Descriptions {
+    /// Locales with non-empty descriptions.
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .filter(|(_, text)| !text.trim().is_empty())
+            .map(|(locale, _)| &locale[..])
+    }
+
+    /// Returns locale and text of description that is the best for `locale`: the same locale, then the same language (`pt` or `pt-PT` for `pt-BR`), then `DEFAULT_LOCALE`, then any description.
+    pub fn get(&self, locale: &str) -> Option<(&str, &str)> {
+        let find =
+            |f: &dyn Fn(&str) -> bool| self.locales().find(|x| f(x)).map(|x| (x, &self.0[x][..]));
+        find(&|x| x == locale)
+            .or_else(|| find(&|x| x == language(locale)))
+            .or_else(|| find(&|x| language(x) == language(locale)))
+            .or_else(|| find(&|x| x == DEFAULT_LOCALE))
+            .or_else(|| find(&|_| true))
+    }
+
+    /// Editor of all descriptions, `editing` contains locales that are shown as text fields.
+    pub fn egui(&mut self, ui: &mut Ui, editing: &mut BTreeSet<String>, new_locale: &mut String) {
+        let mut to_remove = None;
+        for (locale, text) in &mut self.0 {
+            CollapsingHeader::new(locale_name(locale))
+                .id_source(locale)
+                .default_open(false)
+                .show(ui, |ui| {
+                    let mut edit = editing.contains(locale);
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut edit, false, "View");
+                        ui.selectable_value(&mut edit, true, "Edit");
+                        if ui.button("Remove").clicked() {
+                            to_remove = Some(locale.clone());
+                        }
+                    });
+                    if edit {
+                        editing.insert(locale.clone());
+                        ui.add(TextEdit::multiline(text).text_style(TextStyle::Monospace));
+                    } else {
+                        editing.remove(locale);
+                        egui::experimental::easy_mark(ui, text);
+                    }
+                });
+        }
+        if let Some(locale) = to_remove {
+            self.0.remove(&locale);
+            editing.remove(&locale);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Language code:");
+            ui.text_edit_singleline(new_locale);
+            let locale = new_locale.trim();
+            let can_add = is_valid_locale(locale) && !self.0.contains_key(locale);
+            if ui.add(Button::new("Add").enabled(can_add)).clicked() {
+                self.0.insert(locale.to_owned(), String::new());
+                editing.insert(locale.to_owned());
+                new_locale.clear();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptions(list: &[(&str, &str)]) -> Descriptions {
+        Descriptions(
+            list.iter()
+                .map(|(locale, text)| (locale.to_string(), text.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn fallback() {
+        let d = descriptions(&[("en", "a"), ("pt", "b"), ("de-AT", "c"), ("ru", " ")]);
+        assert_eq!(d.get("pt"), Some(("pt", "b")));
+        assert_eq!(d.get("pt-BR"), Some(("pt", "b")));
+        assert_eq!(d.get("de"), Some(("de-AT", "c")));
+        assert_eq!(d.get("ru"), Some(("en", "a")));
+        assert_eq!(d.get(""), Some(("en", "a")));
+        assert_eq!(d.locales().collect::<Vec<_>>(), vec!["de-AT", "en", "pt"]);
+
+        let d = descriptions(&[("ru", "a"), ("en", "")]);
+        assert_eq!(d.get("fr"), Some(("ru", "a")));
+        assert_eq!(descriptions(&[("en", "")]).get("en"), None);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(locale_name("en"), "English");
+        assert_eq!(locale_name("pt-BR"), "Portuguese (pt-BR)");
+        assert_eq!(locale_name("eo"), "eo");
+    }
+}
@@ -0,0 +1,843 @@
+use crate::gui::format::value_to_yaml;
+use crate::gui::scene::Scene;
+use crate::gui::validation::StorageKind;
+
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+const STORAGES: &[(StorageKind, &str)] = &[
+    (StorageKind::Uniforms, "uniforms"),
+    (StorageKind::Matrices, "matrices"),
+    (StorageKind::Objects, "objects"),
+    (StorageKind::Textures, "textures"),
+    (StorageKind::Materials, "materials"),
+    (StorageKind::Library, "library"),
+    (StorageKind::AnimationStages, "animation_stages"),
+];
+
+/// Fields of scene that are not storages.
+const FIELDS: &[&str] = &[
+    "version",
+    "descriptions",
+    "cam",
+    "user_uniforms",
+    "current_stage",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+/// Line diff by longest common subsequence.
+fn diff_lines(before: &str, after: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = before.lines().collect();
+    let b: Vec<&str> = after.lines().collect();
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            result.push(DiffLine::Same(a[i].to_owned()));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            result.push(DiffLine::Removed(a[i].to_owned()));
+            i += 1;
+        } else {
+            result.push(DiffLine::Added(b[j].to_owned()));
+            j += 1;
+        }
+    }
+    result
+}
+
+/// Difference between two scenes. Elements of storages are matched by identifiers and names, so renamed element is not shown as removed and added.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneChange {
+    Added {
+        storage: StorageKind,
+        name: String,
+    },
+    Removed {
+        storage: StorageKind,
+        name: String,
+    },
+    Renamed {
+        storage: StorageKind,
+        from: String,
+        to: String,
+    },
+    Modified {
+        storage: StorageKind,
+        name: String,
+        lines: Vec<DiffLine>,
+    },
+    Reordered {
+        storage: StorageKind,
+    },
+    Field {
+        field: String,
+        lines: Vec<DiffLine>,
+    },
+}
+
+fn fmt_lines(f: &mut Formatter<'_>, lines: &[DiffLine]) -> fmt::Result {
+    for line in lines {
+        match line {
+            DiffLine::Same(_) => {}
+            DiffLine::Removed(line) => write!(f, "\n    - {}", line)?,
+            DiffLine::Added(line) => write!(f, "\n    + {}", line)?,
+        }
+    }
+    Ok(())
+}
+
+impl Display for SceneChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use SceneChange::*;
+        match self {
+            Added { storage, name } => write!(f, "+ {} '{}'", storage, name),
+            Removed { storage, name } => write!(f, "- {} '{}'", storage, name),
+            Renamed { storage, from, to } => {
+                write!(f, "~ {} '{}' renamed to '{}'", storage, from, to)
+            }
+            Modified {
+                storage,
+                name,
+                lines,
+            } => {
+                write!(f, "~ {} '{}' changed:", storage, name)?;
+                fmt_lines(f, lines)
+            }
+            Reordered { storage } => write!(f, "~ {} elements reordered", storage),
+            Field { field, lines } => {
+                write!(f, "~ {} changed:", field)?;
+                fmt_lines(f, lines)
+            }
+        }
+    }
+}
+
+/// Conflicting change in three-way merge, version from `ours` is kept for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// `None` for fields of scene that are not storages.
+    pub storage: Option<StorageKind>,
+    pub name: String,
+
+    /// Path inside element, empty when the whole element conflicts.
+    pub path: String,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.storage {
+            Some(storage) => write!(f, "{} '{}'", storage, self.name)?,
+            None => write!(f, "{}", self.name)?,
+        }
+        if !self.path.is_empty() {
+            write!(f, " at {}", self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Going through the text keeps `f32` numbers short, as in `Scene::to_text`.
+fn to_value(scene: &Scene) -> Value {
+    serde_json::from_str(&serde_json::to_string(scene).unwrap()).unwrap()
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => value_to_yaml(other),
+    }
+}
+
+/// Identifier, name and value of element in storage.
+type Element<'a> = (u64, &'a str, &'a Value);
+
+fn elements(storage: &Value) -> Vec<Element<'_>> {
+    let ids = storage["ids"].as_array().unwrap();
+    let names = storage["names"].as_array().unwrap();
+    let values = storage["storage"].as_array().unwrap();
+    ids.iter()
+        .zip(names)
+        .zip(values)
+        .map(|((id, name), value)| (id.as_u64().unwrap(), name.as_str().unwrap(), value))
+        .collect()
+}
+
+fn find<'a, 'b>(elements: &'b [Element<'a>], id: u64) -> Option<&'b Element<'a>> {
+    elements.iter().find(|x| x.0 == id)
+}
+
+/// Returns identifiers of `other` elements mapped to identifiers of the same elements in `reference`. Elements are the same when they have the same identifier and name, then when they have the same name, and then, if `can_be_renamed`, when they have the same identifier. Names are needed because identifiers of scenes can disagree, for example when both scenes got identifiers from positions during migration.
+fn match_elements(
+    reference: &[Element],
+    other: &[Element],
+    can_be_renamed: bool,
+) -> BTreeMap<u64, u64> {
+    let mut result = BTreeMap::new();
+    let mut used = BTreeSet::new();
+    for pass in 0..3 {
+        for o in other {
+            if result.contains_key(&o.0) {
+                continue;
+            }
+            let is_same = |r: &&Element| {
+                !used.contains(&r.0)
+                    && match pass {
+                        0 => r.0 == o.0 && r.1 == o.1,
+                        1 => r.1 == o.1,
+                        _ => can_be_renamed && r.0 == o.0,
+                    }
+            };
+            if let Some(r) = reference.iter().find(is_same) {
+                result.insert(o.0, r.0);
+                used.insert(r.0);
+            }
+        }
+    }
+    result
+}
+
+/// Replaces identifiers of storage `field` in scene, together with references to them from animation stages and user uniforms.
+fn remap_ids(scene: &mut Value, field: &str, ids: &BTreeMap<u64, u64>) {
+    let remap = |id: u64| ids.get(&id).copied().unwrap_or(id);
+    for id in scene[field]["ids"].as_array_mut().unwrap() {
+        *id = Value::from(remap(id.as_u64().unwrap()));
+    }
+    if field == "uniforms" || field == "matrices" {
+        for stage in scene["animation_stages"]["storage"].as_array_mut().unwrap() {
+            if let Some(map) = stage[field].as_object_mut() {
+                *map = std::mem::take(map)
+                    .into_iter()
+                    .map(|(id, x)| match id.parse::<u64>() {
+                        Ok(id) => (remap(id).to_string(), x),
+                        Err(_) => (id, x),
+                    })
+                    .collect();
+            }
+        }
+        for id in scene["user_uniforms"][field].as_array_mut().unwrap() {
+            *id = Value::from(remap(id.as_u64().unwrap()));
+        }
+    }
+}
+
+/// Gives elements of `other` identifiers of the same elements in `reference`, other elements get new identifiers when theirs are taken in `reference`. When `base` is given, elements of both scenes that are in `base` are already matched, and other elements are matched only by name, this is how elements added independently in both versions are matched.
+fn align_ids(reference: &Value, other: &mut Value, base: Option<&Value>) {
+    for (_, field) in STORAGES {
+        let mut next_id = reference[field]["next_id"]
+            .as_u64()
+            .unwrap()
+            .max(other[field]["next_id"].as_u64().unwrap());
+        let mut ids = BTreeMap::new();
+        {
+            let er = elements(&reference[field]);
+            let eo = elements(&other[field]);
+            let eb = base.map(|base| elements(&base[field])).unwrap_or_default();
+            let is_new = |x: &&Element| find(&eb, x.0).is_none();
+            let matched = if base.is_some() {
+                let er: Vec<Element> = er.iter().filter(is_new).copied().collect();
+                let eo: Vec<Element> = eo.iter().filter(is_new).copied().collect();
+                match_elements(&er, &eo, false)
+            } else {
+                match_elements(&er, &eo, true)
+            };
+            for (id, _, _) in eo.iter().filter(is_new) {
+                match matched.get(id) {
+                    Some(new) if new != id => {
+                        ids.insert(*id, *new);
+                    }
+                    Some(_) => {}
+                    None if find(&er, *id).is_some() => {
+                        ids.insert(*id, next_id);
+                        next_id += 1;
+                    }
+                    None => {}
+                }
+            }
+        }
+        other[field]["next_id"] = Value::from(next_id);
+        if !ids.is_empty() {
+            remap_ids(other, field, &ids);
+        }
+    }
+}
+
+impl Scene {
+    /// Returns changes that turn `self` into `other`.
+    pub fn diff(&self, other: &Scene) -> Vec<SceneChange> {
+        let a = to_value(self);
+        let mut b = to_value(other);
+        align_ids(&a, &mut b, None);
+        let mut result = Vec::new();
+
+        for (storage, field) in STORAGES {
+            let storage = *storage;
+            let ea = elements(&a[field]);
+            let eb = elements(&b[field]);
+            for (id, name, _) in &ea {
+                if find(&eb, *id).is_none() {
+                    result.push(SceneChange::Removed {
+                        storage,
+                        name: name.to_string(),
+                    });
+                }
+            }
+            for (id, name, value) in &eb {
+                match find(&ea, *id) {
+                    None => result.push(SceneChange::Added {
+                        storage,
+                        name: name.to_string(),
+                    }),
+                    Some((_, old_name, old_value)) => {
+                        if old_name != name {
+                            result.push(SceneChange::Renamed {
+                                storage,
+                                from: old_name.to_string(),
+                                to: name.to_string(),
+                            });
+                        }
+                        if old_value != value {
+                            result.push(SceneChange::Modified {
+                                storage,
+                                name: name.to_string(),
+                                lines: diff_lines(&text(old_value), &text(value)),
+                            });
+                        }
+                    }
+                }
+            }
+
+            let order_a: Vec<u64> = ea
+                .iter()
+                .map(|x| x.0)
+                .filter(|id| find(&eb, *id).is_some())
+                .collect();
+            let order_b: Vec<u64> = eb
+                .iter()
+                .map(|x| x.0)
+                .filter(|id| find(&ea, *id).is_some())
+                .collect();
+            if order_a != order_b {
+                result.push(SceneChange::Reordered { storage });
+            }
+        }
+
+        for field in FIELDS {
+            if a[field] != b[field] {
+                result.push(SceneChange::Field {
+                    field: field.to_string(),
+                    lines: diff_lines(&text(&a[field]), &text(&b[field])),
+                });
+            }
+        }
+
+        result
+    }
+}
+
+/// Externally tagged enum is written as object with one capitalized key, such objects are merged as a whole, because merging different variants gives nonsense.
+fn is_variant(map: &Map<String, Value>) -> bool {
+    map.len() == 1
+        && map
+            .keys()
+            .next()
+            .map(|x| x.starts_with(|c: char| c.is_ascii_uppercase()))
+            .unwrap_or(false)
+}
+
+/// Three-way merge of JSON values, objects are merged key by key. Paths of conflicts are added to `conflicts`, and `ours` is taken for them.
+fn merge_value(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    path: &str,
+    conflicts: &mut Vec<String>,
+) -> Option<Value> {
+    if ours == theirs || theirs == base {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+    if let (Some(Value::Object(o)), Some(Value::Object(t))) = (ours, theirs) {
+        let same_variant = !(is_variant(o) || is_variant(t)) || o.keys().eq(t.keys());
+        if same_variant {
+            let b = base.and_then(Value::as_object);
+            let keys: BTreeSet<&String> = o.keys().chain(t.keys()).collect();
+            let mut result = Map::new();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                let merged = merge_value(
+                    b.and_then(|b| b.get(key)),
+                    o.get(key),
+                    t.get(key),
+                    &path,
+                    conflicts,
+                );
+                if let Some(merged) = merged {
+                    result.insert(key.clone(), merged);
+                }
+            }
+            return Some(Value::Object(result));
+        }
+    }
+    conflicts.push(path.to_owned());
+    ours.cloned()
+}
+
+/// Lists of identifiers are sets, they are merged as maps to allow independent additions.
+fn set_to_map(value: &Value) -> Value {
+    Value::Object(
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|id| (id.to_string(), Value::Bool(true)))
+            .collect(),
+    )
+}
+
+fn map_to_set(value: &Value) -> Value {
+    Value::Array(
+        value
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(id, _)| id.parse::<u64>().ok().map(Value::from))
+            .collect(),
+    )
+}
+
+/// Order of elements is taken from the version that changed it, order of `ours` is kept when both versions changed it differently.
+fn merge_order(
+    field: &str,
+    eb: &[Element],
+    eo: &[Element],
+    et: &[Element],
+    conflicts: &mut Vec<Conflict>,
+) -> Vec<u64> {
+    let common: Vec<u64> = eb
+        .iter()
+        .map(|x| x.0)
+        .filter(|id| find(eo, *id).is_some() && find(et, *id).is_some())
+        .collect();
+    let relative = |elements: &[Element]| -> Vec<u64> {
+        elements
+            .iter()
+            .map(|x| x.0)
+            .filter(|id| common.contains(id))
+            .collect()
+    };
+    let (b, o, t) = (relative(eb), relative(eo), relative(et));
+    if o != b && t != b && o != t {
+        conflicts.push(Conflict {
+            storage: None,
+            name: field.to_owned(),
+            path: "order".to_owned(),
+        });
+    }
+
+    let (first, second) = if o == b && t != b { (et, eo) } else { (eo, et) };
+    let mut order: Vec<u64> = first.iter().map(|x| x.0).collect();
+    order.extend(
+        second
+            .iter()
+            .map(|x| x.0)
+            .filter(|id| find(first, *id).is_none()),
+    );
+    order
+}
+
+fn merge_storage(
+    (storage, field): (StorageKind, &str),
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+    conflicts: &mut Vec<Conflict>,
+) -> Value {
+    let element = |x: &Element| serde_json::json!({ "name": x.1, "value": x.2 });
+
+    let eb = elements(base);
+    let eo = elements(ours);
+    let et = elements(theirs);
+    let order = merge_order(field, &eb, &eo, &et, conflicts);
+
+    let mut merged_elements = Vec::new();
+    for id in order {
+        let b = find(&eb, id).map(element);
+        let o = find(&eo, id).map(element);
+        let t = find(&et, id).map(element);
+        let mut paths = Vec::new();
+        let merged = merge_value(b.as_ref(), o.as_ref(), t.as_ref(), "", &mut paths);
+        let name = [&o, &t, &b]
+            .iter()
+            .find_map(|x| x.as_ref())
+            .map(|x| x["name"].as_str().unwrap().to_owned())
+            .unwrap();
+        for path in paths {
+            conflicts.push(Conflict {
+                storage: Some(storage),
+                name: name.clone(),
+                path: path
+                    .strip_prefix("value")
+                    .map(|x| x.trim_start_matches('.'))
+                    .unwrap_or(&path)
+                    .to_owned(),
+            });
+        }
+        if let Some(merged) = merged {
+            merged_elements.push((id, name, merged));
+        }
+    }
+
+    // Name from `theirs` can be taken by other element in `ours`, for example when both versions give the same name to different elements. Name from `ours` is kept then, and element that is only in `theirs` is dropped.
+    let ours_name = |id: u64| find(&eo, id).map(|x| Value::from(x.1));
+    let ours_names: Vec<Value> = merged_elements
+        .iter()
+        .filter(|(id, _, merged)| ours_name(*id).as_ref() == Some(&merged["name"]))
+        .map(|(_, _, merged)| merged["name"].clone())
+        .collect();
+    let mut ids = Vec::new();
+    let mut names = Vec::new();
+    let mut values = Vec::new();
+    for (id, name, mut merged) in merged_elements {
+        let ours_name = ours_name(id);
+        let is_from_theirs = ours_name.as_ref() != Some(&merged["name"]);
+        if (is_from_theirs && ours_names.contains(&merged["name"]))
+            || names.contains(&merged["name"])
+        {
+            conflicts.push(Conflict {
+                storage: Some(storage),
+                name,
+                path: "name".to_owned(),
+            });
+            match ours_name {
+                Some(ours_name) => merged["name"] = ours_name,
+                None => continue,
+            }
+        }
+        ids.push(Value::from(id));
+        names.push(merged["name"].clone());
+        values.push(merged["value"].clone());
+    }
+
+    let next_id = ours["next_id"]
+        .as_u64()
+        .unwrap()
+        .max(theirs["next_id"].as_u64().unwrap());
+    serde_json::json!({
+        "ids": ids,
+        "names": names,
+        "storage": values,
+        "next_id": next_id,
+    })
+}
+
+impl Scene {
+    /// Merges changes made in `ours` and `theirs` relative to `base`. Conflicting changes are reported per element, version from `ours` is kept for them.
+    pub fn three_way_merge(
+        base: &Scene,
+        ours: &Scene,
+        theirs: &Scene,
+    ) -> Result<(Scene, Vec<Conflict>), String> {
+        let base = to_value(base);
+        let mut ours = to_value(ours);
+        let mut theirs = to_value(theirs);
+        align_ids(&base, &mut ours, None);
+        align_ids(&base, &mut theirs, None);
+        align_ids(&ours, &mut theirs, Some(&base));
+
+        let mut conflicts = Vec::new();
+        let mut result = Map::new();
+        for (storage, field) in STORAGES {
+            let merged = merge_storage(
+                (*storage, field),
+                &base[field],
+                &ours[field],
+                &theirs[field],
+                &mut conflicts,
+            );
+            result.insert(field.to_string(), merged);
+        }
+
+        for field in FIELDS {
+            let prepare = |x: &Value| {
+                let mut x = x[field].clone();
+                if *field == "user_uniforms" {
+                    for set in &["uniforms", "matrices"] {
+                        x[set] = set_to_map(&x[set]);
+                    }
+                }
+                x
+            };
+            let (b, o, t) = (prepare(&base), prepare(&ours), prepare(&theirs));
+            let mut paths = Vec::new();
+            let mut merged =
+                merge_value(Some(&b), Some(&o), Some(&t), "", &mut paths).unwrap_or(Value::Null);
+            if *field == "user_uniforms" {
+                for set in &["uniforms", "matrices"] {
+                    merged[set] = map_to_set(&merged[set]);
+                }
+            }
+            for path in paths {
+                conflicts.push(Conflict {
+                    storage: None,
+                    name: field.to_string(),
+                    path,
+                });
+            }
+            result.insert(field.to_string(), merged);
+        }
+
+        let scene = serde_json::from_value(Value::Object(result)).map_err(|e| e.to_string())?;
+        Ok((scene, conflicts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::animation::*;
+    use crate::gui::storage::*;
+
+    fn mobius() -> Scene {
+        Scene::from_json(include_str!("../../scenes/mobius.json")).unwrap()
+    }
+
+    #[test]
+    fn lines() {
+        use DiffLine::*;
+        assert_eq!(
+            diff_lines("a\nb\nc", "a\nc\nd"),
+            vec![
+                Same("a".to_owned()),
+                Removed("b".to_owned()),
+                Same("c".to_owned()),
+                Added("d".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let a = mobius();
+        assert_eq!(a.diff(&a), vec![]);
+
+        let mut b = a.clone();
+        b.descriptions
+            .0
+            .entry("en".to_owned())
+            .or_default()
+            .push_str("\nmore");
+        let removed = b.objects.names[0].clone();
+        b.objects.remove(0);
+        b.matrices.names[0] = "renamed".to_owned();
+        b.uniforms.add("new".to_owned(), Default::default());
+        b.library.swap(0, 1);
+        b.materials.storage[0] = Default::default();
+
+        let changes = a.diff(&b);
+        assert_eq!(changes.len(), 6, "{:#?}", changes);
+        assert!(changes.contains(&SceneChange::Removed {
+            storage: StorageKind::Objects,
+            name: removed,
+        }));
+        assert!(changes.contains(&SceneChange::Renamed {
+            storage: StorageKind::Matrices,
+            from: a.matrices.names[0].clone(),
+            to: "renamed".to_owned(),
+        }));
+        assert!(changes.contains(&SceneChange::Added {
+            storage: StorageKind::Uniforms,
+            name: "new".to_owned(),
+        }));
+        assert!(changes.contains(&SceneChange::Reordered {
+            storage: StorageKind::Library,
+        }));
+        assert!(changes.iter().any(|x| matches!(
+            x,
+            SceneChange::Modified {
+                storage: StorageKind::Materials,
+                ..
+            }
+        )));
+        assert!(changes.iter().any(|x| match x {
+            SceneChange::Field { field, lines } => {
+                field == "descriptions"
+                    && lines.contains(&DiffLine::Added("  more".to_owned()))
+            }
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn merge_without_conflicts() {
+        let base = mobius();
+        let mut ours = base.clone();
+        ours.descriptions.0.insert("en".to_owned(), "ours".to_owned());
+        ours.matrices.names[0] = "renamed".to_owned();
+        let mut theirs = base.clone();
+        theirs.objects.remove(0);
+        theirs.cam.r = 10.;
+
+        let (merged, conflicts) = Scene::three_way_merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(conflicts, vec![]);
+        assert_eq!(merged.descriptions.0["en"], "ours");
+        assert_eq!(merged.matrices.names[0], "renamed");
+        assert_eq!(merged.objects.names, theirs.objects.names);
+        assert_eq!(merged.cam.r, 10.);
+    }
+
+    #[test]
+    fn merge_with_conflicts() {
+        let base = mobius();
+        let mut ours = base.clone();
+        ours.descriptions.0.insert("en".to_owned(), "ours".to_owned());
+        ours.matrices.names[0] = "ours".to_owned();
+        let mut theirs = base.clone();
+        theirs.descriptions.0.insert("en".to_owned(), "theirs".to_owned());
+        theirs.matrices.names[0] = "theirs".to_owned();
+
+        let (merged, conflicts) = Scene::three_way_merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged.descriptions.0["en"], "ours");
+        assert_eq!(merged.matrices.names[0], "ours");
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict {
+                    storage: Some(StorageKind::Matrices),
+                    name: "ours".to_owned(),
+                    path: "name".to_owned(),
+                },
+                Conflict {
+                    storage: None,
+                    name: "descriptions".to_owned(),
+                    path: "en".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn added_in_both() {
+        let base = mobius();
+        let mut ours = base.clone();
+        let a = ours.uniforms.add("a".to_owned(), Default::default());
+        let mut theirs = base.clone();
+        let b = theirs.uniforms.add("b".to_owned(), Default::default());
+        assert_eq!(a, b);
+        theirs.user_uniforms.uniforms.insert(b);
+        let mut stage = AnimationStage::default();
+        stage.uniforms.insert(b, Animation::ProvidedToUser);
+        theirs.animation_stages.add("stage".to_owned(), stage);
+
+        let (merged, conflicts) = Scene::three_way_merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(conflicts, vec![]);
+        let a = merged.uniforms.id_by_name("a").unwrap();
+        let b = merged.uniforms.id_by_name("b").unwrap();
+        assert_ne!(a, b);
+        assert!(merged.uniforms.next_id > b.0);
+        assert!(merged.user_uniforms.uniforms.contains(&b));
+        assert!(!merged.user_uniforms.uniforms.contains(&a));
+        assert!(merged.animation_stages.storage[0].uniforms.contains_key(&b));
+        assert_eq!(merged.validate(), vec![]);
+    }
+
+    /// Identifiers from positions, as after migration of scene without identifiers.
+    fn reset_ids<T>(storage: &mut StorageWithNames<T>) {
+        storage.ids = (0..storage.names.len() as u64).map(EntityId).collect();
+        storage.next_id = storage.names.len() as u64;
+    }
+
+    #[test]
+    fn matched_by_names() {
+        let a = mobius();
+        let mut b = a.clone();
+        b.materials.remove(0);
+        reset_ids(&mut b.materials);
+        assert_eq!(
+            a.diff(&b),
+            vec![SceneChange::Removed {
+                storage: StorageKind::Materials,
+                name: a.materials.names[0].clone(),
+            }]
+        );
+
+        let mut theirs = a.clone();
+        theirs.materials.storage[3] = Default::default();
+        let (merged, conflicts) = Scene::three_way_merge(&a, &b, &theirs).unwrap();
+        assert_eq!(conflicts, vec![]);
+        assert_eq!(merged.materials.names, b.materials.names);
+        assert_eq!(a.diff(&merged).len(), 2);
+    }
+
+    #[test]
+    fn same_name_added_in_both() {
+        let base = mobius();
+        let mut ours = base.clone();
+        ours.uniforms.add("a".to_owned(), Default::default());
+        let mut theirs = base.clone();
+        theirs.uniforms.add("b".to_owned(), Default::default());
+        theirs.uniforms.add("a".to_owned(), Default::default());
+
+        let (merged, conflicts) = Scene::three_way_merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(conflicts, vec![]);
+        let count = |name: &str| merged.uniforms.names.iter().filter(|x| *x == name).count();
+        assert_eq!((count("a"), count("b")), (1, 1));
+
+        let mut theirs = base.clone();
+        theirs.uniforms.names[0] = "a".to_owned();
+        let (merged, conflicts) = Scene::three_way_merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(merged.uniforms.names, ours.uniforms.names);
+    }
+
+    #[test]
+    fn reorders() {
+        let base = mobius();
+        let mut ours = base.clone();
+        ours.uniforms.add("a".to_owned(), Default::default());
+        let mut theirs = base.clone();
+        theirs.matrices.swap(0, 1);
+
+        let (merged, conflicts) = Scene::three_way_merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(conflicts, vec![]);
+        assert_eq!(merged.matrices.names, theirs.matrices.names);
+        assert_eq!(merged.uniforms.names, ours.uniforms.names);
+
+        ours.matrices.swap(1, 2);
+        let (merged, conflicts) = Scene::three_way_merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged.matrices.names, ours.matrices.names);
+        assert_eq!(
+            conflicts,
+            vec![Conflict {
+                storage: None,
+                name: "matrices".to_owned(),
+                path: "order".to_owned(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use crate::gui::migration::*;
+use crate::gui::scene::Scene;
+
+use serde_json::{Map, Number, Value};
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Text format of scene file. YAML is written with multi-line strings as literal blocks, so GLSL code is readable in diffs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneFormat {
+    Json,
+    Yaml,
+}
+
+impl Default for SceneFormat {
+    fn default() -> Self {
+        SceneFormat::Json
+    }
+}
+
+impl SceneFormat {
+    /// JSON scene is always an object, so anything that doesn't start with `{` is treated as YAML.
+    pub fn detect(text: &str) -> Self {
+        if text.trim_start().starts_with('{') {
+            SceneFormat::Json
+        } else {
+            SceneFormat::Yaml
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "json" => Some(SceneFormat::Json),
+            "yaml" | "yml" => Some(SceneFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SceneFormat::Json => "json",
+            SceneFormat::Yaml => "yaml",
+        }
+    }
+}
+
+impl Scene {
+    /// Loads scene in any format and any known version.
+    pub fn from_text(text: &str) -> Result<Scene, String> {
+        let value = match SceneFormat::detect(text) {
+            SceneFormat::Json => serde_json::from_str(text).map_err(|err| err.to_string())?,
+            SceneFormat::Yaml => yaml_to_value(text)?,
+        };
+        serde_json::from_value(migrate(value)?).map_err(|err| err.to_string())
+    }
+
+    pub fn to_text(&self, format: SceneFormat) -> String {
+        let json = serde_json::to_string(self).unwrap();
+        match format {
+            SceneFormat::Json => json,
+            // Going through the text keeps `f32` numbers short, `to_value` would write them with `f64` precision.
+            SceneFormat::Yaml => value_to_yaml(&serde_json::from_str(&json).unwrap()),
+        }
+    }
+}
+
+const INDENT: usize = 2;
+
+fn is_plain(s: &str) -> bool {
+    const RESERVED: &[&str] = &["y", "n", "yes", "no", "true", "false", "on", "off", "null"];
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !RESERVED.contains(&&s.to_lowercase()[..])
+}
+
+fn is_block(s: &str) -> bool {
+    s.contains('\n') && !s.chars().any(|c| c.is_control() && c != '\n' && c != '\t')
+}
+
+fn write_key(s: &str) -> String {
+    if is_plain(s) {
+        s.to_owned()
+    } else {
+        // JSON string is a valid double-quoted YAML scalar.
+        Value::from(s).to_string()
+    }
+}
+
+/// Returns text of value if it can be written in one line.
+fn inline(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if is_block(s) => None,
+        Value::String(s) => Some(write_key(s)),
+        Value::Array(a) if a.is_empty() => Some("[]".to_owned()),
+        Value::Object(o) if o.is_empty() => Some("{}".to_owned()),
+        Value::Array(a) => {
+            let items = a
+                .iter()
+                .map(|x| match x {
+                    Value::Array(_) | Value::Object(_) => None,
+                    x => inline(x),
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!("[{}]", items.join(", ")))
+        }
+        Value::Object(_) => None,
+        other => Some(other.to_string()),
+    }
+}
+
+/// Writes literal block header and then lines of string with `indent`.
+fn write_block_string(out: &mut String, s: &str, indent: usize) {
+    let trailing = s.len() - s.trim_end_matches('\n').len();
+    let chomping = match trailing {
+        0 => "-",
+        1 => "",
+        _ => "+",
+    };
+    let indentation = if s.starts_with(char::is_whitespace) {
+        INDENT.to_string()
+    } else {
+        String::new()
+    };
+    out.push('|');
+    out.push_str(&indentation);
+    out.push_str(chomping);
+    out.push('\n');
+
+    let body = s.strip_suffix('\n').unwrap_or(s);
+    for line in body.split('\n') {
+        if !line.is_empty() {
+            out.push_str(&" ".repeat(indent));
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+}
+
+/// Writes value after `key: ` or `- `, `indent` is indentation of nested lines.
+fn write_value(out: &mut String, value: &Value, indent: usize) {
+    if let Some(s) = inline(value) {
+        out.push(' ');
+        out.push_str(&s);
+        out.push('\n');
+    } else if let Value::String(s) = value {
+        out.push(' ');
+        write_block_string(out, s, indent);
+    } else {
+        out.push('\n');
+        write_container(out, value, indent);
+    }
+}
+
+fn write_container(out: &mut String, value: &Value, indent: usize) {
+    let prefix = " ".repeat(indent);
+    match value {
+        Value::Object(o) => {
+            for (key, value) in o {
+                out.push_str(&prefix);
+                out.push_str(&write_key(key));
+                out.push(':');
+                write_value(out, value, indent + INDENT);
+            }
+        }
+        Value::Array(a) => {
+            for value in a {
+                out.push_str(&prefix);
+                out.push('-');
+                match value {
+                    Value::Object(_) | Value::Array(_) if inline(value).is_none() => {
+                        // First line of nested container goes right after `- `.
+                        let mut nested = String::new();
+                        write_container(&mut nested, value, indent + INDENT);
+                        out.push(' ');
+                        out.push_str(&nested[indent + INDENT..]);
+                    }
+                    _ => write_value(out, value, indent + INDENT),
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub fn value_to_yaml(value: &Value) -> String {
+    match inline(value) {
+        Some(s) => s + "\n",
+        None => {
+            let mut out = String::new();
+            if let Value::String(s) = value {
+                write_block_string(&mut out, s, INDENT);
+            } else {
+                write_container(&mut out, value, 0);
+            }
+            out
+        }
+    }
+}
+
+fn yaml_key(yaml: Yaml) -> Result<String, String> {
+    match yaml {
+        Yaml::String(s) | Yaml::Real(s) => Ok(s),
+        Yaml::Integer(i) => Ok(i.to_string()),
+        Yaml::Boolean(b) => Ok(b.to_string()),
+        other => Err(format!("unsupported key `{:?}`", other)),
+    }
+}
+
+fn yaml_value(yaml: Yaml) -> Result<Value, String> {
+    Ok(match yaml {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(b) => Value::Bool(b),
+        Yaml::Integer(i) => Value::from(i),
+        Yaml::String(s) => Value::String(s),
+        Yaml::Real(ref s) => yaml
+            .as_f64()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| format!("unsupported number `{}`", s))?,
+        Yaml::Array(a) => Value::Array(a.into_iter().map(yaml_value).collect::<Result<_, _>>()?),
+        Yaml::Hash(h) => Value::Object(
+            h.into_iter()
+                .map(|(k, v)| Ok((yaml_key(k)?, yaml_value(v)?)))
+                .collect::<Result<Map<_, _>, String>>()?,
+        ),
+        Yaml::Alias(_) => return Err("aliases are not supported".to_owned()),
+        Yaml::BadValue => return Err("bad value".to_owned()),
+    })
+}
+
+pub fn yaml_to_value(text: &str) -> Result<Value, String> {
+    let mut documents = YamlLoader::load_from_str(text).map_err(|err| err.to_string())?;
+    if documents.len() != 1 {
+        return Err(format!(
+            "expected one YAML document, got {}",
+            documents.len()
+        ));
+    }
+    yaml_value(documents.remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_scenes_roundtrip() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map(|x| x != "json").unwrap_or(true) {
+                continue;
+            }
+            let scene = Scene::from_text(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let yaml = scene.to_text(SceneFormat::Yaml);
+            assert_eq!(SceneFormat::detect(&yaml), SceneFormat::Yaml);
+            let loaded = Scene::from_text(&yaml)
+                .unwrap_or_else(|err| panic!("{}: {}\n{}", path.display(), err, yaml));
+            assert_eq!(
+                scene.to_text(SceneFormat::Json),
+                loaded.to_text(SceneFormat::Json),
+                "{}",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn multiline_strings_are_blocks() {
+        let value = serde_json::json!({
+            "code": "float a = 1.;\n\nreturn a;\n",
+            "no_newline": "a\n  b",
+            "indented": "  a\nb",
+            "keep": "a\n\n\n",
+            "list": [{ "x": [1, 2.5], "y_value": "true" }, "z", ["w"]],
+            "control": "a\r\nb",
+            "0": {},
+        });
+        let yaml = value_to_yaml(&value);
+        assert!(
+            yaml.contains("code: |\n  float a = 1.;\n\n  return a;\n"),
+            "{}",
+            yaml
+        );
+        assert!(yaml.contains("no_newline: |-\n"), "{}", yaml);
+        assert!(
+            yaml.contains("- x: [1, 2.5]\n    y_value: \"true\"\n"),
+            "{}",
+            yaml
+        );
+        assert_eq!(yaml_to_value(&yaml).unwrap(), value);
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(SceneFormat::detect("  {\"a\": 1}"), SceneFormat::Json);
+        assert_eq!(SceneFormat::detect("a: 1"), SceneFormat::Yaml);
+        assert!(yaml_to_value("a: [1").is_err());
+        assert!(yaml_to_value("a: 1\n---\nb: 2").is_err());
+    }
+}
@@ -28,25 +28,25 @@ impl StorageElem for LibraryCode {
     fn egui(
         &mut self,
         ui: &mut Ui,
-        pos: usize,
+        id: EntityId,
         input: &mut Self::Input,
         _: &[String],
     ) -> WhatChanged {
         let mut changed = WhatChanged::default();
-        egui_with_red_field(ui, input.get_errors(self, pos).is_some(), |ui| {
+        egui_with_red_field(ui, input.get_errors(self, id).is_some(), |ui| {
             changed = WhatChanged::from_shader(
                 ui.add(TextEdit::multiline(&mut self.0.0).text_style(TextStyle::Monospace))
                     .changed(),
             );
-            if let Some(local_errors) = input.get_errors(self, pos) {
+            if let Some(local_errors) = input.get_errors(self, id) {
                 egui_errors(ui, local_errors);
             }
         });
         changed
     }
 
-    fn errors_count(&self, pos: usize, input: &Self::Input, _: &[String]) -> usize {
-        if let Some(local_errors) = input.get_errors(self, pos) {
+    fn errors_count(&self, id: EntityId, input: &Self::Input, _: &[String]) -> usize {
+        if let Some(local_errors) = input.get_errors(self, id) {
             local_errors.len()
         } else {
             0
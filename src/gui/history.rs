@@ -0,0 +1,273 @@
+use crate::gui::common::WhatChanged;
+use crate::gui::scene::Scene;
+
+use egui::*;
+use serde_json::Value;
+
+/// Edits that follow each other faster than this are merged into one entry, so dragging a value doesn't flood the history.
+const MERGE_SECONDS: f64 = 0.5;
+
+const MAX_ENTRIES: usize = 100;
+
+/// Camera is changed by mouse all the time, it's not an edit of the scene.
+const IGNORED_FIELDS: &[&str] = &["cam"];
+
+#[derive(Debug)]
+struct HistoryEntry {
+    description: String,
+
+    /// What should be updated after moving from previous entry to this one or back.
+    changed: WhatChanged,
+
+    scene: Value,
+}
+
+/// Undo/redo stack of scene snapshots.
+#[derive(Debug)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    current: usize,
+    last_edit_time: Option<f64>,
+}
+
+fn changed_fields<'a>(previous: &'a Value, current: &'a Value) -> Vec<&'a str> {
+    match (previous.as_object(), current.as_object()) {
+        (Some(previous), Some(current)) => current
+            .iter()
+            .filter(|(name, _)| !IGNORED_FIELDS.contains(&&name[..]))
+            .filter(|(name, value)| previous.get(*name) != Some(*value))
+            .map(|(name, _)| &name[..])
+            .collect(),
+        _ => vec![""],
+    }
+}
+
+fn describe(previous: &Value, current: &Value, changed: &WhatChanged) -> String {
+    let fields = changed_fields(previous, current)
+        .iter()
+        .map(|name| name.replace('_', " "))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let fields = if fields.is_empty() {
+        "scene".to_owned()
+    } else {
+        fields
+    };
+    if changed.shader {
+        format!("Edit {} (recompile)", fields)
+    } else {
+        format!("Edit {}", fields)
+    }
+}
+
+impl History {
+    pub fn new(scene: &Scene, description: &str) -> Self {
+        Self {
+            entries: vec![HistoryEntry {
+                description: description.to_owned(),
+                changed: WhatChanged::default(),
+                scene: serde_json::to_value(scene).unwrap(),
+            }],
+            current: 0,
+            last_edit_time: None,
+        }
+    }
+
+    /// Records scene after edit. Does nothing if the scene is the same as in the current entry, for example after pressing `Recompile`.
+    pub fn push(&mut self, scene: &Scene, changed: WhatChanged, time: f64) {
+        let value = serde_json::to_value(scene).unwrap();
+        if changed_fields(&self.entries[self.current].scene, &value).is_empty() {
+            return;
+        }
+
+        self.entries.truncate(self.current + 1);
+
+        let merge = self.current > 0
+            && self
+                .last_edit_time
+                .map(|last| time - last < MERGE_SECONDS)
+                .unwrap_or(false);
+        self.last_edit_time = Some(time);
+
+        if merge {
+            let (previous, current) = self.entries.split_at_mut(self.current);
+            let entry = &mut current[0];
+            entry.changed |= changed;
+            entry.description = describe(&previous[self.current - 1].scene, &value, &entry.changed);
+            entry.scene = value;
+        } else {
+            let description = describe(&self.entries[self.current].scene, &value, &changed);
+            self.entries.push(HistoryEntry {
+                description,
+                changed,
+                scene: value,
+            });
+            if self.entries.len() > MAX_ENTRIES {
+                self.entries.remove(0);
+            }
+            self.current = self.entries.len() - 1;
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+
+    pub fn undo(&mut self) -> Option<(Scene, WhatChanged)> {
+        if self.can_undo() {
+            self.go_to(self.current - 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn redo(&mut self) -> Option<(Scene, WhatChanged)> {
+        if self.can_redo() {
+            self.go_to(self.current + 1)
+        } else {
+            None
+        }
+    }
+
+    /// Returns scene of entry at `pos` and everything that changed between it and the current entry.
+    pub fn go_to(&mut self, pos: usize) -> Option<(Scene, WhatChanged)> {
+        if pos == self.current || pos >= self.entries.len() {
+            return None;
+        }
+
+        let (from, to) = if pos < self.current {
+            (pos + 1, self.current)
+        } else {
+            (self.current + 1, pos)
+        };
+        let mut changed = WhatChanged::default();
+        for entry in &self.entries[from..=to] {
+            changed |= entry.changed.clone();
+        }
+
+        self.current = pos;
+        self.last_edit_time = None;
+
+        let scene = serde_json::from_value(self.entries[pos].scene.clone()).unwrap();
+        Some((scene, changed))
+    }
+
+    pub fn egui(&mut self, ui: &mut Ui) -> Option<(Scene, WhatChanged)> {
+        let mut result = None;
+        ui.horizontal(|ui| {
+            if ui
+                .add(Button::new("⟲ Undo").enabled(self.can_undo()))
+                .on_hover_text("Ctrl+Z")
+                .clicked()
+            {
+                result = self.undo();
+            }
+            if ui
+                .add(Button::new("⟳ Redo").enabled(self.can_redo()))
+                .on_hover_text("Ctrl+Shift+Z")
+                .clicked()
+            {
+                result = self.redo();
+            }
+        });
+        ui.separator();
+        let mut go_to = None;
+        for (pos, entry) in self.entries.iter().enumerate().rev() {
+            if ui
+                .selectable_label(pos == self.current, &entry.description)
+                .clicked()
+            {
+                go_to = Some(pos);
+            }
+        }
+        if let Some(pos) = go_to {
+            result = self.go_to(pos);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> Scene {
+        Scene::from_json(include_str!("../../scenes/empty.json")).unwrap()
+    }
+
+    fn edit(scene: &mut Scene, text: &str) {
+        scene.descriptions.0.insert("en".to_owned(), text.to_owned());
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut scene = scene();
+        let original = scene.descriptions.clone();
+        let mut history = History::new(&scene, "Load");
+        assert!(history.undo().is_none());
+
+        edit(&mut scene, "a");
+        history.push(&scene, WhatChanged::from_shader(true), 0.);
+        edit(&mut scene, "b");
+        history.push(&scene, WhatChanged::from_uniform(true), 10.);
+        assert_eq!(history.entries.len(), 3);
+        assert_eq!(
+            history.entries[1].description,
+            "Edit descriptions (recompile)"
+        );
+
+        let (scene, changed) = history.undo().unwrap();
+        assert_eq!(scene.descriptions.0["en"], "a");
+        assert!(changed.uniform && !changed.shader);
+
+        let (scene, changed) = history.undo().unwrap();
+        assert_eq!(scene.descriptions, original);
+        assert!(!changed.uniform && changed.shader);
+        assert!(history.undo().is_none());
+
+        let (scene, changed) = history.go_to(2).unwrap();
+        assert_eq!(scene.descriptions.0["en"], "b");
+        assert!(changed.uniform && changed.shader);
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn edit_after_undo_drops_redo() {
+        let mut scene = scene();
+        let original = scene.descriptions.clone();
+        let mut history = History::new(&scene, "Load");
+        edit(&mut scene, "a");
+        history.push(&scene, WhatChanged::from_uniform(true), 0.);
+        let (mut scene, _) = history.undo().unwrap();
+        edit(&mut scene, "b");
+        history.push(&scene, WhatChanged::from_uniform(true), 0.1);
+        assert_eq!(history.entries.len(), 2);
+        assert!(!history.can_redo());
+        assert_eq!(history.undo().unwrap().0.descriptions, original);
+    }
+
+    #[test]
+    fn continuous_edits_are_merged() {
+        let mut scene = scene();
+        let original = scene.descriptions.clone();
+        let mut history = History::new(&scene, "Load");
+        for (i, time) in [0., 0.1, 0.2, 0.3].iter().enumerate() {
+            edit(&mut scene, &i.to_string());
+            history.push(&scene, WhatChanged::from_uniform(true), *time);
+        }
+        assert_eq!(history.entries.len(), 2);
+
+        history.push(&scene, WhatChanged::from_uniform(true), 0.4);
+        assert_eq!(history.entries.len(), 2);
+
+        scene.cam.r += 1.;
+        history.push(&scene, WhatChanged::from_uniform(true), 10.);
+        assert_eq!(history.entries.len(), 2);
+
+        assert_eq!(history.undo().unwrap().0.descriptions, original);
+    }
+}
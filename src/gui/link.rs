@@ -0,0 +1,216 @@
+use crate::gui::migration::*;
+use crate::gui::scene::Scene;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Contents of shareable link before compression. Camera and current animation stage are fields of `Scene`, so they are shared too.
+#[derive(Debug, Serialize, Deserialize)]
+struct Link {
+    /// Version of scene format when link was made, scene is migrated from it after the patch is applied.
+    version: u32,
+
+    /// Id of embedded scene, when it is present `scene` is a patch for it.
+    base: Option<String>,
+
+    scene: Value,
+}
+
+/// Returns JSON Merge Patch (RFC 7386) that turns `from` into `to`.
+fn diff(from: &Value, to: &Value) -> Value {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let mut patch = Map::new();
+            for (key, value) in to {
+                match from.get(key) {
+                    Some(old) if old == value => {}
+                    Some(old) => {
+                        patch.insert(key.clone(), diff(old, value));
+                    }
+                    None => {
+                        patch.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            for key in from.keys() {
+                if !to.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+            Value::Object(patch)
+        }
+        _ => to.clone(),
+    }
+}
+
+fn apply(value: &mut Value, patch: &Value) {
+    match patch {
+        Value::Object(patch) => {
+            if !value.is_object() {
+                *value = Value::Object(Map::new());
+            }
+            let value = value.as_object_mut().unwrap();
+            for (key, patch) in patch {
+                if patch.is_null() {
+                    value.remove(key);
+                } else {
+                    apply(value.entry(key.clone()).or_insert(Value::Null), patch);
+                }
+            }
+        }
+        _ => *value = patch.clone(),
+    }
+}
+
+fn encode(link: &Link) -> String {
+    let json = serde_json::to_vec(link).unwrap();
+    let compressed = miniz_oxide::deflate::compress_to_vec(&json, 10);
+    base64::encode_config(&compressed, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(text: &str) -> Result<Link, String> {
+    let compressed = base64::decode_config(text.trim(), base64::URL_SAFE_NO_PAD)
+        .map_err(|err| format!("link is not base64url: {}", err))?;
+    let json = miniz_oxide::inflate::decompress_to_vec(&compressed)
+        .map_err(|err| format!("can't decompress link: {:?}", err))?;
+    serde_json::from_slice(&json).map_err(|err| format!("broken link: {}", err))
+}
+
+impl Scene {
+    /// Encodes scene into compact string. When `base` is given and the patch for it is smaller than the whole scene, only the patch is stored.
+    pub fn to_link(&self, base: Option<(&str, &Scene)>) -> String {
+        let scene = serde_json::to_value(self).unwrap();
+        let full = Link {
+            version: SCENE_VERSION,
+            base: None,
+            scene: scene.clone(),
+        };
+        let patched = base.and_then(|(id, base)| {
+            let mut base = serde_json::to_value(base).unwrap();
+            let patch = diff(&base, &scene);
+            // Merge patch can't set value to `null`, so check that patch really gives the same scene.
+            apply(&mut base, &patch);
+            if base == scene {
+                Some(Link {
+                    version: SCENE_VERSION,
+                    base: Some(id.to_owned()),
+                    scene: patch,
+                })
+            } else {
+                None
+            }
+        });
+
+        let full = encode(&full);
+        match patched.map(|link| encode(&link)) {
+            Some(patched) if patched.len() < full.len() => patched,
+            _ => full,
+        }
+    }
+
+    /// Decodes string from `to_link`, `get_base` returns embedded scene by its id.
+    pub fn from_link<F>(text: &str, get_base: F) -> Result<Scene, String>
+    where
+        F: FnOnce(&str) -> Result<Scene, String>,
+    {
+        let link = decode(text)?;
+        let value = match &link.base {
+            Some(id) => {
+                // Base of old version is not available, so old patch is applied to the current base and the result is migrated from the version of the link.
+                let mut base = serde_json::to_value(get_base(id)?).unwrap();
+                apply(&mut base, &link.scene);
+                base["version"] = Value::from(link.version);
+                base
+            }
+            None => link.scene,
+        };
+        serde_json::from_value(migrate(value)?).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(text: &str) -> Scene {
+        Scene::from_json(text).unwrap()
+    }
+
+    fn same(a: &Scene, b: &Scene) -> bool {
+        serde_json::to_value(a).unwrap() == serde_json::to_value(b).unwrap()
+    }
+
+    #[test]
+    fn full_scene() {
+        let mobius = scene(include_str!("../../scenes/mobius.json"));
+        let link = mobius.to_link(None);
+        assert!(link
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        let decoded = Scene::from_link(&link, |_| Err("no base".to_owned())).unwrap();
+        assert!(same(&mobius, &decoded));
+    }
+
+    #[test]
+    fn patch_for_base() {
+        let base = scene(include_str!("../../scenes/mobius.json"));
+        let mut edited = base.clone();
+        edited.cam.alpha += 1.;
+        edited.descriptions.0.insert("en".to_owned(), "edited".to_owned());
+
+        let link = edited.to_link(Some(("mobius", &base)));
+        assert!(link.len() < edited.to_link(None).len());
+        assert!(Scene::from_link(&link, |_| Err("no base".to_owned())).is_err());
+
+        let decoded = Scene::from_link(&link, |id| {
+            assert_eq!(id, "mobius");
+            Ok(base.clone())
+        })
+        .unwrap();
+        assert!(same(&edited, &decoded));
+    }
+
+    #[test]
+    fn patch_of_old_version() {
+        let base = scene(include_str!("../../scenes/mobius.json"));
+        let link = encode(&Link {
+            version: 3,
+            base: Some("mobius".to_owned()),
+            scene: serde_json::json!({ "description_en": "old" }),
+        });
+        let decoded = Scene::from_link(&link, |_| Ok(base.clone())).unwrap();
+
+        let mut expected = base.clone();
+        expected
+            .descriptions
+            .0
+            .insert("en".to_owned(), "old".to_owned());
+        assert!(same(&expected, &decoded));
+    }
+
+    #[test]
+    fn merge_patch() {
+        let from = serde_json::json!({ "a": 1, "b": { "c": [1, 2], "d": 2 }, "e": 3 });
+        let to = serde_json::json!({ "a": 1, "b": { "c": [1], "d": 2 }, "f": { "g": 4 } });
+        let patch = diff(&from, &to);
+        assert_eq!(
+            patch,
+            serde_json::json!({ "b": { "c": [1] }, "e": null, "f": { "g": 4 } })
+        );
+        let mut value = from;
+        apply(&mut value, &patch);
+        assert_eq!(value, to);
+    }
+
+    #[test]
+    fn broken_links() {
+        let no_base = |_: &str| Err("no base".to_owned());
+        assert!(Scene::from_link("not base64!", no_base).is_err());
+        assert!(Scene::from_link("AAAA", no_base).is_err());
+        let json = base64::encode_config(
+            miniz_oxide::deflate::compress_to_vec(b"{}", 10),
+            base64::URL_SAFE_NO_PAD,
+        );
+        assert!(Scene::from_link(&json, no_base).is_err());
+    }
+}
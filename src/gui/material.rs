@@ -44,8 +44,8 @@ impl Default for Material {
 }
 
 impl Material {
-    pub fn errors_count(&self, pos: usize, errors: &ShaderErrors) -> usize {
-        if let Some(local_errors) = errors.get_errors(self, pos) {
+    pub fn errors_count(&self, id: EntityId, errors: &ShaderErrors) -> usize {
+        if let Some(local_errors) = errors.get_errors(self, id) {
             local_errors.len()
         } else {
             0
@@ -69,19 +69,24 @@ impl StorageElem for MaterialComboBox {
     fn egui(
         &mut self,
         ui: &mut Ui,
-        pos: usize,
+        id: EntityId,
         input: &mut Self::Input,
         _: &[String],
     ) -> WhatChanged {
-        let mut changed =
-            WhatChanged::from_shader(egui_combo_box(ui, "Type:", 45., &mut self.0, pos));
+        let mut changed = WhatChanged::from_shader(egui_combo_box(
+            ui,
+            "Type:",
+            45.,
+            &mut self.0,
+            id.0 as usize,
+        ));
         ui.separator();
-        changed |= self.0.egui(ui, pos, input);
+        changed |= self.0.egui(ui, id, input);
         changed
     }
 
-    fn errors_count(&self, pos: usize, input: &Self::Input, _: &[String]) -> usize {
-        self.0.errors_count(pos, &input)
+    fn errors_count(&self, id: EntityId, input: &Self::Input, _: &[String]) -> usize {
+        self.0.errors_count(id, &input)
     }
 }
 
@@ -118,10 +123,10 @@ impl ComboBoxChoosable for Material {
 }
 
 impl Material {
-    fn egui(&mut self, ui: &mut Ui, pos: usize, errors: &mut ShaderErrors) -> WhatChanged {
+    fn egui(&mut self, ui: &mut Ui, id: EntityId, errors: &mut ShaderErrors) -> WhatChanged {
         use Material::*;
         let mut changed = false;
-        let has_errors = errors.get_errors(&*self, pos).is_some();
+        let has_errors = errors.get_errors(&*self, id).is_some();
         match self {
             Simple {
                 color,
@@ -239,11 +244,16 @@ impl Material {
                 });
                 ui.add(Label::new("}").monospace());
 
-                if let Some(local_errors) = errors.get_errors(self, pos) {
+                if let Some(local_errors) = errors.get_errors(self, id) {
                     egui_errors(ui, local_errors);
                 }
             }
         }
+        if !matches!(self, Complex { .. }) {
+            if let Some(local_errors) = errors.get_errors(self, id) {
+                egui_errors(ui, local_errors);
+            }
+        }
         WhatChanged::from_shader(changed)
     }
 }
@@ -1,7 +1,6 @@
 use crate::get_try;
 use crate::gui::combo_box::*;
 use crate::gui::common::*;
-use crate::gui::object::*;
 use crate::gui::storage::*;
 use crate::gui::uniform::*;
 
@@ -208,7 +207,7 @@ impl Matrix {
     pub fn egui(
         &mut self,
         ui: &mut Ui,
-        pos: usize,
+        id: EntityId,
         input: &mut megatuple!(Vec<String>, MatrixRecursionError),
         names: &[String],
     ) -> WhatChanged {
@@ -318,7 +317,7 @@ impl Matrix {
         }
         if matrix_recursion_error
             .0
-            .get(&MatrixName(names[pos].clone()))
+            .get(&id)
             .copied()
             .unwrap_or(false)
         {
@@ -406,26 +405,26 @@ impl StorageElem for MatrixComboBox {
     fn egui(
         &mut self,
         ui: &mut Ui,
-        pos: usize,
+        id: EntityId,
         input: &mut Self::Input,
         names: &[String],
     ) -> WhatChanged {
         let mut changed =
             WhatChanged::from_uniform(egui_combo_label(ui, "Type:", 45., &mut self.0));
         ui.separator();
-        changed |= self.0.egui(ui, pos, input, names);
+        changed |= self.0.egui(ui, id, input, names);
         changed
     }
 
-    fn errors_count(&self, pos: usize, input: &Self::Input, names: &[String]) -> usize {
-        self.0.errors_count(pos, input, names)
+    fn errors_count(&self, id: EntityId, input: &Self::Input, names: &[String]) -> usize {
+        self.0.errors_count(id, input, names)
     }
 }
 
 impl Matrix {
     pub fn errors_count(
         &self,
-        pos: usize,
+        id: EntityId,
         input: &megatuple!(Vec<String>, MatrixRecursionError),
         names: &[String],
     ) -> usize {
@@ -477,7 +476,7 @@ impl Matrix {
         }
         if matrix_recursion_error
             .0
-            .get(&MatrixName(names[pos].clone()))
+            .get(&id)
             .copied()
             .unwrap_or(false)
         {
@@ -0,0 +1,268 @@
+use serde_json::{Map, Value};
+
+/// Upgrades scene JSON from version `i + 1` to version `i + 2`, where `i` is index in `MIGRATIONS`. Field `version` is set by `migrate` after each step, so migration must change only the data itself. Patches from old shareable links are applied to the current version of embedded scene before migration, so migration must keep parts that are already in newer format.
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+const MIGRATIONS: &[Migration] = &[v1_to_v2, v2_to_v3, v3_to_v4];
+
+/// Version of scenes that are produced by current code.
+pub const SCENE_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+/// Version 1 is the format before `version` field was introduced, scenes without this field are treated as version 1.
+fn v1_to_v2(_: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+const STORAGES: &[&str] = &[
+    "uniforms",
+    "matrices",
+    "objects",
+    "textures",
+    "materials",
+    "library",
+    "animation_stages",
+];
+
+fn get_object<'a>(
+    value: &'a mut Map<String, Value>,
+    name: &str,
+) -> Result<&'a mut Map<String, Value>, String> {
+    value
+        .get_mut(name)
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| format!("`{}` must be an object", name))
+}
+
+fn get_array<'a>(
+    value: &'a mut Map<String, Value>,
+    name: &str,
+) -> Result<&'a mut Vec<Value>, String> {
+    value
+        .get_mut(name)
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| format!("`{}` must be an array", name))
+}
+
+/// Version 3 adds persistent identifiers to elements of storages, animation stages and global user uniforms refer to elements by identifier instead of position. Existing elements get their positions as identifiers.
+fn v2_to_v3(scene: &mut Map<String, Value>) -> Result<(), String> {
+    for name in STORAGES {
+        let storage = get_object(scene, name)?;
+        let len = get_array(storage, "names")?.len();
+        storage.insert("ids".to_owned(), Value::from((0..len).collect::<Vec<_>>()));
+        storage.insert("next_id".to_owned(), Value::from(len));
+    }
+
+    let uniforms_len = get_array(get_object(scene, "uniforms")?, "names")?.len();
+    let matrices_len = get_array(get_object(scene, "matrices")?, "names")?.len();
+
+    let positions_to_ids = |array: &mut Vec<Value>, len: usize| -> Value {
+        Value::Object(
+            array
+                .drain(..)
+                .take(len)
+                .enumerate()
+                .map(|(pos, x)| (pos.to_string(), x))
+                .collect(),
+        )
+    };
+    for stage in get_array(get_object(scene, "animation_stages")?, "storage")? {
+        let stage = stage
+            .as_object_mut()
+            .ok_or_else(|| "animation stage must be an object".to_owned())?;
+        let uniforms = positions_to_ids(get_array(stage, "uniforms")?, uniforms_len);
+        stage.insert("uniforms".to_owned(), uniforms);
+        let matrices = positions_to_ids(get_array(stage, "matrices")?, matrices_len);
+        stage.insert("matrices".to_owned(), matrices);
+    }
+
+    let flags_to_ids = |array: &mut Vec<Value>, len: usize| -> Value {
+        Value::from(
+            array
+                .iter()
+                .take(len)
+                .enumerate()
+                .filter(|(_, x)| x.as_bool().unwrap_or(false))
+                .map(|(pos, _)| pos)
+                .collect::<Vec<_>>(),
+        )
+    };
+    let user_uniforms = get_object(scene, "user_uniforms")?;
+    let uniforms = flags_to_ids(get_array(user_uniforms, "uniforms")?, uniforms_len);
+    user_uniforms.insert("uniforms".to_owned(), uniforms);
+    let matrices = flags_to_ids(get_array(user_uniforms, "matrices")?, matrices_len);
+    user_uniforms.insert("matrices".to_owned(), matrices);
+
+    Ok(())
+}
+
+/// Version 4 stores descriptions as map from locale to text instead of fixed `description_en` and `description_ru` fields. Empty descriptions are dropped.
+fn v3_to_v4(scene: &mut Map<String, Value>) -> Result<(), String> {
+    let mut descriptions = match scene.remove("descriptions") {
+        Some(Value::Object(descriptions)) => descriptions,
+        _ => Map::new(),
+    };
+    for (field, locale) in &[("description_en", "en"), ("description_ru", "ru")] {
+        match scene.remove(*field) {
+            None => {}
+            Some(Value::String(text)) if text.is_empty() => {}
+            Some(Value::String(text)) => {
+                descriptions.insert(locale.to_string(), Value::String(text));
+            }
+            Some(_) => return Err(format!("`{}` must be a string", field)),
+        }
+    }
+    scene.insert("descriptions".to_owned(), Value::Object(descriptions));
+    Ok(())
+}
+
+/// Upgrades scene of any known version to `SCENE_VERSION`.
+pub fn migrate(mut value: Value) -> Result<Value, String> {
+    {
+        let scene = value
+            .as_object_mut()
+            .ok_or_else(|| "scene must be a JSON object".to_owned())?;
+
+        let mut version = match scene.get("version") {
+            None => 1,
+            Some(v) => v
+                .as_u64()
+                .filter(|v| *v <= u32::MAX as u64)
+                .ok_or_else(|| format!("`version` must be a positive integer, got `{}`", v))?
+                as u32,
+        };
+
+        if version == 0 || version > SCENE_VERSION {
+            return Err(format!(
+                "unsupported scene version {}, latest known version is {}",
+                version, SCENE_VERSION
+            ));
+        }
+
+        while version < SCENE_VERSION {
+            MIGRATIONS[version as usize - 1](scene).map_err(|err| {
+                format!(
+                    "can't migrate scene from version {} to {}: {}",
+                    version,
+                    version + 1,
+                    err
+                )
+            })?;
+            version += 1;
+            scene.insert("version".to_owned(), Value::from(version));
+        }
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::scene::Scene;
+
+    fn bundled_scenes() -> Vec<(String, String)> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut result: Vec<(String, String)> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map(|x| x == "json").unwrap_or(false))
+            .map(|path| {
+                (
+                    path.display().to_string(),
+                    std::fs::read_to_string(&path).unwrap(),
+                )
+            })
+            .collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn bundled_scenes_load() {
+        let scenes = bundled_scenes();
+        assert!(!scenes.is_empty());
+        for (path, text) in scenes {
+            let scene = Scene::from_json(&text).unwrap_or_else(|err| panic!("{}: {}", path, err));
+            assert_eq!(scene.version, SCENE_VERSION, "{}", path);
+
+            let saved = serde_json::to_value(&scene).unwrap();
+            let loaded = Scene::from_json(&saved.to_string()).unwrap();
+            assert_eq!(saved, serde_json::to_value(&loaded).unwrap(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn version_is_set() {
+        let mut value: Value =
+            serde_json::from_str(include_str!("../../scenes/empty.json")).unwrap();
+        value.as_object_mut().unwrap().remove("version");
+        let migrated = migrate(value.clone()).unwrap();
+        assert_eq!(migrated["version"], Value::from(SCENE_VERSION));
+
+        value["version"] = Value::from(1);
+        assert_eq!(migrate(value).unwrap(), migrated);
+    }
+
+    #[test]
+    fn positions_become_ids() {
+        let storage = |len: usize| serde_json::json!({ "names": vec![""; len], "storage": [] });
+        let value = migrate(serde_json::json!({
+            "version": 2,
+            "uniforms": storage(2),
+            "matrices": storage(1),
+            "objects": storage(0),
+            "textures": storage(0),
+            "materials": storage(0),
+            "library": storage(0),
+            "animation_stages": storage(1),
+            "user_uniforms": { "uniforms": [false, true, true], "matrices": [true] },
+        }))
+        .unwrap();
+        assert_eq!(value["uniforms"]["ids"], serde_json::json!([0, 1]));
+        assert_eq!(value["uniforms"]["next_id"], serde_json::json!(2));
+        assert_eq!(value["user_uniforms"]["uniforms"], serde_json::json!([1]));
+        assert_eq!(value["user_uniforms"]["matrices"], serde_json::json!([0]));
+
+        let value = migrate(serde_json::json!({
+            "version": 2,
+            "uniforms": storage(2),
+            "matrices": storage(1),
+            "objects": storage(0),
+            "textures": storage(0),
+            "materials": storage(0),
+            "library": storage(0),
+            "animation_stages": {
+                "names": ["a"],
+                "storage": [{ "uniforms": ["Remains", "ProvidedToUser"], "matrices": [] }],
+            },
+            "user_uniforms": { "uniforms": [], "matrices": [] },
+        }))
+        .unwrap();
+        assert_eq!(
+            value["animation_stages"]["storage"][0],
+            serde_json::json!({
+                "uniforms": { "0": "Remains", "1": "ProvidedToUser" },
+                "matrices": {},
+            })
+        );
+    }
+
+    #[test]
+    fn descriptions_become_map() {
+        let mut value: Value =
+            serde_json::from_str(include_str!("../../scenes/empty.json")).unwrap();
+        value["description_en"] = Value::from("text");
+        value["description_ru"] = Value::from("");
+        let value = migrate(value).unwrap();
+        assert_eq!(value["descriptions"], serde_json::json!({ "en": "text" }));
+        assert!(value.get("description_en").is_none());
+        assert!(value.get("description_ru").is_none());
+    }
+
+    #[test]
+    fn unknown_versions() {
+        assert!(migrate(serde_json::json!({ "version": 0 })).is_err());
+        assert!(migrate(serde_json::json!({ "version": SCENE_VERSION + 1 })).is_err());
+        assert!(migrate(serde_json::json!({ "version": "2" })).is_err());
+        assert!(migrate(serde_json::json!([])).is_err());
+    }
+}
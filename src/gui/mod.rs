@@ -1,12 +1,21 @@
 pub mod animation;
 pub mod combo_box;
 pub mod common;
+pub mod description;
+pub mod diff;
+pub mod format;
 pub mod glsl;
+pub mod history;
+pub mod link;
 pub mod material;
 pub mod matrix;
+pub mod migration;
 pub mod object;
+pub mod prefab;
+pub mod rename;
 pub mod scene;
 #[macro_use]
 pub mod storage;
 pub mod texture;
 pub mod uniform;
+pub mod validation;
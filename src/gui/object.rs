@@ -1,3 +1,4 @@
+use crate::bvh::Aabb;
 use crate::gui::combo_box::*;
 use crate::gui::common::*;
 use crate::gui::glsl::*;
@@ -5,6 +6,7 @@ use crate::gui::storage::*;
 use crate::gui::uniform::*;
 
 use egui::*;
+use glam::*;
 
 use serde::{Deserialize, Serialize};
 
@@ -27,6 +29,16 @@ impl MatrixName {
     }
 }
 
+/// Empty by default, the first existing material is chosen when object becomes `Primitive` in interface.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd, Default)]
+pub struct MaterialName(pub String);
+
+impl MaterialName {
+    pub fn define_name(&self) -> String {
+        format!("{}_M", self.0)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ObjectType {
     Simple(MatrixName),
@@ -43,7 +55,51 @@ pub enum Object {
     Complex {
         kind: ObjectType,
         intersect: IntersectCode, // gets transformed Ray, must return SurfaceIntersect
+        #[serde(default)]
+        bounds: BoundingVolume,
+    },
+    Primitive {
+        matrix: MatrixName,
+        shape: Primitive,
+        material: MaterialName,
+    },
+}
+
+/// Shape with intersection code from library, in coordinates of object. Every parameter can be taken from uniform.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Primitive {
+    Sphere {
+        radius: ParametrizeOrNot,
+    },
+    /// Box from `-size` to `size`.
+    Box {
+        size: TVec3<ParametrizeOrNot>,
+    },
+    /// Cylinder and cone are around `z` axis, with base at `z = 0`.
+    Cylinder {
+        radius: ParametrizeOrNot,
+        height: ParametrizeOrNot,
     },
+    Cone {
+        radius: ParametrizeOrNot,
+        height: ParametrizeOrNot,
+    },
+    /// Torus and disk lie in plane `z = 0`.
+    Torus {
+        major_radius: ParametrizeOrNot,
+        minor_radius: ParametrizeOrNot,
+    },
+    Disk {
+        radius: ParametrizeOrNot,
+    },
+}
+
+/// Volume in coordinates of object that contains the whole object. `intersect` is not called for rays that miss it, or that already hit something nearer.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum BoundingVolume {
+    None,
+    Sphere { center: Vec3, radius: f32 },
+    Box { min: Vec3, max: Vec3 },
 }
 
 impl Default for MatrixName {
@@ -52,12 +108,26 @@ impl Default for MatrixName {
     }
 }
 
+impl Default for Primitive {
+    fn default() -> Self {
+        Primitive::Sphere {
+            radius: ParametrizeOrNot::No(1.),
+        }
+    }
+}
+
 impl Default for ObjectType {
     fn default() -> Self {
         Self::Simple(Default::default())
     }
 }
 
+impl Default for BoundingVolume {
+    fn default() -> Self {
+        BoundingVolume::None
+    }
+}
+
 impl Default for Object {
     fn default() -> Self {
         Object::DebugMatrix(Default::default())
@@ -87,7 +157,7 @@ impl ComboBoxChoosable for ObjectType {
 
 impl ComboBoxChoosable for Object {
     fn variants() -> &'static [&'static str] {
-        &["Debug", "Flat", "Complex"]
+        &["Debug", "Flat", "Complex", "Primitive"]
     }
     fn get_number(&self) -> usize {
         use Object::*;
@@ -95,6 +165,7 @@ impl ComboBoxChoosable for Object {
             DebugMatrix { .. } => 0,
             Flat { .. } => 1,
             Complex { .. } => 2,
+            Primitive { .. } => 3,
         }
     }
     fn set_number(&mut self, number: usize) {
@@ -108,12 +179,295 @@ impl ComboBoxChoosable for Object {
             2 => Complex {
                 kind: Default::default(),
                 intersect: Default::default(),
+                bounds: Default::default(),
+            },
+            3 => Primitive {
+                matrix: Default::default(),
+                shape: Default::default(),
+                material: Default::default(),
             },
             _ => unreachable!(),
         };
     }
 }
 
+impl ComboBoxChoosable for Primitive {
+    fn variants() -> &'static [&'static str] {
+        &["Sphere", "Box", "Cylinder", "Cone", "Torus", "Disk"]
+    }
+    fn get_number(&self) -> usize {
+        use Primitive::*;
+        match self {
+            Sphere { .. } => 0,
+            Box { .. } => 1,
+            Cylinder { .. } => 2,
+            Cone { .. } => 3,
+            Torus { .. } => 4,
+            Disk { .. } => 5,
+        }
+    }
+    fn set_number(&mut self, number: usize) {
+        use ParametrizeOrNot::No;
+        use Primitive::*;
+        *self = match number {
+            0 => Sphere { radius: No(1.) },
+            1 => Box {
+                size: TVec3 {
+                    x: No(1.),
+                    y: No(1.),
+                    z: No(1.),
+                },
+            },
+            2 => Cylinder {
+                radius: No(1.),
+                height: No(1.),
+            },
+            3 => Cone {
+                radius: No(1.),
+                height: No(1.),
+            },
+            4 => Torus {
+                major_radius: No(1.),
+                minor_radius: No(0.25),
+            },
+            5 => Disk { radius: No(1.) },
+            _ => unreachable!(),
+        };
+    }
+}
+
+impl ComboBoxChoosable for BoundingVolume {
+    fn variants() -> &'static [&'static str] {
+        &["None", "Sphere", "Box"]
+    }
+    fn get_number(&self) -> usize {
+        use BoundingVolume::*;
+        match self {
+            None => 0,
+            Sphere { .. } => 1,
+            Box { .. } => 2,
+        }
+    }
+    fn set_number(&mut self, number: usize) {
+        use BoundingVolume::*;
+        *self = match number {
+            0 => None,
+            1 => Sphere {
+                center: Vec3::default(),
+                radius: 1.,
+            },
+            2 => Box {
+                min: Vec3::splat(-1.),
+                max: Vec3::splat(1.),
+            },
+            _ => unreachable!(),
+        };
+    }
+}
+
+impl BoundingVolume {
+    pub fn egui(&mut self, ui: &mut Ui) -> WhatChanged {
+        let mut is_changed = egui_combo_label(ui, "Bounds:", 45., self);
+        let vec3 = |ui: &mut Ui, label: &str, value: &mut Vec3| {
+            let mut is_changed = false;
+            ui.label(label);
+            ui.centered_and_justified(|ui| is_changed |= egui_f32(ui, &mut value.x));
+            ui.centered_and_justified(|ui| is_changed |= egui_f32(ui, &mut value.y));
+            ui.centered_and_justified(|ui| is_changed |= egui_f32(ui, &mut value.z));
+            ui.end_row();
+            is_changed
+        };
+        match self {
+            BoundingVolume::None => {}
+            BoundingVolume::Sphere { center, radius } => {
+                Grid::new("bounds")
+                    .striped(true)
+                    .min_col_width(45.)
+                    .max_col_width(45.)
+                    .show(ui, |ui| {
+                        is_changed |= vec3(ui, "Center: ", center);
+                        ui.label("Radius: ");
+                        is_changed |= egui_f32_positive(ui, radius);
+                        ui.end_row();
+                    });
+            }
+            BoundingVolume::Box { min, max } => {
+                Grid::new("bounds")
+                    .striped(true)
+                    .min_col_width(45.)
+                    .max_col_width(45.)
+                    .show(ui, |ui| {
+                        is_changed |= vec3(ui, "Min: ", min);
+                        is_changed |= vec3(ui, "Max: ", max);
+                    });
+            }
+        }
+        WhatChanged::from_shader(is_changed)
+    }
+
+    /// Box that contains the volume.
+    pub fn aabb(&self) -> Option<Aabb> {
+        match self {
+            BoundingVolume::None => None,
+            BoundingVolume::Sphere { center, radius } => Some(Aabb {
+                min: *center - Vec3::splat(*radius),
+                max: *center + Vec3::splat(*radius),
+            }),
+            BoundingVolume::Box { min, max } => Some(Aabb {
+                min: min.min(*max),
+                max: max.max(*min),
+            }),
+        }
+    }
+
+    /// GLSL expression with distance to volume along normalized `ray`, it's 0 when ray starts inside the volume and -1 when ray misses it.
+    pub fn distance_code(&self, ray: &str) -> Option<String> {
+        match self {
+            BoundingVolume::None => None,
+            BoundingVolume::Sphere { center, radius } => Some(format!(
+                "sphere_bounds({}, vec3({:e}, {:e}, {:e}), {:e})",
+                ray, center.x, center.y, center.z, radius
+            )),
+            BoundingVolume::Box { min, max } => Some(format!(
+                "box_bounds({}, vec3({:e}, {:e}, {:e}), vec3({:e}, {:e}, {:e}))",
+                ray, min.x, min.y, min.z, max.x, max.y, max.z
+            )),
+        }
+    }
+}
+
+impl Primitive {
+    /// Parameters with their labels, in order of arguments of intersection function.
+    /// Name of field in scene file, label in interface and value of every parameter.
+    pub fn parameters(&self) -> Vec<(&'static str, &'static str, &ParametrizeOrNot)> {
+        use Primitive::*;
+        match self {
+            Sphere { radius } | Disk { radius } => vec![("radius", "Radius:", radius)],
+            Box { size } => vec![
+                ("size.x", "Size X:", &size.x),
+                ("size.y", "Size Y:", &size.y),
+                ("size.z", "Size Z:", &size.z),
+            ],
+            Cylinder { radius, height } | Cone { radius, height } => {
+                vec![("radius", "Radius:", radius), ("height", "Height:", height)]
+            }
+            Torus {
+                major_radius,
+                minor_radius,
+            } => vec![
+                ("major_radius", "Major radius:", major_radius),
+                ("minor_radius", "Minor radius:", minor_radius),
+            ],
+        }
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<(&'static str, &'static str, &mut ParametrizeOrNot)> {
+        use Primitive::*;
+        match self {
+            Sphere { radius } | Disk { radius } => vec![("radius", "Radius:", radius)],
+            Box { size } => vec![
+                ("size.x", "Size X:", &mut size.x),
+                ("size.y", "Size Y:", &mut size.y),
+                ("size.z", "Size Z:", &mut size.z),
+            ],
+            Cylinder { radius, height } | Cone { radius, height } => {
+                vec![("radius", "Radius:", radius), ("height", "Height:", height)]
+            }
+            Torus {
+                major_radius,
+                minor_radius,
+            } => vec![
+                ("major_radius", "Major radius:", major_radius),
+                ("minor_radius", "Minor radius:", minor_radius),
+            ],
+        }
+    }
+
+    pub fn egui(&mut self, ui: &mut Ui, formulas_names: &[String]) -> WhatChanged {
+        let mut is_changed = egui_combo_label(ui, "Shape:", 45., self);
+        if matches!(self, Primitive::Box { .. }) {
+            ui.label("Box is from -size to size.");
+        }
+        for (_, label, param) in self.parameters_mut() {
+            is_changed |= param.egui(ui, formulas_names, label, 1.0, |ui, x| {
+                egui_f32_positive(ui, x)
+            });
+        }
+        WhatChanged::from_shader(is_changed)
+    }
+
+    pub fn errors_count(&self, formulas_names: &[String]) -> usize {
+        self.parameters()
+            .into_iter()
+            .map(|(_, _, param)| param.errors_count(formulas_names))
+            .sum()
+    }
+
+    /// GLSL expression with `SurfaceIntersection` of `ray` with this shape.
+    pub fn intersect_code(&self, ray: &str) -> String {
+        use Primitive::*;
+        let function = match self {
+            Sphere { .. } => "sphere_intersect",
+            Box { .. } => "box_intersect",
+            Cylinder { .. } => "cylinder_intersect",
+            Cone { .. } => "cone_intersect",
+            Torus { .. } => "torus_intersect",
+            Disk { .. } => "disk_intersect",
+        };
+        let params = self
+            .parameters()
+            .into_iter()
+            .map(|(_, _, param)| param.code())
+            .collect::<Vec<_>>();
+        match self {
+            Box { .. } => format!("{}({}, vec3({}))", function, ray, params.join(", ")),
+            _ => format!("{}({}, {})", function, ray, params.join(", ")),
+        }
+    }
+
+    /// Known bounding volume, there is none when some parameter is taken from uniform.
+    pub fn bounds(&self) -> BoundingVolume {
+        use Primitive::*;
+        let values = match self
+            .parameters()
+            .into_iter()
+            .map(|(_, _, param)| param.freeget().map(f32::abs))
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(values) => values,
+            None => return BoundingVolume::None,
+        };
+        let (min, max) = match self {
+            Sphere { .. } => {
+                return BoundingVolume::Sphere {
+                    center: Vec3::default(),
+                    radius: values[0],
+                }
+            }
+            Box { .. } => {
+                let size = Vec3::new(values[0], values[1], values[2]);
+                (-size, size)
+            }
+            Cylinder { .. } | Cone { .. } => (
+                Vec3::new(-values[0], -values[0], 0.),
+                Vec3::new(values[0], values[0], values[1]),
+            ),
+            Torus { .. } => {
+                let radius = values[0] + values[1];
+                (
+                    Vec3::new(-radius, -radius, -values[1]),
+                    Vec3::new(radius, radius, values[1]),
+                )
+            }
+            Disk { .. } => (
+                Vec3::new(-values[0], -values[0], 0.),
+                Vec3::new(values[0], values[0], 0.),
+            ),
+        };
+        BoundingVolume::Box { min, max }
+    }
+}
+
 impl ObjectType {
     pub fn egui(&mut self, ui: &mut Ui, names: &mut Vec<String>) -> WhatChanged {
         use ObjectType::*;
@@ -135,22 +489,23 @@ impl ObjectType {
     }
 }
 
+/// Names of matrices, materials and uniforms, that can be used by objects.
+pub type ObjectInput = megatuple!(Vec<String>, Vec<String>, Vec<String>, ShaderErrors);
+
 impl Object {
-    pub fn egui(
-        &mut self,
-        ui: &mut Ui,
-        pos: usize,
-        input: &mut megatuple!(Vec<String>, ShaderErrors),
-    ) -> WhatChanged {
+    pub fn egui(&mut self, ui: &mut Ui, id: EntityId, input: &mut ObjectInput) -> WhatChanged {
         use Object::*;
-        let megapattern!(names, errors) = input;
+        let megapattern!(names, materials, formulas_names, errors) = input;
         let mut is_changed = WhatChanged::default();
-        let has_errors = errors.get_errors(self, pos).is_some();
+        let has_errors = errors.get_errors(self, id).is_some();
         let mut errors_count = 0;
         match self {
             DebugMatrix(a) => {
                 is_changed.shader |=
                     egui_existing_name(ui, "Matrix:", 45., &mut a.0, names, &mut errors_count);
+                if let Some(local_errors) = errors.get_errors(self, id) {
+                    egui_errors(ui, local_errors);
+                }
             }
             Flat { kind, is_inside } => {
                 is_changed.shader |= egui_combo_label(ui, "Kind:", 45., kind);
@@ -199,14 +554,20 @@ impl Object {
                     is_changed |= is_inside.0.egui(ui);
                 });
                 ui.add(Label::new("}").monospace());
-                if let Some(local_errors) = errors.get_errors(self, pos) {
+                if let Some(local_errors) = errors.get_errors(self, id) {
                     egui_errors(ui, local_errors);
                 }
             }
-            Complex { kind, intersect } => {
+            Complex {
+                kind,
+                intersect,
+                bounds,
+            } => {
                 is_changed.shader |= egui_combo_label(ui, "Kind:", 45., kind);
                 is_changed |= kind.egui(ui, names);
                 ui.separator();
+                is_changed |= bounds.egui(ui);
+                ui.separator();
 
                 ui.horizontal_wrapped_for_text(TextStyle::Monospace, |ui| {
                     ui.spacing_mut().item_spacing.x = 0.;
@@ -247,7 +608,28 @@ impl Object {
                     is_changed |= intersect.0.egui(ui);
                 });
                 ui.add(Label::new("}").monospace());
-                if let Some(local_errors) = errors.get_errors(self, pos) {
+                if let Some(local_errors) = errors.get_errors(self, id) {
+                    egui_errors(ui, local_errors);
+                }
+            }
+            Primitive {
+                matrix,
+                shape,
+                material,
+            } => {
+                is_changed.shader |=
+                    egui_existing_name(ui, "Matrix:", 45., &mut matrix.0, names, &mut errors_count);
+                is_changed.shader |= egui_existing_name(
+                    ui,
+                    "Material:",
+                    45.,
+                    &mut material.0,
+                    materials,
+                    &mut errors_count,
+                );
+                ui.separator();
+                is_changed |= shape.egui(ui, formulas_names);
+                if let Some(local_errors) = errors.get_errors(self, id) {
                     egui_errors(ui, local_errors);
                 }
             }
@@ -256,6 +638,22 @@ impl Object {
     }
 }
 
+impl Object {
+    /// Bounding volume that is given by user, or that is known for built-in objects.
+    pub fn bounds(&self) -> BoundingVolume {
+        match self {
+            // Axes of length 1 with radius 0.03, see `debug_intersect`.
+            Object::DebugMatrix(_) => BoundingVolume::Box {
+                min: Vec3::splat(-0.03),
+                max: Vec3::splat(1.03),
+            },
+            Object::Flat { .. } => BoundingVolume::None,
+            Object::Complex { bounds, .. } => bounds.clone(),
+            Object::Primitive { shape, .. } => shape.bounds(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ObjectComboBox(pub Object);
 
@@ -287,10 +685,10 @@ impl ObjectType {
 impl Object {
     pub fn errors_count(
         &self,
-        pos: usize,
-        megapattern!(names, errors): &megatuple!(Vec<String>, ShaderErrors),
+        id: EntityId,
+        megapattern!(names, materials, formulas_names, errors): &ObjectInput,
     ) -> usize {
-        let mut result = if let Some(local_errors) = errors.get_errors(self, pos) {
+        let mut result = if let Some(local_errors) = errors.get_errors(self, id) {
             local_errors.len()
         } else {
             0
@@ -306,9 +704,22 @@ impl Object {
             Flat { kind, is_inside: _ } => {
                 result += kind.errors_count(names);
             }
-            Complex { kind, intersect: _ } => {
+            Complex { kind, .. } => {
                 result += kind.errors_count(names);
             }
+            Primitive {
+                matrix,
+                shape,
+                material,
+            } => {
+                if !names.contains(&matrix.0) {
+                    result += 1;
+                }
+                if !materials.contains(&material.0) {
+                    result += 1;
+                }
+                result += shape.errors_count(formulas_names);
+            }
         }
 
         result
@@ -317,7 +728,7 @@ impl Object {
 
 impl StorageElem for ObjectComboBox {
     type GetType = Object;
-    type Input = megatuple!(Vec<String>, ShaderErrors);
+    type Input = ObjectInput;
 
     fn get<F: FnMut(&str) -> GetEnum<Self::GetType>>(
         &self,
@@ -331,17 +742,150 @@ impl StorageElem for ObjectComboBox {
     fn egui(
         &mut self,
         ui: &mut Ui,
-        pos: usize,
+        id: EntityId,
         input: &mut Self::Input,
         _: &[String],
     ) -> WhatChanged {
         let mut changed = WhatChanged::from_shader(egui_combo_label(ui, "Type:", 45., &mut self.0));
+        if let (true, Object::Primitive { material, .. }) = (changed.shader, &mut self.0) {
+            let megapattern!(_, materials, _, _) = input;
+            material.0 = materials.first().cloned().unwrap_or_default();
+        }
         ui.separator();
-        changed |= self.0.egui(ui, pos, input);
+        changed |= self.0.egui(ui, id, input);
         changed
     }
 
-    fn errors_count(&self, pos: usize, data: &Self::Input, _: &[String]) -> usize {
-        self.0.errors_count(pos, data)
+    fn errors_count(&self, id: EntityId, data: &Self::Input, _: &[String]) -> usize {
+        self.0.errors_count(id, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::scene::ShaderOptions;
+    use crate::scene_files::*;
+
+    #[test]
+    fn bounding_volumes() {
+        let sphere = BoundingVolume::Sphere {
+            center: Vec3::new(1., 0., 0.),
+            radius: 2.,
+        };
+        let aabb = sphere.aabb().unwrap();
+        assert_eq!(aabb.min, Vec3::new(-1., -2., -2.));
+        assert_eq!(aabb.max, Vec3::new(3., 2., 2.));
+        let reversed = BoundingVolume::Box {
+            min: Vec3::splat(1.),
+            max: Vec3::splat(-1.),
+        };
+        let aabb = reversed.aabb().unwrap();
+        assert_eq!(aabb.min, Vec3::splat(-1.));
+        assert_eq!(aabb.max, Vec3::splat(1.));
+        assert_eq!(BoundingVolume::None.aabb(), None);
+
+        // Shader checks volumes of complex objects before calling their code.
+        let mut scene = embedded_scene("mobius").unwrap();
+        let box_volume = BoundingVolume::Box {
+            min: Vec3::splat(-2.),
+            max: Vec3::splat(2.),
+        };
+        for volume in &[sphere, box_volume] {
+            for object in &mut scene.objects.storage {
+                if let Object::Complex { bounds, .. } = &mut object.0 {
+                    *bounds = volume.clone();
+                }
+            }
+            let objects = &scene.objects.storage;
+            assert!(objects.iter().any(|x| x.0.bounds() == *volume));
+            assert!(scene.validate_shader(&ShaderOptions::default()).is_ok());
+        }
+    }
+
+    #[test]
+    fn primitive_objects() {
+        use crate::cpu_render::{CpuRender, Ray};
+        use crate::gui::common::Data;
+        use crate::gui::material::MaterialComboBox;
+        use crate::gui::uniform::*;
+
+        let mut shapes = (0..Primitive::variants().len())
+            .map(|number| {
+                let mut shape = Primitive::default();
+                shape.set_number(number);
+                shape
+            })
+            .collect::<Vec<_>>();
+        let size = FormulaName("size".to_owned());
+        let parametrized = Primitive::Sphere {
+            radius: ParametrizeOrNot::Yes(size),
+        };
+        assert_eq!(parametrized.bounds(), BoundingVolume::None);
+        shapes.push(parametrized);
+
+        for shape in &shapes {
+            let mut scene = embedded_scene("empty").unwrap();
+            scene.objects.remove(0);
+            scene
+                .materials
+                .add("red".to_owned(), MaterialComboBox::default());
+            let size = AnyUniform::Float {
+                min: None,
+                max: None,
+                value: 0.5,
+            };
+            scene
+                .uniforms
+                .add("size".to_owned(), AnyUniformComboBox(size));
+            let object = Object::Primitive {
+                matrix: MatrixName("id".to_owned()),
+                shape: shape.clone(),
+                material: MaterialName("red".to_owned()),
+            };
+            scene
+                .objects
+                .add("primitive".to_owned(), ObjectComboBox(object));
+            for pack_uniforms in &[false, true] {
+                let options = ShaderOptions {
+                    pack_uniforms: *pack_uniforms,
+                    ..Default::default()
+                };
+                assert!(scene.validate_shader(&options).is_ok(), "{:?}", shape);
+            }
+
+            let bounds = match shape.bounds().aabb() {
+                Some(aabb) => aabb,
+                None => continue,
+            };
+            // Every point where rays around shape hit it is inside of its bounds.
+            let mut data = Data::default();
+            scene.init(&mut data);
+            let render = CpuRender::new(&scene, &data.formulas_cache);
+            let mut hits = 0;
+            for i in 0..8 {
+                for j in 0..16 {
+                    let theta = std::f32::consts::PI * (i as f32 + 0.5) / 8.;
+                    let phi = std::f32::consts::PI * j as f32 / 8.;
+                    let (sin, cos) = (theta.sin(), theta.cos());
+                    let o = 4. * Vec3::new(sin * phi.cos(), sin * phi.sin(), cos);
+                    let target = Vec3::new(0.1 * (j % 3) as f32, 0.1 * (i % 2) as f32, 0.);
+                    let d = (target - o).normalize();
+                    let ray = Ray {
+                        o: o.extend(1.),
+                        d: d.extend(0.),
+                    };
+                    let hit = render.scene_intersect(ray).hit;
+                    if hit.hit {
+                        let point = o + d * hit.t;
+                        let epsilon = Vec3::splat(1e-3);
+                        assert!(point.cmpge(bounds.min - epsilon).all(), "{:?}", shape);
+                        assert!(point.cmple(bounds.max + epsilon).all(), "{:?}", shape);
+                        hits += 1;
+                    }
+                }
+            }
+            assert!(hits > 0, "{:?}", shape);
+        }
     }
 }
@@ -0,0 +1,641 @@
+use crate::dead_code_elimination::split_items;
+use crate::gui::animation::*;
+use crate::gui::common::*;
+use crate::gui::rename::*;
+use crate::gui::scene::Scene;
+use crate::gui::storage::*;
+use crate::gui::uniform::*;
+use crate::gui::validation::StorageKind;
+use crate::scene_files::{parse_scene, SceneFile};
+
+use egui::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+const KINDS: &[StorageKind] = &[
+    StorageKind::Uniforms,
+    StorageKind::Matrices,
+    StorageKind::Objects,
+    StorageKind::Textures,
+    StorageKind::Materials,
+    StorageKind::Library,
+    StorageKind::AnimationStages,
+];
+
+/// What was changed in other scene to merge it without name collisions.
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    /// Storage, old name and new name.
+    pub renamed: Vec<(StorageKind, String, String)>,
+
+    /// References to renamed elements that were rewritten.
+    pub sites: Vec<RenameSite>,
+
+    /// Library entries that are not added because the same code is already in this scene.
+    pub skipped: Vec<String>,
+
+    pub added: usize,
+}
+
+fn rename_kind(kind: StorageKind) -> Option<RenameKind> {
+    match kind {
+        StorageKind::Uniforms => Some(RenameKind::Uniform),
+        StorageKind::Matrices => Some(RenameKind::Matrix),
+        StorageKind::Materials => Some(RenameKind::Material),
+        StorageKind::Textures => Some(RenameKind::Texture),
+        _ => None,
+    }
+}
+
+impl Scene {
+    fn storage_names(&self, kind: StorageKind) -> &[String] {
+        match kind {
+            StorageKind::Uniforms => &self.uniforms.names,
+            StorageKind::Matrices => &self.matrices.names,
+            StorageKind::Objects => &self.objects.names,
+            StorageKind::Textures => &self.textures.names,
+            StorageKind::Materials => &self.materials.names,
+            StorageKind::Library => &self.library.names,
+            StorageKind::AnimationStages => &self.animation_stages.names,
+        }
+    }
+
+    fn storage_ids(&self, kind: StorageKind) -> &[EntityId] {
+        match kind {
+            StorageKind::Uniforms => &self.uniforms.ids,
+            StorageKind::Matrices => &self.matrices.ids,
+            StorageKind::Objects => &self.objects.ids,
+            StorageKind::Textures => &self.textures.ids,
+            StorageKind::Materials => &self.materials.ids,
+            StorageKind::Library => &self.library.ids,
+            StorageKind::AnimationStages => &self.animation_stages.ids,
+        }
+    }
+}
+
+/// `prefix` + `name`, with number added if this name is taken too.
+fn unique_name<F: Fn(&str) -> bool>(name: &str, prefix: &str, is_taken: F) -> String {
+    let mut result = format!("{}{}", prefix, name);
+    let mut counter = 2;
+    while is_taken(&result) {
+        result = format!("{}{}_{}", prefix, name, counter);
+        counter += 1;
+    }
+    result
+}
+
+/// Appends selected elements, prefixing names that are still taken. Returns new identifiers of added elements.
+fn append<T: StorageElem + Clone>(
+    to: &mut StorageWithNames<T>,
+    from: &StorageWithNames<T>,
+    kind: StorageKind,
+    selected: &BTreeSet<EntityId>,
+    prefix: &str,
+    report: &mut MergeReport,
+) -> BTreeMap<EntityId, EntityId> {
+    let mut ids = BTreeMap::new();
+    for ((id, name), t) in from.ids.iter().zip(&from.names).zip(&from.storage) {
+        if !selected.contains(id) {
+            continue;
+        }
+        let new_name = if to.names.contains(name) {
+            let new_name = unique_name(name, prefix, |x| {
+                to.names.iter().chain(&from.names).any(|y| y == x)
+            });
+            report.renamed.push((kind, name.clone(), new_name.clone()));
+            new_name
+        } else {
+            name.clone()
+        };
+        ids.insert(*id, to.add(new_name, t.clone()));
+        report.added += 1;
+    }
+    ids
+}
+
+fn remap<T: Clone>(
+    map: &BTreeMap<EntityId, T>,
+    ids: &BTreeMap<EntityId, EntityId>,
+) -> BTreeMap<EntityId, T> {
+    map.iter()
+        .filter_map(|(id, x)| Some((*ids.get(id)?, x.clone())))
+        .collect()
+}
+
+impl Scene {
+    /// Adds elements of `other` scene, `is_selected` receives storage and name of element in `other`. Colliding names of uniforms, matrices, materials and textures get `prefix`, and references to them are rewritten in formulas, matrices, objects and GLSL code of merged elements. Unselected elements are not added, so references to them are resolved by name in this scene, this is how prefab can use `id` matrix of the scene. Names of GLSL functions in library are not changed, so library entry with the same code as in this scene is skipped, and entry that defines already defined name is an error.
+    pub fn merge<F>(
+        &mut self,
+        other: &Scene,
+        prefix: &str,
+        is_selected: F,
+    ) -> Result<MergeReport, String>
+    where
+        F: Fn(StorageKind, &str) -> bool,
+    {
+        let mut selected: BTreeMap<StorageKind, BTreeSet<EntityId>> = KINDS
+            .iter()
+            .map(|kind| {
+                let ids = other
+                    .storage_ids(*kind)
+                    .iter()
+                    .zip(other.storage_names(*kind))
+                    .filter(|(_, name)| is_selected(*kind, name))
+                    .map(|(id, _)| *id)
+                    .collect();
+                (*kind, ids)
+            })
+            .collect();
+
+        let mut report = MergeReport::default();
+        let defined: BTreeMap<&str, &str> = self
+            .library
+            .iter()
+            .flat_map(|(name, code)| {
+                split_items(&code.0 .0)
+                    .into_iter()
+                    .flat_map(|item| item.defines)
+                    .map(move |x| (x, name.as_str()))
+            })
+            .collect();
+        let library = selected.get_mut(&StorageKind::Library).unwrap();
+        for (id, (name, code)) in other.library.ids.iter().zip(other.library.iter()) {
+            if !library.contains(id) {
+                continue;
+            }
+            if self
+                .library
+                .storage
+                .iter()
+                .any(|x| x.0 .0.trim() == code.0 .0.trim())
+            {
+                library.remove(id);
+                report.skipped.push(name.clone());
+                continue;
+            }
+            for item in split_items(&code.0 .0) {
+                for x in item.defines {
+                    if let Some(existing) = defined.get(x) {
+                        return Err(format!(
+                            "library '{}' defines `{}` that is already defined in library '{}'",
+                            name, x, existing
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut prefab = other.clone();
+        for kind in KINDS {
+            let rename = match rename_kind(*kind) {
+                Some(rename) => rename,
+                None => continue,
+            };
+            for id in &selected[kind] {
+                let pos = prefab
+                    .storage_ids(*kind)
+                    .iter()
+                    .position(|x| x == id)
+                    .unwrap();
+                let name = prefab.storage_names(*kind)[pos].clone();
+                if !self.storage_names(*kind).contains(&name) {
+                    continue;
+                }
+                let new_name = unique_name(&name, prefix, |x| {
+                    self.storage_names(*kind)
+                        .iter()
+                        .chain(prefab.storage_names(*kind))
+                        .any(|y| y == x)
+                });
+                let sites = prefab
+                    .rename(rename, &name, &new_name, true)
+                    .map_err(|err| format!("can't rename {} '{}': {}", kind, name, err))?;
+                report.sites.extend(sites);
+                report.renamed.push((*kind, name, new_name));
+            }
+        }
+
+        let uniforms = append(
+            &mut self.uniforms,
+            &prefab.uniforms,
+            StorageKind::Uniforms,
+            &selected[&StorageKind::Uniforms],
+            prefix,
+            &mut report,
+        );
+        let matrices = append(
+            &mut self.matrices,
+            &prefab.matrices,
+            StorageKind::Matrices,
+            &selected[&StorageKind::Matrices],
+            prefix,
+            &mut report,
+        );
+        append(
+            &mut self.objects,
+            &prefab.objects,
+            StorageKind::Objects,
+            &selected[&StorageKind::Objects],
+            prefix,
+            &mut report,
+        );
+        append(
+            &mut self.textures,
+            &prefab.textures,
+            StorageKind::Textures,
+            &selected[&StorageKind::Textures],
+            prefix,
+            &mut report,
+        );
+        append(
+            &mut self.materials,
+            &prefab.materials,
+            StorageKind::Materials,
+            &selected[&StorageKind::Materials],
+            prefix,
+            &mut report,
+        );
+        append(
+            &mut self.library,
+            &prefab.library,
+            StorageKind::Library,
+            &selected[&StorageKind::Library],
+            prefix,
+            &mut report,
+        );
+
+        // Stages of other scene can refer only to merged elements, new elements remain unchanged in stages of this scene.
+        for stage in &mut prefab.animation_stages.storage {
+            *stage = AnimationStage {
+                uniforms: remap(&stage.uniforms, &uniforms),
+                matrices: remap(&stage.matrices, &matrices),
+            };
+        }
+        append(
+            &mut self.animation_stages,
+            &prefab.animation_stages,
+            StorageKind::AnimationStages,
+            &selected[&StorageKind::AnimationStages],
+            prefix,
+            &mut report,
+        );
+
+        for id in &prefab.user_uniforms.uniforms {
+            if let Some(id) = uniforms.get(id) {
+                self.user_uniforms.uniforms.insert(*id);
+            }
+        }
+        for id in &prefab.user_uniforms.matrices {
+            if let Some(id) = matrices.get(id) {
+                self.user_uniforms.matrices.insert(*id);
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Window for merging other scene into current one, like a prefab.
+#[derive(Debug)]
+pub struct MergeWindow {
+    source: usize,
+    prefix: String,
+    excluded: BTreeSet<(StorageKind, String)>,
+    preview: Option<MergePreview>,
+}
+
+impl Default for MergeWindow {
+    fn default() -> Self {
+        Self {
+            source: 0,
+            prefix: "prefab_".to_owned(),
+            excluded: BTreeSet::new(),
+            preview: None,
+        }
+    }
+}
+
+/// Parsed source scene and result of merging it, they are computed again only when something they depend on is changed.
+#[derive(Debug)]
+struct MergePreview {
+    content: String,
+    prefix: String,
+    excluded: BTreeSet<(StorageKind, String)>,
+    names: Vec<Vec<String>>,
+    library: Vec<String>,
+
+    other: Result<Scene, String>,
+    report: Result<MergeReport, String>,
+}
+
+impl MergePreview {
+    fn new(
+        file: &SceneFile,
+        prefix: &str,
+        excluded: &BTreeSet<(StorageKind, String)>,
+        scene: &Scene,
+    ) -> Self {
+        let other = parse_scene(file);
+        let report = match &other {
+            Ok(other) => scene.clone().merge(other, prefix, |kind, name| {
+                !excluded.contains(&(kind, name.to_owned()))
+            }),
+            Err(err) => Err(err.clone()),
+        };
+        Self {
+            content: file.content.clone(),
+            prefix: prefix.to_owned(),
+            excluded: excluded.clone(),
+            names: KINDS
+                .iter()
+                .map(|kind| scene.storage_names(*kind).to_vec())
+                .collect(),
+            library: scene
+                .library
+                .storage
+                .iter()
+                .map(|x| x.0 .0.clone())
+                .collect(),
+            other,
+            report,
+        }
+    }
+
+    fn is_actual(
+        &self,
+        file: &SceneFile,
+        prefix: &str,
+        excluded: &BTreeSet<(StorageKind, String)>,
+        scene: &Scene,
+    ) -> bool {
+        self.content == file.content
+            && self.prefix == prefix
+            && &self.excluded == excluded
+            && KINDS
+                .iter()
+                .zip(&self.names)
+                .all(|(kind, names)| scene.storage_names(*kind) == names.as_slice())
+            && self
+                .library
+                .iter()
+                .eq(scene.library.storage.iter().map(|x| &x.0 .0))
+    }
+}
+
+impl MergeWindow {
+    fn update_preview(&mut self, file: &SceneFile, scene: &Scene) {
+        let is_actual = match &self.preview {
+            Some(preview) => preview.is_actual(file, &self.prefix, &self.excluded, scene),
+            None => false,
+        };
+        if !is_actual {
+            self.preview = Some(MergePreview::new(file, &self.prefix, &self.excluded, scene));
+        }
+    }
+
+    pub fn egui(
+        &mut self,
+        ui: &mut Ui,
+        scene: &mut Scene,
+        sources: &[SceneFile],
+        formulas_cache: &mut FormulasCache,
+    ) -> WhatChanged {
+        let mut changed = WhatChanged::default();
+        if sources.is_empty() {
+            return changed;
+        }
+        self.source = self.source.min(sources.len() - 1);
+
+        let previous = self.source;
+        ui.horizontal(|ui| {
+            egui_label(ui, "Scene:", 45.);
+            let id = ui.make_persistent_id("merge source");
+            egui::combo_box(ui, id, &sources[self.source].name, |ui| {
+                for (pos, file) in sources.iter().enumerate() {
+                    ui.selectable_value(&mut self.source, pos, &file.name);
+                }
+            });
+        });
+        if previous != self.source {
+            self.excluded.clear();
+        }
+        ui.horizontal(|ui| {
+            egui_label(ui, "Prefix:", 45.);
+            ui.text_edit_singleline(&mut self.prefix);
+        });
+
+        let file = &sources[self.source];
+        self.update_preview(file, scene);
+        let other = match &self.preview.as_ref().unwrap().other {
+            Ok(other) => other,
+            Err(err) => {
+                ui.horizontal_wrapped_for_text(TextStyle::Body, |ui| {
+                    ui.add(Label::new("Error: ").text_color(Color32::RED));
+                    ui.label(err);
+                });
+                return changed;
+            }
+        };
+
+        let excluded = &mut self.excluded;
+        for kind in KINDS {
+            let names = other.storage_names(*kind);
+            if names.is_empty() {
+                continue;
+            }
+            CollapsingHeader::new(kind.to_string())
+                .id_source(kind)
+                .show(ui, |ui| {
+                    for name in names {
+                        let key = (*kind, name.clone());
+                        let mut is_selected = !excluded.contains(&key);
+                        ui.checkbox(&mut is_selected, name);
+                        if is_selected {
+                            excluded.remove(&key);
+                        } else {
+                            excluded.insert(key);
+                        }
+                    }
+                });
+        }
+
+        ui.separator();
+        self.update_preview(file, scene);
+        let preview = self.preview.as_ref().unwrap();
+        match &preview.report {
+            Ok(report) => {
+                for (kind, from, to) in &report.renamed {
+                    ui.label(format!("{} '{}' → '{}'", kind, from, to));
+                }
+                for name in &report.skipped {
+                    ui.label(format!("library '{}' is already in the scene", name));
+                }
+                for site in &report.sites {
+                    ui.label(&site.place);
+                    ui.horizontal_wrapped_for_text(TextStyle::Monospace, |ui| {
+                        ui.add(
+                            Label::new(format!("- {}", site.before))
+                                .text_color(Color32::RED)
+                                .monospace(),
+                        );
+                    });
+                    ui.horizontal_wrapped_for_text(TextStyle::Monospace, |ui| {
+                        ui.add(
+                            Label::new(format!("+ {}", site.after))
+                                .text_color(Color32::GREEN)
+                                .monospace(),
+                        );
+                    });
+                }
+                if ui
+                    .button(format!("Merge ({} elements)", report.added))
+                    .clicked()
+                {
+                    let other = preview.other.as_ref().unwrap();
+                    let excluded = &self.excluded;
+                    let mut merged = scene.clone();
+                    if merged
+                        .merge(other, &self.prefix, |kind, name| {
+                            !excluded.contains(&(kind, name.to_owned()))
+                        })
+                        .is_ok()
+                    {
+                        *scene = merged;
+                        for (_, uniform) in scene.uniforms.iter() {
+                            if let AnyUniform::Formula(f) = &uniform.0 {
+                                formulas_cache.compile(&f.0);
+                            }
+                        }
+                        changed.shader = true;
+                        changed.uniform = true;
+                    }
+                }
+            }
+            Err(err) => {
+                ui.horizontal_wrapped_for_text(TextStyle::Body, |ui| {
+                    ui.add(Label::new("Error: ").text_color(Color32::RED));
+                    ui.label(err);
+                });
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::object::*;
+    use crate::gui::scene::ShaderOptions;
+
+    fn room() -> Scene {
+        Scene::from_json(include_str!("../../scenes/room.json")).unwrap()
+    }
+
+    #[test]
+    fn merge_into_itself() {
+        let mut scene = room();
+        let other = room();
+        let report = scene.merge(&other, "copy_", |_, _| true).unwrap();
+
+        let count = |s: &Scene| {
+            KINDS
+                .iter()
+                .map(|k| s.storage_names(*k).len())
+                .sum::<usize>()
+        };
+        let library = other.library.names.len();
+        assert_eq!(report.skipped, other.library.names);
+        assert_eq!(report.added, count(&other) - library);
+        assert_eq!(count(&scene), 2 * count(&other) - library);
+        for name in &other.matrices.names {
+            assert!(scene.matrices.names.contains(&format!("copy_{}", name)));
+        }
+        assert_eq!(scene.validate(), vec![]);
+        assert!(scene.validate_shader(&ShaderOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn library_redefinition() {
+        let mut scene = room();
+        let mut other = room();
+        other.library.storage[0]
+            .0
+             .0
+            .insert_str(0, "// Same function.\n");
+        let err = scene.merge(&other, "copy_", |_, _| true).unwrap_err();
+        assert!(err.contains("is_inside_square"), "{}", err);
+
+        let report = scene
+            .merge(&other, "copy_", |kind, _| kind != StorageKind::Library)
+            .unwrap();
+        assert!(report.skipped.is_empty());
+        assert!(scene.validate_shader(&ShaderOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn references_are_rewritten() {
+        let mut scene = room();
+        let mut other = room();
+        other.objects.names[0] = "wall".to_owned();
+        let first_object = other.objects.storage[0].0.clone();
+        let matrix = match &first_object {
+            Object::DebugMatrix(m) | Object::Primitive { matrix: m, .. } => m.0.clone(),
+            Object::Flat { kind, .. } | Object::Complex { kind, .. } => match kind {
+                ObjectType::Simple(m) => m.0.clone(),
+                ObjectType::Portal(m, _) => m.0.clone(),
+            },
+        };
+
+        // Only one object and its matrix, everything else is taken from the scene by name.
+        scene
+            .merge(&other, "p_", |kind, name| match kind {
+                StorageKind::Objects => name == "wall",
+                StorageKind::Matrices => name == matrix,
+                _ => false,
+            })
+            .unwrap();
+
+        let object = &scene.objects.storage.last().unwrap().0;
+        let new_matrix = format!("p_{}", matrix);
+        match object {
+            Object::DebugMatrix(m) | Object::Primitive { matrix: m, .. } => {
+                assert_eq!(m.0, new_matrix)
+            }
+            Object::Flat { kind, .. } | Object::Complex { kind, .. } => match kind {
+                ObjectType::Simple(m) | ObjectType::Portal(m, _) => assert_eq!(m.0, new_matrix),
+            },
+        }
+        assert!(scene.matrices.names.contains(&new_matrix));
+        assert_eq!(scene.validate(), vec![]);
+    }
+
+    #[test]
+    fn ids_are_remapped() {
+        let mut scene = room();
+        scene
+            .animation_stages
+            .add("stage".to_owned(), Default::default());
+        let mut other = room();
+        let uniform = other.uniforms.add("x".to_owned(), Default::default());
+        let matrix = other.matrices.add("m".to_owned(), Default::default());
+        other.user_uniforms.uniforms.insert(uniform);
+        let mut stage = AnimationStage::default();
+        stage.matrices.insert(matrix, Animation::ProvidedToUser);
+        other.animation_stages.add("stage".to_owned(), stage);
+
+        scene
+            .merge(&other, "p_", |kind, name| {
+                name == "x" || name == "m" || kind == StorageKind::AnimationStages
+            })
+            .unwrap();
+
+        let new_uniform = scene.uniforms.id_by_name("x").unwrap();
+        let new_matrix = scene.matrices.id_by_name("m").unwrap();
+        assert!(scene.user_uniforms.uniforms.contains(&new_uniform));
+        let stage = scene.animation_stages.storage.last().unwrap();
+        assert_eq!(scene.animation_stages.names.last().unwrap(), "p_stage");
+        assert_eq!(stage.matrices.keys().collect::<Vec<_>>(), vec![&new_matrix]);
+        assert_eq!(scene.validate(), vec![]);
+    }
+}
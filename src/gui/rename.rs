@@ -0,0 +1,639 @@
+use crate::gui::animation::*;
+use crate::gui::combo_box::*;
+use crate::gui::common::*;
+use crate::gui::glsl::*;
+use crate::gui::matrix::*;
+use crate::gui::object::*;
+use crate::gui::scene::Scene;
+use crate::gui::texture::TextureName;
+use crate::gui::uniform::*;
+
+use egui::*;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameKind {
+    Uniform,
+    Matrix,
+    Material,
+    Texture,
+}
+
+impl ComboBoxChoosable for RenameKind {
+    fn variants() -> &'static [&'static str] {
+        &["Uniform", "Matrix", "Material", "Texture"]
+    }
+    fn get_number(&self) -> usize {
+        use RenameKind::*;
+        match self {
+            Uniform => 0,
+            Matrix => 1,
+            Material => 2,
+            Texture => 3,
+        }
+    }
+    fn set_number(&mut self, number: usize) {
+        use RenameKind::*;
+        *self = match number {
+            0 => Uniform,
+            1 => Matrix,
+            2 => Material,
+            3 => Texture,
+            _ => unreachable!(),
+        };
+    }
+}
+
+/// One place changed by rename. For code blocks `before` and `after` contain whole changed lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameSite {
+    pub place: String,
+    pub before: String,
+    pub after: String,
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replaces whole identifiers in code. Numbers are skipped, so `1e5` is never treated as identifier `e5`. If `skip_calls` is set, identifiers followed by `(` are not replaced, this is how formulas distinguish functions from variables.
+fn replace_identifiers(text: &str, map: &BTreeMap<String, String>, skip_calls: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_alphanumeric() || c == '_' {
+            rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len())
+        } else {
+            c.len_utf8()
+        };
+        let (token, after) = rest.split_at(len);
+        let is_call = skip_calls && after.trim_start().starts_with('(');
+        match map.get(token) {
+            Some(new) if !c.is_ascii_digit() && !is_call => result.push_str(new),
+            _ => result.push_str(token),
+        }
+        rest = after;
+    }
+    result
+}
+
+struct Renamer<'a> {
+    from: &'a str,
+    to: &'a str,
+    glsl: Option<BTreeMap<String, String>>,
+    sites: Vec<RenameSite>,
+}
+
+impl<'a> Renamer<'a> {
+    fn name(&mut self, place: String, name: &mut String) {
+        if name == self.from {
+            *name = self.to.to_owned();
+            self.sites.push(RenameSite {
+                place,
+                before: self.from.to_owned(),
+                after: self.to.to_owned(),
+            });
+        }
+    }
+
+    fn formula(&mut self, place: String, formula: &mut Formula) {
+        let mut map = BTreeMap::new();
+        map.insert(self.from.to_owned(), self.to.to_owned());
+        let new = replace_identifiers(&formula.0, &map, true);
+        if new != formula.0 {
+            self.sites.push(RenameSite {
+                place,
+                before: std::mem::replace(&mut formula.0, new.clone()),
+                after: new,
+            });
+        }
+    }
+
+    fn code(&mut self, place: String, code: &mut GlslCode) {
+        let map = match &self.glsl {
+            Some(map) => map,
+            None => return,
+        };
+        let new = replace_identifiers(&code.0, map, false);
+        if new != code.0 {
+            for (line, (before, after)) in code.0.lines().zip(new.lines()).enumerate() {
+                if before != after {
+                    self.sites.push(RenameSite {
+                        place: format!("{}, line {}", place, line + 1),
+                        before: before.to_owned(),
+                        after: after.to_owned(),
+                    });
+                }
+            }
+            code.0 = new;
+        }
+    }
+
+    fn parameter(&mut self, place: &str, field: &str, param: &mut ParametrizeOrNot) {
+        if let ParametrizeOrNot::Yes(name) = param {
+            self.name(format!("{}: {}", place, field), &mut name.0);
+        }
+    }
+
+    fn matrix(&mut self, kind: RenameKind, place: &str, matrix: &mut Matrix) {
+        use Matrix::*;
+        match (kind, matrix) {
+            (RenameKind::Matrix, Mul { to, what }) => {
+                self.name(format!("{}: mul to", place), to);
+                self.name(format!("{}: what", place), what);
+            }
+            (
+                RenameKind::Matrix,
+                Teleport {
+                    first_portal,
+                    second_portal,
+                    what,
+                },
+            ) => {
+                self.name(format!("{}: from", place), first_portal);
+                self.name(format!("{}: to", place), second_portal);
+                self.name(format!("{}: what", place), what);
+            }
+            (
+                RenameKind::Uniform,
+                Parametrized {
+                    offset,
+                    rotate,
+                    mirror,
+                    scale,
+                },
+            ) => {
+                self.parameter(place, "offset x", &mut offset.x);
+                self.parameter(place, "offset y", &mut offset.y);
+                self.parameter(place, "offset z", &mut offset.z);
+                self.parameter(place, "rotate x", &mut rotate.x);
+                self.parameter(place, "rotate y", &mut rotate.y);
+                self.parameter(place, "rotate z", &mut rotate.z);
+                self.parameter(place, "mirror x", &mut mirror.x);
+                self.parameter(place, "mirror y", &mut mirror.y);
+                self.parameter(place, "mirror z", &mut mirror.z);
+                self.parameter(place, "scale", scale);
+            }
+            _ => {}
+        }
+    }
+
+    fn object_type(&mut self, place: &str, kind: &mut ObjectType) {
+        match kind {
+            ObjectType::Simple(a) => self.name(format!("{}: matrix", place), &mut a.0),
+            ObjectType::Portal(a, b) => {
+                self.name(format!("{}: first", place), &mut a.0);
+                self.name(format!("{}: second", place), &mut b.0);
+            }
+        }
+    }
+}
+
+impl Scene {
+    /// Renames uniform, matrix, material or texture and rewrites every reference to it. With `rewrite_glsl`, identifiers generated from this name (`name_u`, `name_mat`, `name_mat_inv`, teleport matrices, `name_M`, `name_tex`) are also rewritten in user GLSL code. Returns all changed places, so rename on a clone of the scene works as preview.
+    pub fn rename(
+        &mut self,
+        kind: RenameKind,
+        from: &str,
+        to: &str,
+        rewrite_glsl: bool,
+    ) -> Result<Vec<RenameSite>, String> {
+        let names = match kind {
+            RenameKind::Uniform => &self.uniforms.names,
+            RenameKind::Matrix => &self.matrices.names,
+            RenameKind::Material => &self.materials.names,
+            RenameKind::Texture => &self.textures.names,
+        };
+        if !names.iter().any(|x| x == from) {
+            return Err(format!("name '{}' not found", from));
+        }
+        if from == to {
+            return Err("new name is the same".to_owned());
+        }
+        if !is_identifier(to) {
+            return Err(format!("'{}' is not a valid identifier", to));
+        }
+        if names.iter().any(|x| x == to) {
+            return Err(format!("name '{}' already used", to));
+        }
+
+        let glsl = if rewrite_glsl {
+            let mut map = BTreeMap::new();
+            match kind {
+                RenameKind::Uniform => {
+                    map.insert(format!("{}_u", from), format!("{}_u", to));
+                }
+                RenameKind::Matrix => {
+                    let (from, to) = (MatrixName(from.to_owned()), MatrixName(to.to_owned()));
+                    map.insert(from.normal_name(), to.normal_name());
+                    map.insert(from.inverse_name(), to.inverse_name());
+                    for other in self.matrices.names.iter().map(|x| MatrixName(x.clone())) {
+                        let (new_other, other) = if other == from {
+                            (to.clone(), from.clone())
+                        } else {
+                            (other.clone(), other)
+                        };
+                        map.insert(
+                            from.teleport_to_name(&other),
+                            to.teleport_to_name(&new_other),
+                        );
+                        map.insert(
+                            other.teleport_to_name(&from),
+                            new_other.teleport_to_name(&to),
+                        );
+                    }
+                }
+                RenameKind::Material => {
+                    map.insert(format!("{}_M", from), format!("{}_M", to));
+                }
+                RenameKind::Texture => {
+                    map.insert(TextureName::name(from), TextureName::name(to));
+                }
+            }
+            Some(map)
+        } else {
+            None
+        };
+
+        let mut r = Renamer {
+            from,
+            to,
+            glsl,
+            sites: Vec::new(),
+        };
+
+        match kind {
+            RenameKind::Uniform => {
+                for name in &mut self.uniforms.names {
+                    r.name("uniform name".to_owned(), name);
+                }
+                for (name, uniform) in self.uniforms.names.iter().zip(&mut self.uniforms.storage) {
+                    if let AnyUniform::Formula(f) = &mut uniform.0 {
+                        r.formula(format!("uniform '{}': formula", name), f);
+                    }
+                }
+            }
+            RenameKind::Matrix => {
+                for name in &mut self.matrices.names {
+                    r.name("matrix name".to_owned(), name);
+                }
+            }
+            RenameKind::Material => {
+                for name in &mut self.materials.names {
+                    r.name("material name".to_owned(), name);
+                }
+            }
+            RenameKind::Texture => {
+                for name in &mut self.textures.names {
+                    r.name("texture name".to_owned(), name);
+                }
+            }
+        }
+
+        for (name, matrix) in self.matrices.names.iter().zip(&mut self.matrices.storage) {
+            r.matrix(kind, &format!("matrix '{}'", name), &mut matrix.0);
+        }
+
+        for (name, object) in self.objects.names.iter().zip(&mut self.objects.storage) {
+            let place = format!("object '{}'", name);
+            match &mut object.0 {
+                Object::DebugMatrix(a) => {
+                    if kind == RenameKind::Matrix {
+                        r.name(format!("{}: matrix", place), &mut a.0);
+                    }
+                }
+                Object::Flat { kind: t, is_inside } => {
+                    if kind == RenameKind::Matrix {
+                        r.object_type(&place, t);
+                    }
+                    r.code(place, &mut is_inside.0);
+                }
+                Object::Complex {
+                    kind: t, intersect, ..
+                } => {
+                    if kind == RenameKind::Matrix {
+                        r.object_type(&place, t);
+                    }
+                    r.code(place, &mut intersect.0);
+                }
+                Object::Primitive {
+                    matrix,
+                    shape,
+                    material,
+                } => match kind {
+                    RenameKind::Matrix => r.name(format!("{}: matrix", place), &mut matrix.0),
+                    RenameKind::Material => r.name(format!("{}: material", place), &mut material.0),
+                    RenameKind::Uniform => {
+                        for (field, _, param) in shape.parameters_mut() {
+                            r.parameter(&place, field, param);
+                        }
+                    }
+                    RenameKind::Texture => {}
+                },
+            }
+        }
+
+        for (name, material) in self.materials.names.iter().zip(&mut self.materials.storage) {
+            if let crate::gui::material::Material::Complex { code } = &mut material.0 {
+                r.code(format!("material '{}'", name), &mut code.0);
+            }
+        }
+
+        for (name, code) in self.library.names.iter().zip(&mut self.library.storage) {
+            r.code(format!("library '{}'", name), &mut code.0);
+        }
+
+        for (name, stage) in self
+            .animation_stages
+            .names
+            .iter()
+            .zip(&mut self.animation_stages.storage)
+        {
+            for (id, uniform_name) in self.uniforms.ids.iter().zip(self.uniforms.names.iter()) {
+                if let Some(Animation::Changed(AnyUniform::Formula(f)))
+                | Some(Animation::ChangedAndToUser(AnyUniform::Formula(f))) =
+                    stage.uniforms.get_mut(id)
+                {
+                    if kind == RenameKind::Uniform {
+                        r.formula(
+                            format!("animation stage '{}': uniform '{}'", name, uniform_name),
+                            f,
+                        );
+                    }
+                }
+            }
+            for (id, matrix_name) in self.matrices.ids.iter().zip(self.matrices.names.iter()) {
+                if let Some(Animation::Changed(x)) | Some(Animation::ChangedAndToUser(x)) =
+                    stage.matrices.get_mut(id)
+                {
+                    let place = format!("animation stage '{}': matrix '{}'", name, matrix_name);
+                    r.matrix(kind, &place, x);
+                }
+            }
+        }
+
+        Ok(r.sites)
+    }
+}
+
+#[derive(Debug)]
+pub struct RenameWindow {
+    kind: RenameKind,
+    from: String,
+    to: String,
+    rewrite_glsl: bool,
+}
+
+impl Default for RenameWindow {
+    fn default() -> Self {
+        Self {
+            kind: RenameKind::Uniform,
+            from: String::new(),
+            to: String::new(),
+            rewrite_glsl: true,
+        }
+    }
+}
+
+impl RenameWindow {
+    pub fn egui(
+        &mut self,
+        ui: &mut Ui,
+        scene: &mut Scene,
+        formulas_cache: &mut FormulasCache,
+    ) -> WhatChanged {
+        let mut changed = WhatChanged::default();
+
+        egui_combo_label(ui, "Rename:", 45., &mut self.kind);
+        let names = match self.kind {
+            RenameKind::Uniform => &scene.uniforms.names,
+            RenameKind::Matrix => &scene.matrices.names,
+            RenameKind::Material => &scene.materials.names,
+            RenameKind::Texture => &scene.textures.names,
+        };
+        let mut errors_count = 0;
+        egui_existing_name(ui, "From:", 45., &mut self.from, names, &mut errors_count);
+        ui.horizontal(|ui| {
+            egui_label(ui, "To:", 45.);
+            ui.text_edit_singleline(&mut self.to);
+        });
+        ui.checkbox(&mut self.rewrite_glsl, "Rewrite identifiers in GLSL code");
+        if errors_count > 0 || self.to.is_empty() {
+            return changed;
+        }
+
+        ui.separator();
+        let mut preview = scene.clone();
+        match preview.rename(self.kind, &self.from, &self.to, self.rewrite_glsl) {
+            Ok(sites) => {
+                for site in &sites {
+                    ui.label(&site.place);
+                    ui.horizontal_wrapped_for_text(TextStyle::Monospace, |ui| {
+                        ui.add(
+                            Label::new(format!("- {}", site.before))
+                                .text_color(Color32::RED)
+                                .monospace(),
+                        );
+                    });
+                    ui.horizontal_wrapped_for_text(TextStyle::Monospace, |ui| {
+                        ui.add(
+                            Label::new(format!("+ {}", site.after))
+                                .text_color(Color32::GREEN)
+                                .monospace(),
+                        );
+                    });
+                }
+                if ui
+                    .button(format!("Apply ({} changes)", sites.len()))
+                    .clicked()
+                {
+                    *scene = preview;
+                    for (_, uniform) in scene.uniforms.iter() {
+                        if let AnyUniform::Formula(f) = &uniform.0 {
+                            formulas_cache.compile(&f.0);
+                        }
+                    }
+                    self.from = self.to.clone();
+                    changed.shader = true;
+                    changed.uniform = true;
+                }
+            }
+            Err(err) => {
+                ui.horizontal_wrapped_for_text(TextStyle::Body, |ui| {
+                    ui.add(Label::new("Error: ").text_color(Color32::RED));
+                    ui.label(err);
+                });
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::storage::EntityId;
+
+    fn scene() -> Scene {
+        Scene::from_json(
+            r#"{
+                "description_en": "", "description_ru": "",
+                "cam": { "look_at": [0, 0, 0], "alpha": 0, "beta": 1, "r": 3 },
+                "uniforms": {
+                    "names": ["a", "ab", "f"],
+                    "storage": [{ "Bool": true }, { "Bool": false }, { "Formula": "a + ab * a(1) + 1e5" }]
+                },
+                "matrices": {
+                    "names": ["m", "n", "p"],
+                    "storage": [
+                        { "Simple": { "offset": [0, 0, 0], "scale": 1, "rotate": [0, 0, 0], "mirror": [false, false, false] } },
+                        { "Mul": { "to": "m", "what": "m" } },
+                        { "Parametrized": {
+                            "offset": { "x": { "Yes": "a" }, "y": { "No": 0 }, "z": { "No": 0 } },
+                            "rotate": { "x": { "No": 0 }, "y": { "No": 0 }, "z": { "No": 0 } },
+                            "mirror": { "x": { "No": 0 }, "y": { "No": 0 }, "z": { "No": 0 } },
+                            "scale": { "Yes": "ab" }
+                        } }
+                    ]
+                },
+                "objects": {
+                    "names": ["portal"],
+                    "storage": [{ "Flat": { "kind": { "Portal": ["m", "n"] }, "is_inside": "if (a_u == 1) {\n  return NOT_INSIDE;\n}\nvec4 x = m_mat * m_to_n_mat_teleport * m_mat_inv * pos;\nreturn ab_u;" } }]
+                },
+                "textures": { "names": [], "storage": [] },
+                "materials": { "names": [], "storage": [] },
+                "library": { "names": [], "storage": [] },
+                "user_uniforms": { "uniforms": [], "matrices": [] },
+                "animation_stages": {
+                    "names": ["stage"],
+                    "storage": [{
+                        "uniforms": ["Remains", "Remains", { "Changed": { "Formula": "a * 2" } }],
+                        "matrices": ["Remains", { "Changed": { "Teleport": { "first_portal": "m", "second_portal": "n", "what": "p" } } }, "Remains"]
+                    }]
+                },
+                "current_stage": 0
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn rename_uniform() {
+        let mut scene = scene();
+        let sites = scene.rename(RenameKind::Uniform, "a", "b", true).unwrap();
+        assert_eq!(sites.len(), 5);
+        assert_eq!(scene.uniforms.names, vec!["b", "ab", "f"]);
+        assert_eq!(
+            scene.uniforms.storage[2].0,
+            AnyUniform::Formula(Formula("b + ab * a(1) + 1e5".to_owned()))
+        );
+        match &scene.matrices.storage[2].0 {
+            Matrix::Parametrized { offset, scale, .. } => {
+                assert_eq!(offset.x, ParametrizeOrNot::Yes(FormulaName("b".to_owned())));
+                assert_eq!(*scale, ParametrizeOrNot::Yes(FormulaName("ab".to_owned())));
+            }
+            _ => unreachable!(),
+        }
+        match &scene.objects.storage[0].0 {
+            Object::Flat { is_inside, .. } => {
+                assert!(is_inside.0 .0.starts_with("if (b_u == 1)"));
+                assert!(is_inside.0 .0.ends_with("return ab_u;"));
+            }
+            _ => unreachable!(),
+        }
+        match &scene.animation_stages.storage[0].uniforms[&EntityId(2)] {
+            Animation::Changed(x) => {
+                assert_eq!(*x, AnyUniform::Formula(Formula("b * 2".to_owned())))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn rename_matrix() {
+        let mut scene = scene();
+        let sites = scene.rename(RenameKind::Matrix, "m", "k", false).unwrap();
+        assert_eq!(sites.len(), 5);
+        assert_eq!(scene.matrices.names, vec!["k", "n", "p"]);
+        match &scene.objects.storage[0].0 {
+            Object::Flat { kind, is_inside } => {
+                assert!(matches!(kind, ObjectType::Portal(a, b) if a.0 == "k" && b.0 == "n"));
+                assert!(is_inside
+                    .0
+                     .0
+                    .contains("m_mat * m_to_n_mat_teleport * m_mat_inv"));
+            }
+            _ => unreachable!(),
+        }
+
+        let mut scene = self::scene();
+        scene.rename(RenameKind::Matrix, "m", "k", true).unwrap();
+        match &scene.objects.storage[0].0 {
+            Object::Flat { is_inside, .. } => {
+                assert!(is_inside
+                    .0
+                     .0
+                    .contains("k_mat * k_to_n_mat_teleport * k_mat_inv"));
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(scene.validate(), vec![]);
+    }
+
+    #[test]
+    fn rename_in_primitive() {
+        let mut scene = scene();
+        scene.materials.add(
+            "red".to_owned(),
+            crate::gui::material::MaterialComboBox::default(),
+        );
+        let shape = Primitive::Cylinder {
+            radius: ParametrizeOrNot::Yes(FormulaName("a".to_owned())),
+            height: ParametrizeOrNot::No(1.),
+        };
+        let object = Object::Primitive {
+            matrix: MatrixName("m".to_owned()),
+            shape,
+            material: MaterialName("red".to_owned()),
+        };
+        scene
+            .objects
+            .add("cylinder".to_owned(), ObjectComboBox(object));
+
+        let sites = scene.rename(RenameKind::Uniform, "a", "b", true).unwrap();
+        assert!(sites.iter().any(|x| x.place == "object 'cylinder': radius"));
+        scene.rename(RenameKind::Matrix, "m", "k", true).unwrap();
+        scene
+            .rename(RenameKind::Material, "red", "blue", true)
+            .unwrap();
+        match &scene.objects.storage[1].0 {
+            Object::Primitive {
+                matrix,
+                shape: Primitive::Cylinder { radius, .. },
+                material,
+            } => {
+                assert_eq!(matrix.0, "k");
+                assert_eq!(*radius, ParametrizeOrNot::Yes(FormulaName("b".to_owned())));
+                assert_eq!(material.0, "blue");
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(scene.validate(), vec![]);
+    }
+
+    #[test]
+    fn rename_errors() {
+        let mut scene = scene();
+        assert!(scene.rename(RenameKind::Uniform, "x", "y", true).is_err());
+        assert!(scene.rename(RenameKind::Uniform, "a", "ab", true).is_err());
+        assert!(scene.rename(RenameKind::Uniform, "a", "1a", true).is_err());
+        assert!(scene.rename(RenameKind::Matrix, "a", "b", true).is_err());
+    }
+}
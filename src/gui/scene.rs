@@ -1,16 +1,24 @@
-use crate::code_generation::apply_template;
 use crate::gui::glsl::*;
 
+use crate::bvh::{self, Aabb};
 use crate::code_generation::*;
+use crate::dead_code_elimination::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::glsl_validation::*;
 use crate::gui::animation::*;
 use crate::gui::common::*;
+use crate::gui::description::*;
+use crate::gui::format::*;
 use crate::gui::material::*;
 use crate::gui::matrix::*;
+use crate::gui::migration::*;
 use crate::gui::object::*;
 use crate::gui::storage::*;
 use crate::gui::texture::*;
 use crate::gui::uniform::*;
 use crate::shader_error_parser::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::wgsl::*;
 
 use egui::*;
 use glam::*;
@@ -27,79 +35,59 @@ pub struct CamSettings {
     pub r: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Scene {
-    pub description_en: String,
-    pub description_ru: String,
-
-    pub cam: CamSettings,
-
-    pub uniforms: StorageWithNames<AnyUniformComboBox>,
-
-    pub matrices: StorageWithNames<MatrixComboBox>,
-    objects: StorageWithNames<ObjectComboBox>,
-
-    pub textures: StorageWithNames<TextureName>,
-
-    materials: StorageWithNames<MaterialComboBox>,
-    library: StorageWithNames<LibraryCode>,
-
-    user_uniforms: GlobalUserUniforms,
-    animation_stages: StorageWithNames<AnimationStage>,
-
-    current_stage: usize,
+impl CamSettings {
+    pub fn get_matrix(&self) -> Mat4 {
+        let pos = Vec3::new(
+            self.beta.sin() * self.alpha.cos(),
+            self.beta.cos(),
+            self.beta.sin() * self.alpha.sin(),
+        ) * self.r
+            + self.look_at;
+
+        let k = (self.look_at - pos).normalize();
+        let i = k.cross(Vec3::new(0., 1., 0.)).normalize();
+        let j = k.cross(i).normalize();
+
+        Mat4::from_cols(
+            Vec4::new(i.x, i.y, i.z, 0.),
+            Vec4::new(j.x, j.y, j.z, 0.),
+            Vec4::new(k.x, k.y, k.z, 0.),
+            Vec4::new(pos.x, pos.y, pos.z, 1.),
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OldScene {
-    pub description_en: String,
-    pub description_ru: String,
+pub struct Scene {
+    pub version: u32,
 
-    pub uniforms: StorageWithNames<AnyUniformComboBox>,
+    pub descriptions: Descriptions,
 
     pub cam: CamSettings,
 
+    pub uniforms: StorageWithNames<AnyUniformComboBox>,
+
     pub matrices: StorageWithNames<MatrixComboBox>,
-    objects: StorageWithNames<ObjectComboBox>,
+    pub objects: StorageWithNames<ObjectComboBox>,
 
     pub textures: StorageWithNames<TextureName>,
 
-    materials: StorageWithNames<MaterialComboBox>,
-    library: StorageWithNames<LibraryCode>,
+    pub materials: StorageWithNames<MaterialComboBox>,
+    pub library: StorageWithNames<LibraryCode>,
 
-    user_uniforms: GlobalUserUniforms,
-    animation_stages: StorageWithNames<AnimationStage>,
+    pub user_uniforms: GlobalUserUniforms,
+    pub animation_stages: StorageWithNames<AnimationStage>,
 
     current_stage: usize,
 }
 
-impl From<OldScene> for Scene {
-    fn from(old: OldScene) -> Scene {
-        Scene {
-            description_en: old.description_en,
-            description_ru: old.description_ru,
-
-            cam: old.cam,
-
-            uniforms: old.uniforms,
-
-            matrices: old.matrices,
-            objects: old.objects,
-
-            textures: old.textures,
-
-            materials: old.materials,
-            library: old.library,
-
-            user_uniforms: old.user_uniforms,
-            animation_stages: old.animation_stages,
-
-            current_stage: old.current_stage,
-        }
+impl Scene {
+    /// Loads scene of any known version, upgrading it to the current one.
+    pub fn from_json(s: &str) -> Result<Scene, String> {
+        let value = serde_json::from_str(s).map_err(|err| err.to_string())?;
+        serde_json::from_value(migrate(value)?).map_err(|err| err.to_string())
     }
-}
 
-impl Scene {
     pub fn init(&mut self, data: &mut Data) {
         for (_, object) in self.uniforms.iter() {
             if let AnyUniform::Formula(f) = &object.0 {
@@ -108,12 +96,6 @@ impl Scene {
         }
         data.errors = Default::default();
         data.show_error_window = false;
-        self.user_uniforms
-            .uniforms
-            .resize(self.uniforms.storage.len(), false);
-        self.user_uniforms
-            .matrices
-            .resize(self.matrices.storage.len(), false);
         drop(self.init_stage(self.current_stage));
     }
 
@@ -131,14 +113,15 @@ impl Scene {
 
         ui.horizontal(|ui| {
             if ui.button("Export").clicked() {
-                let s = serde_json::to_string(self).unwrap();
-                data.to_export = Some(s);
+                data.to_export = Some(self.to_text(data.export_format));
             }
+            ui.selectable_value(&mut data.export_format, SceneFormat::Json, "JSON");
+            ui.selectable_value(&mut data.export_format, SceneFormat::Yaml, "YAML");
             if ui
                 .add(Button::new("Recompile").enabled(*should_recompile))
                 .clicked()
             {
-                match self.get_new_material() {
+                match self.get_new_material(&data.shader_options) {
                     Ok(m) => {
                         data.reload_textures = true;
                         material = Some(Ok(m));
@@ -161,53 +144,14 @@ impl Scene {
         CollapsingHeader::new("Description")
             .default_open(false)
             .show(ui, |ui| {
-                CollapsingHeader::new("English")
-                    .default_open(false)
-                    .show(ui, |ui| {
-                        ui.horizontal(|ui| {
-                            ui.selectable_value(&mut data.description_en_edit, false, "View");
-                            ui.selectable_value(&mut data.description_en_edit, true, "Edit");
-                        });
-                        if data.description_en_edit {
-                            ui.add(
-                                TextEdit::multiline(&mut self.description_en)
-                                    .text_style(TextStyle::Monospace),
-                            );
-                        } else {
-                            egui::experimental::easy_mark(ui, &self.description_en);
-                        }
-                    });
-                CollapsingHeader::new("Russian")
-                    .default_open(false)
-                    .show(ui, |ui| {
-                        ui.horizontal(|ui| {
-                            ui.selectable_value(&mut data.description_ru_edit, false, "View");
-                            ui.selectable_value(&mut data.description_ru_edit, true, "Edit");
-                        });
-                        if data.description_ru_edit {
-                            ui.add(
-                                TextEdit::multiline(&mut self.description_ru)
-                                    .text_style(TextStyle::Monospace),
-                            );
-                        } else {
-                            egui::experimental::easy_mark(ui, &self.description_ru);
-                        }
-                    });
+                self.descriptions
+                    .egui(ui, &mut data.description_edit, &mut data.new_locale);
             });
 
         changed |= self
             .uniforms
             .rich_egui(ui, &mut data.formulas_cache, "Uniforms");
 
-        if changed.uniform {
-            self.user_uniforms
-                .uniforms
-                .resize(self.uniforms.storage.len(), false);
-             self.user_uniforms
-                .matrices
-                .resize(self.matrices.storage.len(), false);
-        }
-
         ui.collapsing("Calculated uniforms", |ui| {
             for name in self.uniforms.names_iter() {
                 ui.horizontal(|ui| {
@@ -233,7 +177,12 @@ impl Scene {
         with_swapped!(x => (self.uniforms.names, data.matrix_recursion_error);
             changed |= self.matrices.rich_egui(ui, &mut x, "Matrices"));
 
-        with_swapped!(x => (self.matrices.names, data.errors);
+        with_swapped!(x => (
+                self.matrices.names,
+                self.materials.names,
+                self.uniforms.names,
+                data.errors
+            );
             changed |= self.objects.rich_egui(ui, &mut x, "Objects"));
 
         changed |= self.materials.rich_egui(ui, &mut data.errors, "Materials");
@@ -247,9 +196,9 @@ impl Scene {
             .rich_egui(ui, &mut data.errors, "User GLSL code");
 
         ui.collapsing("Global user uniforms", |ui| {
-            changed |=
-                self.user_uniforms
-                    .egui(ui, &mut self.matrices.names, &mut self.uniforms.names);
+            changed |= self
+                .user_uniforms
+                .egui(ui, &self.matrices, &self.uniforms);
         });
 
         with_swapped!(x => (self.matrices, self.uniforms, self.user_uniforms);
@@ -257,6 +206,10 @@ impl Scene {
                 .animation_stages
                 .rich_egui(ui, &mut x, "Animation stages"));
 
+        ui.collapsing("Rename", |ui| {
+            changed |= data.rename.egui(ui, self, &mut data.formulas_cache);
+        });
+
         ui.separator();
 
         ui.horizontal(|ui| {
@@ -264,10 +217,45 @@ impl Scene {
                 data.show_glsl_library = true;
             }
             if ui.button("View generated GLSL code").clicked() {
-                data.show_compiled_code = Some(self.generate_shader_code().storage);
+                let code = match self.generate_shader_code(&data.shader_options) {
+                    Ok(code) => code.storage,
+                    Err(errors) => errors.iter().map(|err| format!("{}\n", err)).collect(),
+                };
+                data.show_compiled_code = Some(code);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui.button("View WGSL code").clicked() {
+                let code = match self.generate_wgsl(&data.shader_options) {
+                    Ok(code) => code,
+                    Err((_, message, _)) => message,
+                };
+                data.show_compiled_code = Some(code);
             }
         });
 
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.label("GLSL version:");
+            for dialect in ShaderDialect::OPENGL.iter() {
+                let selected = data.shader_options.dialect == *dialect;
+                if ui.selectable_label(selected, dialect.name()).clicked() && !selected {
+                    data.shader_options.dialect = *dialect;
+                    *should_recompile = true;
+                }
+            }
+        });
+        if check_changed(&mut data.shader_options.pack_uniforms, |pack| {
+            drop(ui.checkbox(pack, "Pack uniforms into texture"))
+        }) {
+            *should_recompile = true;
+        }
+
+        for (source, local_errors) in self.generated_errors(&data.errors) {
+            ui.separator();
+            ui.label(format!("Errors in code generated for {}:", source));
+            egui_errors(ui, local_errors);
+        }
+
         if let Some(local_errors) = data.errors.0.get(&ErrId::default()).cloned() {
             ui.separator();
             ui.horizontal(|ui| {
@@ -284,13 +272,47 @@ impl Scene {
 }
 
 impl Scene {
+    /// Errors of entities that don't have their own place to show them: matrices, uniforms and textures.
+    fn generated_errors<'a>(
+        &self,
+        errors: &'a ShaderErrors,
+    ) -> Vec<(String, &'a [(usize, String)])> {
+        let mut result = Vec::new();
+        for (pos, (name, matrix)) in self.matrices.iter().enumerate() {
+            if let Some(local_errors) = errors.get_errors(&matrix.0, self.matrices.ids[pos]) {
+                result.push((format!("matrix `{}`", name), local_errors));
+            }
+        }
+        for (pos, (name, uniform)) in self.uniforms.iter().enumerate() {
+            if let Some(local_errors) = errors.get_errors(&uniform.0, self.uniforms.ids[pos]) {
+                result.push((format!("uniform `{}`", name), local_errors));
+            }
+        }
+        for (pos, (name, texture)) in self.textures.iter().enumerate() {
+            if let Some(local_errors) = errors.get_errors(texture, self.textures.ids[pos]) {
+                result.push((format!("texture `{}`", name), local_errors));
+            }
+        }
+        result
+    }
+
     pub fn errors_count(&mut self, _: usize, data: &mut Data) -> usize {
         with_swapped!(x => (self.uniforms.names, data.matrix_recursion_error);
             self.matrices.errors_count(0, &mut x))
-            + with_swapped!(x => (self.matrices.names, data.errors);
+            + with_swapped!(x => (
+                    self.matrices.names,
+                    self.materials.names,
+                    self.uniforms.names,
+                    data.errors
+                );
                 self.objects.errors_count(0, &mut x))
             + self.materials.errors_count(0, &mut data.errors)
             + self.library.errors_count(0, &mut data.errors)
+            + self
+                .generated_errors(&data.errors)
+                .iter()
+                .map(|(_, local_errors)| local_errors.len())
+                .sum::<usize>()
             + if let Some(local_errors) = data.errors.0.get(&ErrId::default()).cloned() {
                 local_errors.len()
             } else {
@@ -305,26 +327,50 @@ pub trait UniformStruct {
 }
 
 impl Scene {
-    pub fn textures(&self) -> Vec<String> {
-        self.textures
+    pub fn textures(&self, options: &ShaderOptions) -> Vec<String> {
+        let mut result = self
+            .textures
             .names_iter()
             .cloned()
             .map(|x| TextureName::name(&x))
-            .collect()
+            .collect::<Vec<_>>();
+        if options.pack_uniforms {
+            result.push(PACKED_TEXTURE.to_owned());
+        }
+        result
     }
 
-    pub fn uniforms(&self) -> Vec<(String, UniformType)> {
+    /// Uniforms of generated shader, matrices that are folded into constants and uniforms that are packed into texture are not included.
+    pub fn uniforms(&self, options: &ShaderOptions) -> Vec<(String, UniformType)> {
+        let mut result = if options.pack_uniforms {
+            Vec::new()
+        } else {
+            self.scene_uniforms(options)
+        };
+        result.extend(vec![
+            ("_camera".to_owned(), UniformType::Mat4),
+            ("_resolution".to_owned(), UniformType::Float2),
+            ("_ray_tracing_depth".to_owned(), UniformType::Int1),
+            ("_offset_after_material".to_owned(), UniformType::Float1),
+            ("_view_angle".to_owned(), UniformType::Float1),
+            ("_panini_param".to_owned(), UniformType::Float1),
+        ]);
+        result
+    }
+
+    /// Uniforms of matrices and user uniforms, without matrices that are folded into constants.
+    fn scene_uniforms(&self, options: &ShaderOptions) -> Vec<(String, UniformType)> {
         use Object::*;
         use ObjectType::*;
 
         let mut result = Vec::new();
         for (_, object) in self.objects.iter() {
             match &object.0 {
-                DebugMatrix(matrix) => {
+                DebugMatrix(matrix) | Primitive { matrix, .. } => {
                     result.push(matrix.normal_name());
                     result.push(matrix.inverse_name());
                 }
-                Flat { kind, is_inside: _ } | Complex { kind, intersect: _ } => match kind {
+                Flat { kind, .. } | Complex { kind, .. } => match kind {
                     Simple(matrix) => {
                         result.push(matrix.normal_name());
                         result.push(matrix.inverse_name());
@@ -343,10 +389,12 @@ impl Scene {
             }
         }
 
+        let folded = self.folded_matrices(options);
         let mut result = result
             .into_iter()
             .collect::<BTreeSet<_>>()
             .into_iter()
+            .filter(|name| !folded.contains_key(name))
             .map(|name| (name, UniformType::Mat4))
             .collect::<Vec<_>>();
 
@@ -361,16 +409,160 @@ impl Scene {
             }
         }
 
-        result.extend(vec![
-            ("_camera".to_owned(), UniformType::Mat4),
-            ("_resolution".to_owned(), UniformType::Float2),
-            ("_ray_tracing_depth".to_owned(), UniformType::Int1),
-            ("_offset_after_material".to_owned(), UniformType::Float1),
-            ("_view_angle".to_owned(), UniformType::Float1),
-            ("_use_panini_projection".to_owned(), UniformType::Int1),
-            ("_panini_param".to_owned(), UniformType::Float1),
-        ]);
+        result
+    }
+
+    /// Uniforms that are packed into texture with their offsets, and count of packed floats. Nothing is packed when option is disabled.
+    fn packed_uniforms(
+        &self,
+        options: &ShaderOptions,
+    ) -> (Vec<(String, UniformType, usize)>, usize) {
+        let mut result = Vec::new();
+        let mut offset = 0;
+        if !options.pack_uniforms {
+            return (result, offset);
+        }
+
+        for (name, kind) in self.scene_uniforms(options) {
+            let size = match kind {
+                UniformType::Mat4 => 16,
+                _ => 1,
+            };
+            result.push((name, kind, offset));
+            offset += size;
+        }
+        (result, offset)
+    }
+
+    /// Matrices that don't depend on uniforms and can't be changed by user or by animation stages: `Simple` matrices and `Mul`, `Teleport` of static matrices.
+    pub fn static_matrices(&self) -> BTreeSet<String> {
+        let is_changeable = |id: &EntityId| {
+            self.user_uniforms.matrices.contains(id)
+                || self.animation_stages.storage.iter().any(|stage| {
+                    !matches!(stage.matrices.get(id), None | Some(Animation::Remains))
+                })
+        };
+
+        // Matrices with recursion or with unknown names never become static.
+        let mut result = BTreeSet::new();
+        loop {
+            let mut changed = false;
+            for (pos, (name, matrix)) in self.matrices.iter().enumerate() {
+                if result.contains(name) || is_changeable(&self.matrices.ids[pos]) {
+                    continue;
+                }
+                let is_static = match &matrix.0 {
+                    Matrix::Simple { .. } => true,
+                    Matrix::Mul { to, what } => result.contains(to) && result.contains(what),
+                    Matrix::Teleport {
+                        first_portal,
+                        second_portal,
+                        what,
+                    } => {
+                        result.contains(first_portal)
+                            && result.contains(second_portal)
+                            && result.contains(what)
+                    }
+                    Matrix::Parametrized { .. } => false,
+                };
+                if is_static {
+                    result.insert(name.clone());
+                    changed = true;
+                }
+            }
+            if !changed {
+                return result;
+            }
+        }
+    }
+
+    /// Values of matrix uniforms that are written into shader as constants.
+    fn folded_matrices(&self, options: &ShaderOptions) -> BTreeMap<String, Mat4> {
+        use Object::*;
+        use ObjectType::*;
+
+        let mut result = BTreeMap::new();
+        if !options.fold_static_matrices {
+            return result;
+        }
 
+        let static_matrices = self.static_matrices();
+        let formulas_cache = FormulasCache::default();
+        let get = |name: &MatrixName| {
+            if !static_matrices.contains(&name.0) {
+                return None;
+            }
+            match self.matrices.get(&name.0, &self.uniforms, &formulas_cache) {
+                GetEnum::Ok(matrix) => Some(matrix),
+                _ => None,
+            }
+        };
+        for (_, object) in self.objects.iter() {
+            let (a, b) = match &object.0 {
+                DebugMatrix(matrix)
+                | Primitive { matrix, .. }
+                | Flat {
+                    kind: Simple(matrix),
+                    ..
+                }
+                | Complex {
+                    kind: Simple(matrix),
+                    ..
+                } => (matrix, None),
+                Flat {
+                    kind: Portal(a, b), ..
+                }
+                | Complex {
+                    kind: Portal(a, b), ..
+                } => (a, Some(b)),
+            };
+            let ma = match get(a) {
+                Some(ma) => ma,
+                None => continue,
+            };
+            result.insert(a.normal_name(), ma);
+            result.insert(a.inverse_name(), ma.inverse());
+            if let Some((b, mb)) = b.and_then(|b| Some((b, get(b)?))) {
+                result.insert(b.normal_name(), mb);
+                result.insert(b.inverse_name(), mb.inverse());
+                result.insert(a.teleport_to_name(b), mb * ma.inverse());
+                result.insert(b.teleport_to_name(a), ma * mb.inverse());
+            }
+        }
+        result
+    }
+
+    /// Entities that produce uniforms from `uniforms`, teleport matrix belongs to matrix from which it teleports.
+    fn uniform_sources(&self) -> BTreeMap<String, ErrId> {
+        let mut result = BTreeMap::new();
+        for (pos, (name, matrix)) in self.matrices.iter().enumerate() {
+            let identifier = matrix.0.identifier(self.matrices.ids[pos]);
+            let name = MatrixName(name.clone());
+            result.insert(name.normal_name(), identifier);
+            result.insert(name.inverse_name(), identifier);
+            for (_, object) in self.objects.iter() {
+                if let Object::Flat {
+                    kind: ObjectType::Portal(a, b),
+                    ..
+                }
+                | Object::Complex {
+                    kind: ObjectType::Portal(a, b),
+                    ..
+                } = &object.0
+                {
+                    if *a == name {
+                        result.insert(a.teleport_to_name(b), identifier);
+                    }
+                    if *b == name {
+                        result.insert(b.teleport_to_name(a), identifier);
+                    }
+                }
+            }
+        }
+        for (pos, (name, uniform)) in self.uniforms.iter().enumerate() {
+            let identifier = uniform.0.identifier(self.uniforms.ids[pos]);
+            result.insert(format!("{}_u", name), identifier);
+        }
         result
     }
 
@@ -381,23 +573,15 @@ impl Scene {
         uniforms: &StorageWithNames<AnyUniformComboBox>,
     ) {
         data.matrix_recursion_error.0.clear();
+        let mut sink = UniformsSink::new(self, material, &data.shader_options);
         macro_rules! local_try {
             ($a:expr, $c:ident, $b: expr) => {
                 match self.matrices.get(&$a.0, uniforms, &data.formulas_cache) {
-                    GetEnum::Ok($c) => {
-                        *data
-                            .matrix_recursion_error
-                            .0
-                            .entry($a.clone())
-                            .or_insert(false) = false;
-                        $b
-                    }
+                    GetEnum::Ok($c) => $b,
                     GetEnum::Recursion => {
-                        *data
-                            .matrix_recursion_error
-                            .0
-                            .entry($a.clone())
-                            .or_insert(false) = true;
+                        if let Some(id) = self.matrices.id_by_name(&$a.0) {
+                            data.matrix_recursion_error.0.insert(id, true);
+                        }
                     }
                     _ => {}
                 }
@@ -407,29 +591,29 @@ impl Scene {
         use ObjectType::*;
         for (_, object) in self.objects.iter() {
             match &object.0 {
-                DebugMatrix(matrix) => {
+                DebugMatrix(matrix) | Primitive { matrix, .. } => {
                     local_try!(matrix, m, {
-                        material.set_uniform(&matrix.normal_name(), m);
-                        material.set_uniform(&matrix.inverse_name(), m.inverse());
+                        sink.set_matrix(matrix.normal_name(), m);
+                        sink.set_matrix(matrix.inverse_name(), m.inverse());
                     })
                 }
-                Flat { kind, is_inside: _ } | Complex { kind, intersect: _ } => match kind {
+                Flat { kind, .. } | Complex { kind, .. } => match kind {
                     Simple(matrix) => {
                         local_try!(matrix, m, {
-                            material.set_uniform(&matrix.normal_name(), m);
-                            material.set_uniform(&matrix.inverse_name(), m.inverse());
+                            sink.set_matrix(matrix.normal_name(), m);
+                            sink.set_matrix(matrix.inverse_name(), m.inverse());
                         })
                     }
                     Portal(a, b) => {
                         local_try!(a, ma, {
                             local_try!(b, mb, {
-                                material.set_uniform(&a.normal_name(), ma);
-                                material.set_uniform(&a.inverse_name(), ma.inverse());
-                                material.set_uniform(&b.normal_name(), mb);
-                                material.set_uniform(&b.inverse_name(), mb.inverse());
-                                material.set_uniform(&a.teleport_to_name(b), mb * ma.inverse());
+                                sink.set_matrix(a.normal_name(), ma);
+                                sink.set_matrix(a.inverse_name(), ma.inverse());
+                                sink.set_matrix(b.normal_name(), mb);
+                                sink.set_matrix(b.inverse_name(), mb.inverse());
+                                sink.set_matrix(a.teleport_to_name(b), mb * ma.inverse());
                                 if a != b {
-                                    material.set_uniform(&b.teleport_to_name(a), ma * mb.inverse());
+                                    sink.set_matrix(b.teleport_to_name(a), ma * mb.inverse());
                                 }
                             })
                         })
@@ -441,31 +625,277 @@ impl Scene {
         for name in self.uniforms.names_iter() {
             let name_u = format!("{}_u", name);
             match self.uniforms.get(&name, uniforms, &data.formulas_cache) {
-                GetEnum::Ok(result) => match result {
-                    AnyUniformResult::Bool(b) => material.set_uniform(&name_u, b as i32),
-                    AnyUniformResult::Int(i) => material.set_uniform(&name_u, i),
-                    AnyUniformResult::Float(f) => material.set_uniform(&name_u, f as f32),
-                },
+                GetEnum::Ok(result) => sink.set_number(&name_u, result),
                 _ => {
                     println!("Error getting `{}` uniform", name);
                 }
             }
         }
+
+        if data.shader_options.pack_uniforms {
+            upload_packed_uniforms(material, data, &sink.packed);
+        }
+    }
+}
+
+/// Adds code that is executed only when transformed ray can hit bounding volume nearer than current intersection.
+fn add_bounds_check(
+    result: &mut StringStorage,
+    bounds: &BoundingVolume,
+    f: impl FnOnce(&mut StringStorage),
+) {
+    match bounds.distance_code("transformed_ray") {
+        Some(distance) => {
+            result.add_string(format!("if (bounds_nearer(i, {}, len)) {{\n", distance));
+            f(result);
+            result.add_string("}\n");
+        }
+        None => f(result),
+    }
+}
+
+/// Destination of uniform values: separate uniforms of material, or floats that are packed into texture.
+struct UniformsSink {
+    material: macroquad::material::Material,
+    folded: BTreeMap<String, Mat4>,
+    offsets: BTreeMap<String, usize>,
+    packed: Vec<f32>,
+}
+
+impl UniformsSink {
+    fn new(
+        scene: &Scene,
+        material: macroquad::material::Material,
+        options: &ShaderOptions,
+    ) -> Self {
+        let (packed_uniforms, size) = scene.packed_uniforms(options);
+        UniformsSink {
+            material,
+            folded: scene.folded_matrices(options),
+            offsets: packed_uniforms
+                .into_iter()
+                .map(|(name, _, offset)| (name, offset))
+                .collect(),
+            packed: vec![0.0; size],
+        }
+    }
+
+    fn set_matrix(&mut self, name: String, matrix: Mat4) {
+        if self.folded.contains_key(&name) {
+            return;
+        }
+        match self.offsets.get(&name) {
+            Some(offset) => {
+                self.packed[*offset..*offset + 16].copy_from_slice(&matrix.to_cols_array())
+            }
+            None => self.material.set_uniform(&name, matrix),
+        }
+    }
+
+    fn set_number(&mut self, name: &str, value: AnyUniformResult) {
+        match (self.offsets.get(name), value) {
+            (Some(offset), AnyUniformResult::Bool(b)) => self.packed[*offset] = b as i32 as f32,
+            (Some(offset), AnyUniformResult::Int(i)) => self.packed[*offset] = i as f32,
+            (Some(offset), AnyUniformResult::Float(f)) => self.packed[*offset] = f as f32,
+            (None, AnyUniformResult::Bool(b)) => self.material.set_uniform(name, b as i32),
+            (None, AnyUniformResult::Int(i)) => self.material.set_uniform(name, i),
+            (None, AnyUniformResult::Float(f)) => self.material.set_uniform(name, f as f32),
+        }
+    }
+}
+
+/// Width and height of texture for `len` packed floats.
+fn packed_texture_size(len: usize) -> (usize, usize) {
+    let height = (0..len.max(1)).step_by(PACKED_TEXTURE_WIDTH).len();
+    (PACKED_TEXTURE_WIDTH, height)
+}
+
+/// Bytes of texture with packed floats: every texel holds bytes of one float in little endian, they are decoded by `_packed_float` in shader.
+fn packed_texture_bytes(values: &[f32]) -> Vec<u8> {
+    let (width, height) = packed_texture_size(values.len());
+    let mut result = values
+        .iter()
+        .flat_map(|x| x.to_le_bytes().to_vec())
+        .collect::<Vec<u8>>();
+    result.resize(width * height * 4, 0);
+    result
+}
+
+/// Writes packed uniforms into texture, which is recreated only when its size is changed.
+fn upload_packed_uniforms(
+    material: macroquad::material::Material,
+    data: &mut Data,
+    values: &[f32],
+) {
+    use macroquad::prelude::miniquad::{FilterMode, Texture};
+    use macroquad::prelude::{get_internal_gl, Texture2D};
+
+    let (width, height) = packed_texture_size(values.len());
+    let bytes = packed_texture_bytes(values);
+    let context = unsafe { get_internal_gl().quad_context };
+    let texture = match data.packed_uniforms_texture {
+        Some(texture) if texture.width as usize == width && texture.height as usize == height => {
+            texture.update(context, &bytes);
+            texture
+        }
+        previous => {
+            if let Some(previous) = previous {
+                previous.delete();
+            }
+            let texture = Texture::from_rgba8(context, width as u16, height as u16, &bytes);
+            texture.set_filter(context, FilterMode::Nearest);
+            data.packed_uniforms_texture = Some(texture);
+            texture
+        }
+    };
+    material.set_texture(PACKED_TEXTURE, Texture2D::from_miniquad_texture(texture));
+}
+
+/// Version of GLSL that shader is generated for. GLSL ES 1.00 works everywhere including WebGL 1, but has restrictions like constant loop bounds. GLSL 4.50 is written for Vulkan and is used only to translate shader to WGSL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderDialect {
+    GlslEs100,
+    Glsl330,
+    Glsl450,
+}
+
+impl Default for ShaderDialect {
+    fn default() -> Self {
+        ShaderDialect::GlslEs100
+    }
+}
+
+impl ShaderDialect {
+    pub const ALL: [ShaderDialect; 3] = [
+        ShaderDialect::GlslEs100,
+        ShaderDialect::Glsl330,
+        ShaderDialect::Glsl450,
+    ];
+
+    /// Dialects that can be compiled by OpenGL.
+    pub const OPENGL: [ShaderDialect; 2] = [ShaderDialect::GlslEs100, ShaderDialect::Glsl330];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShaderDialect::GlslEs100 => "GLSL ES 1.00",
+            ShaderDialect::Glsl330 => "GLSL 3.30",
+            ShaderDialect::Glsl450 => "GLSL 4.50 (Vulkan)",
+        }
+    }
+
+    /// Code at the start of fragment shader, before the library.
+    fn prelude(self) -> &'static str {
+        match self {
+            ShaderDialect::GlslEs100 => PRELUDE_ES_100,
+            ShaderDialect::Glsl330 => PRELUDE_330,
+            ShaderDialect::Glsl450 => PRELUDE_450,
+        }
+    }
+
+    /// There is no vertex shader for dialects that can't be compiled by OpenGL.
+    pub fn vertex_shader(self) -> Option<&'static str> {
+        match self {
+            ShaderDialect::GlslEs100 => Some(VERTEX_SHADER),
+            ShaderDialect::Glsl330 => Some(VERTEX_SHADER_330),
+            ShaderDialect::Glsl450 => None,
+        }
+    }
+}
+
+/// Settings that change generated code, so shader is recompiled when they are changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderOptions {
+    pub panini: bool,
+    pub dialect: ShaderDialect,
+
+    /// Static matrices are written as constants instead of uniforms. Disabled while scene is edited, so changes of matrices don't need recompilation.
+    pub fold_static_matrices: bool,
+
+    /// Matrices and user uniforms are packed into one texture instead of separate uniforms. This removes limits on count of uniforms, but every read of uniform becomes texture reads.
+    pub pack_uniforms: bool,
+}
+
+impl Default for ShaderOptions {
+    fn default() -> Self {
+        ShaderOptions {
+            panini: false,
+            dialect: ShaderDialect::default(),
+            fold_static_matrices: true,
+            pack_uniforms: false,
+        }
+    }
+}
+
+impl ShaderOptions {
+    /// Flags of conditional sections in `frag.glsl`.
+    fn flags(&self) -> BTreeMap<&'static str, bool> {
+        vec![
+            ("panini", self.panini),
+            ("glsl_es_100", self.dialect == ShaderDialect::GlslEs100),
+        ]
+        .into_iter()
+        .collect()
     }
 }
 
 impl Scene {
-    pub fn generate_shader_code(&self) -> StringStorage {
+    pub fn generate_shader_code(
+        &self,
+        options: &ShaderOptions,
+    ) -> Result<StringStorage, Vec<TemplateError>> {
         let mut storages: BTreeMap<String, StringStorage> = BTreeMap::new();
 
+        storages.insert("prelude".to_owned(), {
+            let mut result = StringStorage::default();
+            result.add_string(options.dialect.prelude());
+            result
+        });
+
         storages.insert("uniforms".to_owned(), {
+            let sources = self.uniform_sources();
             let mut result = StringStorage::default();
+            for (name, matrix) in self.folded_matrices(options) {
+                let values = matrix
+                    .to_cols_array()
+                    .iter()
+                    .map(|x| format!("{:e}", x))
+                    .collect::<Vec<_>>();
+                let code = format!("const mat4 {} = mat4({});\n", name, values.join(", "));
+                match sources.get(&name) {
+                    Some(id) => result.add_generated(*id, |result| result.add_string(code)),
+                    None => result.add_string(code),
+                }
+            }
+            let (packed_uniforms, size) = self.packed_uniforms(options);
+            if options.pack_uniforms {
+                let (width, height) = packed_texture_size(size);
+                result.add_string(format!(
+                    "uniform sampler2D {};\nconst vec2 _packed_size = vec2({}., {}.);\n",
+                    PACKED_TEXTURE, width, height
+                ));
+                result.add_string(PACKED_UNIFORMS_CODE);
+            }
+            for (name, kind, offset) in packed_uniforms {
+                let code = format!(
+                    "#define {} {}\n",
+                    name,
+                    match kind {
+                        UniformType::Mat4 => format!("_packed_mat4({}.)", offset),
+                        UniformType::Int1 => format!("int(_packed_float({}.))", offset),
+                        _ => format!("_packed_float({}.)", offset),
+                    }
+                );
+                match sources.get(&name) {
+                    Some(id) => result.add_generated(*id, |result| result.add_string(code)),
+                    None => result.add_string(code),
+                }
+            }
             for (name, kind) in self
-                .uniforms()
+                .uniforms(options)
                 .into_iter()
                 .filter(|(name, _)| !name.starts_with("_"))
             {
-                result.add_string(format!(
+                let code = format!(
                     "uniform {} {};\n",
                     match kind {
                         UniformType::Mat4 => "mat4",
@@ -480,15 +910,21 @@ impl Scene {
                         UniformType::Int4 => unreachable!(),
                     },
                     name
-                ))
+                );
+                match sources.get(&name) {
+                    Some(id) => result.add_generated(*id, |result| result.add_string(code)),
+                    None => result.add_string(code),
+                }
             }
             result
         });
 
         storages.insert("textures".to_owned(), {
             let mut result = StringStorage::default();
-            for name in self.textures.names_iter() {
-                result.add_string(format!("uniform sampler2D {};\n", TextureName::name(name)));
+            for (pos, (name, texture)) in self.textures.iter().enumerate() {
+                result.add_generated(texture.identifier(self.textures.ids[pos]), |result| {
+                    result.add_string(format!("uniform sampler2D {};\n", TextureName::name(name)))
+                });
             }
             result
         });
@@ -501,55 +937,60 @@ impl Scene {
             use Material::*;
             for (pos, (name, material)) in self.materials.iter().enumerate() {
                 let name_m = format!("{}_M", name);
+                let identifier = material.0.identifier(self.materials.ids[pos]);
 
-                material_defines.add_string(format!(
-                    "#define {} (USER_MATERIAL_OFFSET + {})\n",
-                    name_m, counter
-                ));
+                material_defines.add_generated(identifier, |material_defines| {
+                    material_defines.add_string(format!(
+                        "#define {} (USER_MATERIAL_OFFSET + {})\n",
+                        name_m, counter
+                    ))
+                });
                 counter += 1;
 
-                material_processing
-                    .add_string(format!("}} else if (i.material == {}) {{\n", name_m));
-
-                match &material.0 {
-                    Simple {
-                        color,
-                        normal_coef,
-                        grid,
-                        grid_scale,
-                        grid_coef,
-                    } => {
-                        material_processing.add_string(
-                            format!(
-                                "return material_simple(hit, r, vec3({:e}, {:e}, {:e}), {:e}, {}, {:e}, {:e});\n",
-                                color[0], color[1], color[2], normal_coef, grid, grid_scale, grid_coef,
-                            )
-                        );
-                    }
-                    Reflect { add_to_color } => {
-                        material_processing.add_string(format!(
-                            "return material_reflect(hit, r, vec3({:e}, {:e}, {:e}));\n",
-                            add_to_color[0], add_to_color[1], add_to_color[2],
-                        ));
-                    }
-                    Refract {
-                        refractive_index,
-                        add_to_color,
-                    } => {
-                        material_processing.add_string(format!(
-                            "return material_refract(hit, r, vec3({:e}, {:e}, {:e}), {:e});\n",
-                            add_to_color[0], add_to_color[1], add_to_color[2], refractive_index,
-                        ));
-                    }
-                    x @ Complex { .. } => {
-                        let code = match x {
-                            Complex { code } => code,
-                            _ => unreachable!(),
-                        };
-                        material_processing.add_identifier_string(x.identifier(pos), &code.0.0);
-                        material_processing.add_string("\n");
-                    }
-                };
+                material_processing.add_generated(identifier, |material_processing| {
+                    material_processing
+                        .add_string(format!("}} else if (i.material == {}) {{\n", name_m));
+
+                    match &material.0 {
+                        Simple {
+                            color,
+                            normal_coef,
+                            grid,
+                            grid_scale,
+                            grid_coef,
+                        } => {
+                            material_processing.add_string(
+                                format!(
+                                    "return material_simple(hit, r, vec3({:e}, {:e}, {:e}), {:e}, {}, {:e}, {:e});\n",
+                                    color[0], color[1], color[2], normal_coef, grid, grid_scale, grid_coef,
+                                )
+                            );
+                        }
+                        Reflect { add_to_color } => {
+                            material_processing.add_string(format!(
+                                "return material_reflect(hit, r, vec3({:e}, {:e}, {:e}));\n",
+                                add_to_color[0], add_to_color[1], add_to_color[2],
+                            ));
+                        }
+                        Refract {
+                            refractive_index,
+                            add_to_color,
+                        } => {
+                            material_processing.add_string(format!(
+                                "return material_refract(hit, r, vec3({:e}, {:e}, {:e}), {:e});\n",
+                                add_to_color[0], add_to_color[1], add_to_color[2], refractive_index,
+                            ));
+                        }
+                        x @ Complex { .. } => {
+                            let code = match x {
+                                Complex { code } => code,
+                                _ => unreachable!(),
+                            };
+                            material_processing.add_identifier_string(identifier, &code.0.0);
+                            material_processing.add_string("\n");
+                        }
+                    };
+                });
             }
             for (pos, first, second) in
                 self.objects
@@ -557,6 +998,7 @@ impl Scene {
                     .enumerate()
                     .filter_map(|(pos, (_, x))| match &x.0 {
                         Object::DebugMatrix { .. }
+                        | Object::Primitive { .. }
                         | Object::Flat {
                             kind: ObjectType::Simple { .. },
                             ..
@@ -577,31 +1019,36 @@ impl Scene {
             {
                 let name_m_1 = format!("teleport_{}_1_M", pos);
                 let name_m_2 = format!("teleport_{}_2_M", pos);
-
-                material_defines.add_string(format!(
-                    "#define {} (USER_MATERIAL_OFFSET + {})\n",
-                    name_m_1, counter
-                ));
-                counter += 1;
-                material_defines.add_string(format!(
-                    "#define {} (USER_MATERIAL_OFFSET + {})\n",
-                    name_m_2, counter
-                ));
-                counter += 1;
-
-                material_processing
-                    .add_string(format!("}} else if (i.material == {}) {{\n", name_m_1));
-                material_processing.add_string(format!(
-                    "return material_teleport(hit, r, {});",
-                    first.teleport_to_name(second)
-                ));
-
-                material_processing
-                    .add_string(format!("}} else if (i.material == {}) {{\n", name_m_2));
-                material_processing.add_string(format!(
-                    "return material_teleport(hit, r, {});",
-                    second.teleport_to_name(first)
-                ));
+                let identifier = self.objects.storage[pos].0.identifier(self.objects.ids[pos]);
+
+                material_defines.add_generated(identifier, |material_defines| {
+                    material_defines.add_string(format!(
+                        "#define {} (USER_MATERIAL_OFFSET + {})\n",
+                        name_m_1, counter
+                    ));
+                    material_defines.add_string(format!(
+                        "#define {} (USER_MATERIAL_OFFSET + {})\n",
+                        name_m_2,
+                        counter + 1
+                    ));
+                });
+                counter += 2;
+
+                material_processing.add_generated(identifier, |material_processing| {
+                    material_processing
+                        .add_string(format!("}} else if (i.material == {}) {{\n", name_m_1));
+                    material_processing.add_string(format!(
+                        "return material_teleport(hit, r, {});",
+                        first.teleport_to_name(second)
+                    ));
+
+                    material_processing
+                        .add_string(format!("}} else if (i.material == {}) {{\n", name_m_2));
+                    material_processing.add_string(format!(
+                        "return material_teleport(hit, r, {});",
+                        second.teleport_to_name(first)
+                    ));
+                });
             }
             (material_processing, material_defines)
         };
@@ -615,8 +1062,9 @@ impl Scene {
             let mut result = StringStorage::default();
 
             for (pos, (_, i)) in self.objects.iter().enumerate() {
-                match &i.0 {
-                    DebugMatrix(_) => {}
+                let identifier = i.0.identifier(self.objects.ids[pos]);
+                result.add_generated(identifier, |result| match &i.0 {
+                    DebugMatrix(_) | Primitive { .. } => {}
                     Flat { kind, is_inside } => {
                         if matches!(kind, Portal { .. }) {
                             result.add_string(format!(
@@ -626,10 +1074,10 @@ impl Scene {
                         } else {
                             result.add_string(format!("int is_inside_{}(vec4 pos, float x, float y) {{\n", pos));
                         }
-                        result.add_identifier_string(i.0.identifier(pos), &is_inside.0.0);
+                        result.add_identifier_string(identifier, &is_inside.0.0);
                         result.add_string("\n}\n");
                     }
-                    Complex { kind, intersect } => {
+                    Complex { kind, intersect, .. } => {
                         if matches!(kind, Portal { .. }) {
                             result.add_string(format!(
                                 "SceneIntersection intersect_{}(Ray r, bool first) {{\n",
@@ -638,136 +1086,291 @@ impl Scene {
                         } else {
                             result.add_string(format!("SceneIntersection intersect_{}(Ray r) {{\n", pos));
                         }
-                        result.add_identifier_string(i.0.identifier(pos), &intersect.0.0);
+                        result.add_identifier_string(identifier, &intersect.0.0);
                         result.add_string("\n}\n");
                     }
-                }
+                });
             }
             result
         });
 
         storages.insert("intersections".to_owned(), {
-            use Object::*;
-            use ObjectType::*;
             let mut result = StringStorage::default();
 
-            for (pos, (_, i)) in self.objects.iter().enumerate() {
-                match &i.0 {
-                    DebugMatrix(matrix) => {
-                        result.add_string(format!(
-                            "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray.d = normalize(transformed_ray.d);",
-                            matrix.inverse_name()
-                        ));
-                        result.add_string("ihit = debug_intersect(transformed_ray);\nihit.hit.t /= len;\n");
-                        result.add_string(format!(
-                            "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(({} * vec4(i.hit.n, 0.)).xyz); }}\n\n",
-                            matrix.normal_name()
-                        ));
-                    }
-                    Flat { kind, is_inside: _ } => match kind {
-                        Simple(matrix) => {
-                            result.add_string(format!(
-                                "hit = plane_intersect(r, {}, get_normal({}));\n",
-                                matrix.inverse_name(),
-                                matrix.normal_name()
-                            ));
-                            result.add_string(format!(
-                                "if (nearer(i, hit)) {{ i = process_plane_intersection(i, hit, is_inside_{}(r.o + r.d * hit.t, hit.u, hit.v)); }}\n\n",
-                                pos
-                            ));
-                        }
-                        Portal(a, b) => {
-                            let mut add = |matrix: &MatrixName, first, material| {
-                                result.add_string(format!(
-                                    "normal = {}get_normal({});\n",
-                                    if first { "-" } else { "" },
-                                    matrix.normal_name()
-                                ));
-                                result.add_string(format!(
-                                    "hit = plane_intersect(r, {}, normal);\n",
-                                    matrix.inverse_name()
-                                ));
-                                result.add_string(format!(
-                                    "if (nearer(i, hit)) {{ i = process_portal_intersection(i, hit, is_inside_{}(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), {}), {}); }}\n\n",
-                                    pos, first, material
-                                ));
-                            };
-                            add(a, true, format!("teleport_{}_1_M", pos));
-                            add(b, false, format!("teleport_{}_2_M", pos));
-                        }
-                    },
-                    Complex { kind, intersect: _ } => match kind {
-                        Simple(matrix) => {
-                            result.add_string(format!(
-                                "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray.d = normalize(transformed_ray.d);",
-                                matrix.inverse_name()
-                            ));
-                            result.add_string(format!(
-                                "ihit = intersect_{}(transformed_ray);\nihit.hit.t /= len;\n",
-                                pos,
-                            ));
-                            result.add_string(format!(
-                                "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(({} * vec4(i.hit.n, 0.)).xyz); }}\n\n",
-                                matrix.normal_name()
-                            ));
-                        }
-                        Portal(a, b) => {
-                            let mut add = |matrix: &MatrixName, first, material| {
-                                result.add_string(format!(
-                                    "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray.d = normalize(transformed_ray.d);",
-                                    matrix.inverse_name()
-                                ));
-                                result.add_string(format!(
-                                    "ihit = intersect_{}(transformed_ray, {});\nihit.hit.t /= len;\n",
-                                    pos, first
-                                ));
-                                result.add_string(format!(
-                                    "if (nearer(i, ihit) && ihit.material != NOT_INSIDE) {{ if (ihit.material == TELEPORT) {{ ihit.material = {}; }} i = ihit; i.hit.n = normalize(({} * vec4(i.hit.n, 0.)).xyz); }}\n\n",
-                                    material,
-                                    matrix.normal_name()
-                                ));
-                            };
-                            add(a, true, format!("teleport_{}_1_M", pos));
-                            add(b, false, format!("teleport_{}_2_M", pos));
-                        }
-                    },
+            // Objects that are not in hierarchy are tested for every ray.
+            let items = self.bvh_items(options);
+            let tree = if items.len() >= 2 {
+                bvh::build(&items.iter().map(|(_, aabb)| *aabb).collect::<Vec<_>>())
+            } else {
+                None
+            };
+            for pos in 0..self.objects.storage.len() {
+                if tree.is_none() || !items.iter().any(|(x, _)| *x == pos) {
+                    self.add_intersection(&mut result, pos);
                 }
-                result.add_string("\n");
+            }
+            if let Some(tree) = &tree {
+                self.add_bvh_node(&mut result, tree, &items);
             }
             result
         });
 
+        // Library code is included only when something from it is used by the rest of shader.
+        let flags = options.flags();
+        let mut without_library = storages.clone();
+        without_library.insert("library".to_owned(), StringStorage::default());
+        without_library.insert("predefined_library".to_owned(), StringStorage::default());
+        let roots = identifiers(&apply_template(FRAGMENT_SHADER, without_library, &flags)?.storage);
+
+        let predefined = split_items(LIBRARY);
+        let user = self
+            .library
+            .iter()
+            .map(|(_, i)| split_items(&i.0.0))
+            .collect::<Vec<_>>();
+        let groups = predefined
+            .iter()
+            .map(std::slice::from_ref)
+            .chain(user.iter().map(|items| &items[..]))
+            .collect::<Vec<_>>();
+        let reachable = reachable(&groups, &roots);
+        let (predefined_reachable, user_reachable) = reachable.split_at(predefined.len());
+
         storages.insert("library".to_owned(), {
             let mut result = StringStorage::default();
             for (pos, (_, i)) in self.library.iter().enumerate() {
-                result.add_identifier_string(i.identifier(pos), &i.0.0);
+                if user_reachable[pos] {
+                    result.add_identifier_string(i.identifier(self.library.ids[pos]), &i.0.0);
+                }
             }
             result
         });
 
         storages.insert("predefined_library".to_owned(), {
             let mut result = StringStorage::default();
-            result.add_string(LIBRARY);
+            for (item, _) in predefined
+                .iter()
+                .zip(predefined_reachable)
+                .filter(|(_, reachable)| **reachable)
+            {
+                result.add_string(item.text);
+            }
             result
         });
 
-        apply_template(FRAGMENT_SHADER, storages)
+        let code = apply_template(FRAGMENT_SHADER, storages, &flags)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        if options.dialect == ShaderDialect::Glsl450 {
+            return Ok(to_vulkan_glsl(code));
+        }
+        Ok(code)
+    }
+
+    /// Adds code of intersection with object to `scene_intersect`.
+    fn add_intersection(&self, result: &mut StringStorage, pos: usize) {
+        use Object::*;
+        use ObjectType::*;
+
+        let i = &self.objects.storage[pos];
+        let identifier = i.0.identifier(self.objects.ids[pos]);
+        let bounds = i.0.bounds();
+        result.add_generated(identifier, |result| match &i.0 {
+            DebugMatrix(matrix) => {
+                result.add_string(format!(
+                    "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray.d = normalize(transformed_ray.d);",
+                    matrix.inverse_name()
+                ));
+                add_bounds_check(result, &bounds, |result| {
+                    result.add_string("ihit = debug_intersect(transformed_ray);\nihit.hit.t /= len;\n");
+                    result.add_string(format!(
+                        "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(({} * vec4(i.hit.n, 0.)).xyz); }}\n",
+                        matrix.normal_name()
+                    ));
+                });
+                result.add_string("\n");
+            }
+            Primitive {
+                matrix,
+                shape,
+                material,
+            } => {
+                result.add_string(format!(
+                    "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray.d = normalize(transformed_ray.d);",
+                    matrix.inverse_name()
+                ));
+                add_bounds_check(result, &bounds, |result| {
+                    result.add_string(format!(
+                        "ihit = SceneIntersection({}, {});\nihit.hit.t /= len;\n",
+                        material.define_name(),
+                        shape.intersect_code("transformed_ray")
+                    ));
+                    result.add_string(format!(
+                        "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(({} * vec4(i.hit.n, 0.)).xyz); }}\n",
+                        matrix.normal_name()
+                    ));
+                });
+                result.add_string("\n");
+            }
+            Flat { kind, is_inside: _ } => match kind {
+                Simple(matrix) => {
+                    result.add_string(format!(
+                        "hit = plane_intersect(r, {}, get_normal({}));\n",
+                        matrix.inverse_name(),
+                        matrix.normal_name()
+                    ));
+                    result.add_string(format!(
+                        "if (nearer(i, hit)) {{ i = process_plane_intersection(i, hit, is_inside_{}(r.o + r.d * hit.t, hit.u, hit.v)); }}\n\n",
+                        pos
+                    ));
+                }
+                Portal(a, b) => {
+                    let mut add = |matrix: &MatrixName, first, material| {
+                        result.add_string(format!(
+                            "normal = {}get_normal({});\n",
+                            if first { "-" } else { "" },
+                            matrix.normal_name()
+                        ));
+                        result.add_string(format!(
+                            "hit = plane_intersect(r, {}, normal);\n",
+                            matrix.inverse_name()
+                        ));
+                        result.add_string(format!(
+                            "if (nearer(i, hit)) {{ i = process_portal_intersection(i, hit, is_inside_{}(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), {}), {}); }}\n\n",
+                            pos, first, material
+                        ));
+                    };
+                    add(a, true, format!("teleport_{}_1_M", pos));
+                    add(b, false, format!("teleport_{}_2_M", pos));
+                }
+            },
+            Complex { kind, .. } => match kind {
+                Simple(matrix) => {
+                    result.add_string(format!(
+                        "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray.d = normalize(transformed_ray.d);",
+                        matrix.inverse_name()
+                    ));
+                    add_bounds_check(result, &bounds, |result| {
+                        result.add_string(format!(
+                            "ihit = intersect_{}(transformed_ray);\nihit.hit.t /= len;\n",
+                            pos,
+                        ));
+                        result.add_string(format!(
+                            "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(({} * vec4(i.hit.n, 0.)).xyz); }}\n",
+                            matrix.normal_name()
+                        ));
+                    });
+                    result.add_string("\n");
+                }
+                Portal(a, b) => {
+                    let mut add = |matrix: &MatrixName, first, material| {
+                        result.add_string(format!(
+                            "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray.d = normalize(transformed_ray.d);",
+                            matrix.inverse_name()
+                        ));
+                        add_bounds_check(result, &bounds, |result| {
+                            result.add_string(format!(
+                                "ihit = intersect_{}(transformed_ray, {});\nihit.hit.t /= len;\n",
+                                pos, first
+                            ));
+                            result.add_string(format!(
+                                "if (nearer(i, ihit) && ihit.material != NOT_INSIDE) {{ if (ihit.material == TELEPORT) {{ ihit.material = {}; }} i = ihit; i.hit.n = normalize(({} * vec4(i.hit.n, 0.)).xyz); }}\n",
+                                material,
+                                matrix.normal_name()
+                            ));
+                        });
+                        result.add_string("\n");
+                    };
+                    add(a, true, format!("teleport_{}_1_M", pos));
+                    add(b, false, format!("teleport_{}_2_M", pos));
+                }
+            },
+        });
+        result.add_string("\n");
+    }
+
+    /// World-space boxes of objects that can be put into hierarchy: objects that have bounding volume and static matrices, which are folded into constants.
+    pub(crate) fn bvh_items(&self, options: &ShaderOptions) -> Vec<(usize, Aabb)> {
+        use Object::*;
+        use ObjectType::*;
+
+        let folded = self.folded_matrices(options);
+        let mut result = Vec::new();
+        for (pos, (_, object)) in self.objects.iter().enumerate() {
+            let aabb = match object.0.bounds().aabb() {
+                Some(aabb) => aabb,
+                None => continue,
+            };
+            let matrices = match &object.0 {
+                DebugMatrix(matrix)
+                | Primitive { matrix, .. }
+                | Complex {
+                    kind: Simple(matrix),
+                    ..
+                } => vec![matrix],
+                Complex {
+                    kind: Portal(a, b), ..
+                } => vec![a, b],
+                Flat { .. } => continue,
+            };
+            let boxes = matrices
+                .into_iter()
+                .map(|matrix| Some(aabb.transformed(folded.get(&matrix.normal_name())?)))
+                .collect::<Option<Vec<_>>>();
+            if let Some(boxes) = boxes {
+                let aabb = boxes[1..].iter().fold(boxes[0], |acc, x| acc.union(x));
+                result.push((pos, aabb));
+            }
+        }
+        result
+    }
+
+    /// Adds nested checks of boxes of hierarchy, objects are tested only when ray hits all boxes that contain them.
+    fn add_bvh_node(
+        &self,
+        result: &mut StringStorage,
+        node: &bvh::Node,
+        items: &[(usize, Aabb)],
+    ) {
+        match node {
+            bvh::Node::Leaf(pos) => self.add_intersection(result, items[*pos].0),
+            bvh::Node::Branch { bounds, children } => {
+                result.add_string(format!(
+                    "if (bounds_nearer(i, box_bounds(r, vec3({:e}, {:e}, {:e}), vec3({:e}, {:e}, {:e})), 1.)) {{\n",
+                    bounds.min.x,
+                    bounds.min.y,
+                    bounds.min.z,
+                    bounds.max.x,
+                    bounds.max.y,
+                    bounds.max.z
+                ));
+                self.add_bvh_node(result, &children.0, items);
+                self.add_bvh_node(result, &children.1, items);
+                result.add_string("}\n");
+            }
+        }
     }
 
     pub fn get_new_material(
         &self,
+        options: &ShaderOptions,
     ) -> Result<macroquad::prelude::Material, (String, String, ShaderErrors)> {
-        let code = self.generate_shader_code();
+        let vertex_shader = options.dialect.vertex_shader().ok_or_else(|| {
+            let message = format!("{} can't be compiled by OpenGL", options.dialect.name());
+            line_errors(StringStorage::default(), &[(usize::MAX, message)])
+        })?;
+        let code = self
+            .generate_shader_code(options)
+            .map_err(|errors| template_errors(&errors))?;
 
         use macroquad::prelude::load_material;
         use macroquad::prelude::MaterialParams;
 
         load_material(
-            VERTEX_SHADER,
+            vertex_shader,
             &code.storage,
             MaterialParams {
-                uniforms: self.uniforms(),
-                textures: self.textures(),
+                uniforms: self.uniforms(options),
+                textures: self.textures(options),
                 ..Default::default()
             },
         )
@@ -783,70 +1386,167 @@ impl Scene {
                     Default::default()
                 }
             };
-            let mut errors: BTreeMap<ErrId, Vec<(usize, String)>> = BTreeMap::new();
-            for x in shader_error_parser(&error_message) {
-                match x {
-                    Ok((line_no, message)) => match code.line_numbers.get_identifier(line_no) {
-                        Some((identifier, local_line_no)) => {
-                            errors
-                                .entry(identifier)
-                                .or_insert_with(|| Default::default())
-                                .push((local_line_no, message.to_owned()));
-                        }
-                        None => {
-                            errors
-                                .entry(ErrId::default())
-                                .or_insert_with(|| Default::default())
-                                .push((line_no, message.to_owned()));
-                        }
-                    },
-                    Err(message) => {
-                        errors
-                            .entry(ErrId::default())
-                            .or_insert_with(|| Default::default())
-                            .push((usize::MAX, message.to_owned()));
+            let errors = attribute_errors(&code, shader_error_parser(&error_message));
+            (code.storage, error_message, errors)
+        })
+    }
+
+    /// Checks generated code without GPU by parsing it with naga, see `validate_glsl`. Code is checked as GLSL 4.50 with the same other options, so errors specific to chosen dialect are found only by `get_new_material`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn validate_shader(
+        &self,
+        options: &ShaderOptions,
+    ) -> Result<(), (String, String, ShaderErrors)> {
+        let options = ShaderOptions {
+            dialect: ShaderDialect::Glsl450,
+            ..options.clone()
+        };
+        let code = self
+            .generate_shader_code(&options)
+            .map_err(|errors| template_errors(&errors))?;
+        let errors = validate_glsl(&code.storage);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(line_errors(code, &errors))
+    }
+
+    /// Generates fragment shader in WGSL by translating GLSL 4.50 code, see `glsl_to_wgsl`. Errors of translation are attributed to entities like errors of `get_new_material`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn generate_wgsl(
+        &self,
+        options: &ShaderOptions,
+    ) -> Result<String, (String, String, ShaderErrors)> {
+        let options = ShaderOptions {
+            dialect: ShaderDialect::Glsl450,
+            ..options.clone()
+        };
+        let code = self
+            .generate_shader_code(&options)
+            .map_err(|errors| template_errors(&errors))?;
+        glsl_to_wgsl(&code.storage).map_err(|errors| line_errors(code, &errors))
+    }
+
+    /// Human readable name of entity that has errors, used in command line.
+    pub fn error_source(&self, identifier: ErrId) -> String {
+        macro_rules! find {
+            ($storage:expr, $kind:expr, $x:ident => $get:expr) => {
+                for (pos, (name, $x)) in $storage.iter().enumerate() {
+                    if $get.identifier($storage.ids[pos]) == identifier {
+                        return format!("{} `{}`", $kind, name);
                     }
                 }
-            }
-            (code.storage, error_message, ShaderErrors(errors))
+            };
+        }
+        find!(self.matrices, "matrix", x => x.0);
+        find!(self.objects, "object", x => x.0);
+        find!(self.materials, "material", x => x.0);
+        find!(self.library, "library", x => x);
+        find!(self.uniforms, "uniform", x => x.0);
+        find!(self.textures, "texture", x => x);
+        "generated code".to_owned()
+    }
+}
+
+/// Template errors are not attributed to any entity, because they are errors in `frag.glsl`.
+fn template_errors(errors: &[TemplateError]) -> (String, String, ShaderErrors) {
+    let messages = errors
+        .iter()
+        .map(|err| (usize::MAX, format!("frag.glsl: {}", err)))
+        .collect::<Vec<_>>();
+    let message = messages
+        .iter()
+        .map(|(_, message)| &message[..])
+        .collect::<Vec<_>>()
+        .join("\n");
+    let errors = vec![(ErrId::default(), messages)].into_iter().collect();
+    (String::new(), message, ShaderErrors(errors))
+}
+
+/// Errors with line numbers of generated code, as they are returned by `get_new_material`.
+fn line_errors(code: StringStorage, errors: &[(usize, String)]) -> (String, String, ShaderErrors) {
+    let message = errors
+        .iter()
+        .map(|(line_no, message)| match *line_no {
+            usize::MAX => format!("{}\n", message),
+            line_no => format!("{}: {}\n", line_no, message),
         })
+        .collect::<String>();
+    let attributed = attribute_errors(
+        &code,
+        errors
+            .iter()
+            .map(|(line_no, message)| Ok((*line_no, &message[..]))),
+    );
+    (code.storage, message, attributed)
+}
+
+/// Finds entities that are responsible for errors in lines of generated code.
+fn attribute_errors<'a>(
+    code: &StringStorage,
+    parsed: impl IntoIterator<Item = Result<(usize, &'a str), &'a str>>,
+) -> ShaderErrors {
+    let mut errors: BTreeMap<ErrId, Vec<(usize, String)>> = BTreeMap::new();
+    for x in parsed {
+        match x {
+            Ok((line_no, message)) => match code.get_identifier(line_no) {
+                Some((identifier, Some(local_line_no))) => {
+                    errors
+                        .entry(identifier)
+                        .or_insert_with(|| Default::default())
+                        .push((local_line_no, message.to_owned()));
+                }
+                Some((identifier, None)) => {
+                    let line = code.storage.lines().nth(line_no - 1).unwrap_or_default();
+                    let message = format!("{} (in `{}`)", message, line.trim());
+                    errors
+                        .entry(identifier)
+                        .or_insert_with(|| Default::default())
+                        .push((GENERATED_LINE, message));
+                }
+                None => {
+                    errors
+                        .entry(ErrId::default())
+                        .or_insert_with(|| Default::default())
+                        .push((line_no, message.to_owned()));
+                }
+            },
+            Err(message) => {
+                errors
+                    .entry(ErrId::default())
+                    .or_insert_with(|| Default::default())
+                    .push((usize::MAX, message.to_owned()));
+            }
+        }
     }
+    ShaderErrors(errors)
 }
 
 impl Scene {
     fn init_stage(&mut self, stage: usize) -> WhatChanged {
         let mut result = WhatChanged::default();
         if self.animation_stages.storage.len() > 0 {
-            for (pos, uniform) in self.animation_stages.storage[stage]
-                .uniforms
-                .iter()
-                .enumerate()
-            {
+            let stage = &self.animation_stages.storage[stage];
+            for (id, uniform) in self.uniforms.ids.iter().zip(self.uniforms.storage.iter_mut()) {
                 use Animation::*;
-                match uniform {
-                    Changed(x) | ChangedAndToUser(x) => {
-                        result.uniform |= check_changed(&mut self.uniforms.storage[pos].0, |u| {
+                match stage.uniforms.get(id) {
+                    Some(Changed(x)) | Some(ChangedAndToUser(x)) => {
+                        result.uniform |= check_changed(&mut uniform.0, |u| {
                             *u = x.clone();
                         });
                     }
-                    ProvidedToUser | Remains => {}
+                    Some(ProvidedToUser) | Some(Remains) | None => {}
                 }
             }
-        }
-        if self.animation_stages.storage.len() > 0 {
-            for (pos, matrix) in self.animation_stages.storage[stage]
-                .matrices
-                .iter()
-                .enumerate()
-            {
+            for (id, matrix) in self.matrices.ids.iter().zip(self.matrices.storage.iter_mut()) {
                 use Animation::*;
-                match matrix {
-                    Changed(x) | ChangedAndToUser(x) => {
-                        result.uniform |= check_changed(&mut self.matrices.storage[pos].0, |u| {
+                match stage.matrices.get(id) {
+                    Some(Changed(x)) | Some(ChangedAndToUser(x)) => {
+                        result.uniform |= check_changed(&mut matrix.0, |u| {
                             *u = x.clone();
                         });
                     }
-                    ProvidedToUser | Remains => {}
+                    Some(ProvidedToUser) | Some(Remains) | None => {}
                 }
             }
         }
@@ -855,14 +1555,15 @@ impl Scene {
 
     pub fn control_egui(&mut self, ui: &mut Ui, _: &mut Data) -> WhatChanged {
         let mut result = WhatChanged::default();
-        if self.user_uniforms.uniforms.iter().any(|x| *x) {
+        if !self.user_uniforms.uniforms.is_empty() {
+            let user_uniforms = &self.user_uniforms.uniforms;
             for ((uniform, name), _) in self
                 .uniforms
                 .storage
                 .iter_mut()
                 .zip(self.uniforms.names.iter())
-                .zip(self.user_uniforms.uniforms.iter())
-                .filter(|(_, x)| **x)
+                .zip(self.uniforms.ids.iter())
+                .filter(|(_, id)| user_uniforms.contains(id))
             {
                 ui.horizontal(|ui| {
                     ui.label(name);
@@ -872,14 +1573,15 @@ impl Scene {
             ui.separator();
         }
 
-        if self.user_uniforms.matrices.iter().any(|x| *x) {
+        if !self.user_uniforms.matrices.is_empty() {
+            let user_matrices = &self.user_uniforms.matrices;
             for ((matrix, name), _) in self
                 .matrices
                 .storage
                 .iter_mut()
                 .zip(self.matrices.names.iter())
-                .zip(self.user_uniforms.matrices.iter())
-                .filter(|(_, x)| **x)
+                .zip(self.matrices.ids.iter())
+                .filter(|(_, id)| user_matrices.contains(id))
             {
                 ui.separator();
                 ui.label(name);
@@ -904,38 +1606,29 @@ impl Scene {
                 self.current_stage = self.animation_stages.storage.len() - 1;
             }
             ui.separator();
+            let stage = &self.animation_stages.storage[self.current_stage];
             let uniforms = &mut self.uniforms;
-            for (pos, uniform) in self.animation_stages.storage[self.current_stage]
-                .uniforms
-                .iter()
-                .enumerate()
-            {
+            for (pos, id) in uniforms.ids.clone().iter().enumerate() {
                 use Animation::*;
-                match uniform {
-                    ProvidedToUser | ChangedAndToUser(_) => drop(ui.horizontal(|ui| {
+                match stage.uniforms.get(id) {
+                    Some(ProvidedToUser) | Some(ChangedAndToUser(_)) => drop(ui.horizontal(|ui| {
                         ui.label(&uniforms.names[pos]);
                         result |= uniforms.storage[pos].0.simple_egui(ui)
                     })),
-                    Remains => {}
-                    Changed(_) => {}
+                    Some(Remains) | Some(Changed(_)) | None => {}
                 }
             }
             ui.separator();
             let matrices = &mut self.matrices;
-            for (pos, matrix) in self.animation_stages.storage[self.current_stage]
-                .matrices
-                .iter()
-                .enumerate()
-            {
+            for (pos, id) in matrices.ids.clone().iter().enumerate() {
                 use Animation::*;
-                match matrix {
-                    ProvidedToUser | ChangedAndToUser(_) => {
+                match stage.matrices.get(id) {
+                    Some(ProvidedToUser) | Some(ChangedAndToUser(_)) => {
                         ui.separator();
                         ui.label(&matrices.names[pos]);
                         result |= matrices.storage[pos].0.simple_egui(ui)
                     },
-                    Remains => {}
-                    Changed(_) => {}
+                    Some(Remains) | Some(Changed(_)) | None => {}
                 }
             }
         }
@@ -947,6 +1640,57 @@ const FRAGMENT_SHADER: &'static str = include_str!("../frag.glsl");
 
 pub const LIBRARY: &'static str = include_str!("../library.glsl");
 
+const PRELUDE_ES_100: &str = "#version 100
+// Version can't be changed to upper versions because of WebGL.
+
+precision highp float;
+";
+
+const PRELUDE_330: &str = "#version 330 core
+
+// Library and user code is written for GLSL ES 1.00.
+#define texture2D texture
+";
+
+/// `#version` and declarations of uniforms are added before prelude by `to_vulkan_glsl`.
+const PRELUDE_450: &str = "
+// Library and user code is written for GLSL ES 1.00.
+#define texture2D texture
+";
+
+/// Name of texture with packed uniforms.
+const PACKED_TEXTURE: &str = "_packed_texture";
+
+/// Width of texture with packed uniforms, every texel holds one float.
+const PACKED_TEXTURE_WIDTH: usize = 256;
+
+/// Reads floats from `_packed_texture`, every texel contains bytes of IEEE 754 float, so uniforms can be read without float textures.
+const PACKED_UNIFORMS_CODE: &str = "
+float _packed_float(float pos) {
+    vec2 texel = vec2(mod(pos, _packed_size.x), floor(pos / _packed_size.x));
+    vec4 bytes = floor(texture2D(_packed_texture, (texel + 0.5) / _packed_size) * 255. + 0.5);
+    float exponent = mod(bytes.a, 128.) * 2. + floor(bytes.b / 128.);
+    if (exponent == 0.) {
+        return 0.;
+    }
+    float mantissa = 1. + (mod(bytes.b, 128.) * 65536. + bytes.g * 256. + bytes.r) / 8388608.;
+    return (bytes.a >= 128. ? -1. : 1.) * mantissa * exp2(exponent - 127.);
+}
+
+vec4 _packed_vec4(float pos) {
+    return vec4(
+        _packed_float(pos), _packed_float(pos + 1.),
+        _packed_float(pos + 2.), _packed_float(pos + 3.)
+    );
+}
+
+mat4 _packed_mat4(float pos) {
+    return mat4(
+        _packed_vec4(pos), _packed_vec4(pos + 4.), _packed_vec4(pos + 8.), _packed_vec4(pos + 12.)
+    );
+}
+";
+
 const VERTEX_SHADER: &'static str = "#version 100
 attribute vec3 position;
 attribute vec2 texcoord;
@@ -969,3 +1713,161 @@ void main() {
     gl_Position = res;
 }
 ";
+
+const VERTEX_SHADER_330: &str = "#version 330 core
+in vec3 position;
+in vec2 texcoord;
+
+out vec2 uv;
+out vec2 uv_screen;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+uniform vec2 Center;
+uniform vec2 _resolution;
+
+void main() {
+    vec4 res = Projection * Model * vec4(position, 1);
+
+    uv_screen = (position.xy - _resolution/2.) / min(_resolution.x, _resolution.y) * 2.;
+    uv = texcoord;
+
+    gl_Position = res;
+}
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_files::*;
+
+    #[test]
+    fn shader_dialects() {
+        for file in embedded_scenes() {
+            let scene = parse_scene(&file).unwrap();
+            for panini in &[false, true] {
+                for dialect in ShaderDialect::ALL.iter() {
+                    let options = ShaderOptions {
+                        panini: *panini,
+                        dialect: *dialect,
+                        ..Default::default()
+                    };
+                    let code = scene
+                        .generate_shader_code(&options)
+                        .unwrap_or_else(|errors| panic!("{}: {:?}", file.id, errors));
+                    let lines = code.storage.lines().collect::<Vec<_>>();
+
+                    // Shaders are linked only when they are written in the same version.
+                    let version = dialect.vertex_shader().and_then(|x| x.lines().next());
+                    if let Some(version) = version {
+                        assert_eq!(lines[0], version, "{}", file.id);
+                    }
+
+                    // Line numbers of user code point to the same text in every dialect.
+                    for (pos, (_, i)) in scene.library.iter().enumerate() {
+                        let id = i.identifier(scene.library.ids[pos]);
+                        let range = code.line_numbers.0[&id].clone();
+                        let first_line = i.0.0.lines().next().unwrap_or_default();
+                        assert_eq!(lines[range.start - 1], first_line, "{}", file.id);
+                    }
+
+                    // Generated code that calls object's functions is attributed to the object.
+                    for (pos, (_, object)) in scene.objects.iter().enumerate() {
+                        let id = object.0.identifier(scene.objects.ids[pos]);
+                        let calls = [format!("is_inside_{}(", pos), format!("intersect_{}(", pos)];
+                        for (line_no, line) in lines.iter().enumerate() {
+                            if calls.iter().any(|call| line.contains(&call[..])) {
+                                let source = code.get_identifier(line_no + 1).map(|(id, _)| id);
+                                assert_eq!(source, Some(id), "{}: {}", file.id, line);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn static_matrices_are_folded() {
+        let options = |fold_static_matrices| ShaderOptions {
+            fold_static_matrices,
+            ..Default::default()
+        };
+        let names = |scene: &Scene, options| {
+            scene
+                .uniforms(&options)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<BTreeSet<_>>()
+        };
+
+        // Folded matrices are not uniforms anymore, and shader is valid without them.
+        for file in embedded_scenes() {
+            let scene = parse_scene(&file).unwrap();
+            let mut folded = names(&scene, options(true));
+            folded.extend(scene.folded_matrices(&options(true)).keys().cloned());
+            assert_eq!(folded, names(&scene, options(false)), "{}", file.id);
+            assert!(scene.validate_shader(&options(true)).is_ok(), "{}", file.id);
+        }
+
+        // `mobius_a` and `mobius_b` are simple, but they are controlled by user.
+        let mut scene = embedded_scene("mobius").unwrap();
+        assert!(!scene.static_matrices().contains("mobius_a"));
+        assert!(!scene.static_matrices().contains("rz1"));
+        assert!(names(&scene, options(true)).contains("mobius_a_mat"));
+
+        scene.user_uniforms.matrices.clear();
+        assert!(scene.static_matrices().contains("mobius_a"));
+        let folded = names(&scene, options(true));
+        assert!(!folded.contains("mobius_a_mat"));
+        assert!(!folded.contains("mobius_a_to_mobius_b_mat_teleport"));
+        assert!(folded.contains("rz1_mat"));
+        assert!(names(&scene, options(false)).contains("mobius_a_mat"));
+        assert!(scene.validate_shader(&options(true)).is_ok());
+    }
+
+    #[test]
+    fn uniforms_are_packed() {
+        let packed = ShaderOptions {
+            pack_uniforms: true,
+            ..Default::default()
+        };
+        for file in embedded_scenes() {
+            let scene = parse_scene(&file).unwrap();
+            assert!(scene.textures(&packed).contains(&PACKED_TEXTURE.to_owned()));
+            let uniforms = scene.uniforms(&packed);
+            assert!(uniforms.iter().all(|(name, _)| name.starts_with('_')));
+            assert!(scene.validate_shader(&packed).is_ok(), "{}", file.id);
+
+            // Every uniform of scene is packed, and uniforms don't overlap in texture.
+            let (uniforms, size) = scene.packed_uniforms(&packed);
+            let names = uniforms.iter().map(|(name, _, _)| name);
+            assert!(names.eq(scene.scene_uniforms(&packed).iter().map(|(name, _)| name)));
+            let mut used = vec![false; size];
+            for (name, kind, offset) in &uniforms {
+                let len = match kind {
+                    UniformType::Mat4 => 16,
+                    _ => 1,
+                };
+                for used in &mut used[*offset..*offset + len] {
+                    assert!(!*used, "{}: {}", file.id, name);
+                    *used = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packed_texture() {
+        let values = (0..300).map(|x| x as f32 * 0.37 - 5.).collect::<Vec<_>>();
+        let (width, height) = packed_texture_size(values.len());
+        assert_eq!((width, height), (PACKED_TEXTURE_WIDTH, 2));
+        let bytes = packed_texture_bytes(&values);
+        assert_eq!(bytes.len(), width * height * 4);
+        for (value, texel) in values.iter().zip(bytes.chunks(4)) {
+            let texel = [texel[0], texel[1], texel[2], texel[3]];
+            assert_eq!(f32::from_le_bytes(texel), *value);
+        }
+    }
+}
@@ -4,6 +4,8 @@ use crate::gui::uniform::*;
 use egui::*;
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
 
 #[derive(Debug, Clone)]
 pub enum GetEnum<T> {
@@ -36,19 +38,83 @@ pub trait StorageElem: Sized + Default {
     fn egui(
         &mut self,
         ui: &mut Ui,
-        pos: usize,
+        id: EntityId,
         input: &mut Self::Input,
         names: &[String],
     ) -> WhatChanged;
 
-    fn errors_count(&self, pos: usize, input: &Self::Input, names: &[String]) -> usize;
+    fn errors_count(&self, id: EntityId, input: &Self::Input, names: &[String]) -> usize;
 }
 
+/// Persistent identifier of element in `StorageWithNames`, it doesn't change when element is renamed or moved. Identifiers are unique only inside one storage.
+#[derive(
+    Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Default, Serialize, Deserialize,
+)]
+pub struct EntityId(pub u64);
+
 // Checks if this name is used, sends name to
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(try_from = "UncheckedStorage<T>")]
 pub struct StorageWithNames<T> {
+    pub ids: Vec<EntityId>,
     pub names: Vec<String>,
     pub storage: Vec<T>,
+
+    /// Identifiers are never reused, so references to deleted elements can't point to new ones.
+    pub next_id: u64,
+}
+
+/// `StorageWithNames` as it is written in file, it can be edited by hand, so it is checked on load.
+#[derive(Deserialize)]
+struct UncheckedStorage<T> {
+    ids: Vec<EntityId>,
+    names: Vec<String>,
+    storage: Vec<T>,
+    next_id: u64,
+}
+
+impl<T> TryFrom<UncheckedStorage<T>> for StorageWithNames<T> {
+    type Error = String;
+
+    fn try_from(unchecked: UncheckedStorage<T>) -> Result<Self, String> {
+        let UncheckedStorage {
+            mut ids,
+            names,
+            storage,
+            mut next_id,
+        } = unchecked;
+        if names.len() != storage.len() {
+            return Err(format!(
+                "{} names are given for {} elements",
+                names.len(),
+                storage.len()
+            ));
+        }
+        if ids.len() > names.len() {
+            return Err(format!(
+                "{} ids are given for {} elements",
+                ids.len(),
+                names.len()
+            ));
+        }
+        if ids.iter().collect::<BTreeSet<_>>().len() != ids.len() {
+            return Err("ids of elements are not unique".to_owned());
+        }
+
+        next_id = ids.iter().map(|id| id.0 + 1).fold(next_id, u64::max);
+        // Elements that are added to the end of file by hand get new ids.
+        while ids.len() < names.len() {
+            ids.push(EntityId(next_id));
+            next_id += 1;
+        }
+
+        Ok(StorageWithNames {
+            ids,
+            names,
+            storage,
+            next_id,
+        })
+    }
 }
 
 impl<T: StorageElem> StorageWithNames<T> {
@@ -62,16 +128,38 @@ impl<T: StorageElem> StorageWithNames<T> {
         self.get_inner(name, &mut visited, uniforms, formulas_cache)
     }
 
-    pub fn add(&mut self, name: String, t: T) {
+    pub fn add(&mut self, name: String, t: T) -> EntityId {
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+        self.ids.push(id);
         self.names.push(name);
         self.storage.push(t);
+        id
     }
 
     pub fn remove(&mut self, pos: usize) {
+        self.ids.remove(pos);
         self.names.remove(pos);
         self.storage.remove(pos);
     }
 
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.ids.swap(a, b);
+        self.names.swap(a, b);
+        self.storage.swap(a, b);
+    }
+
+    pub fn position(&self, id: EntityId) -> Option<usize> {
+        self.ids.iter().position(|x| *x == id)
+    }
+
+    pub fn id_by_name(&self, name: &str) -> Option<EntityId> {
+        self.names
+            .iter()
+            .position(|x| x == name)
+            .map(|pos| self.ids[pos])
+    }
+
     pub fn names_iter(&self) -> std::slice::Iter<String> {
         self.names.iter()
     }
@@ -134,16 +222,18 @@ impl<T: StorageElem> StorageWithNames<T> {
         let mut to_move_down = None;
         let storage = &mut self.storage;
         let names = &mut self.names;
+        let ids = &self.ids;
         let len = storage.len();
         for (pos, elem) in storage.iter_mut().enumerate() {
+            let id = ids[pos];
             let errors_count =
-                elem.errors_count(pos, input, names) + names[..pos].contains(&names[pos]) as usize;
+                elem.errors_count(id, input, names) + names[..pos].contains(&names[pos]) as usize;
             CollapsingHeader::new(if errors_count > 0 {
                 format!("{} ({} err)", names[pos], errors_count)
             } else {
                 names[pos].to_owned()
             })
-            .id_source(pos)
+            .id_source(id)
             .show(ui, |ui| {
                 let previous = names[pos].clone();
                 ui.horizontal(|ui| {
@@ -190,18 +280,16 @@ impl<T: StorageElem> StorageWithNames<T> {
                 }
                 changed.shader |= previous != names[pos];
 
-                changed |= elem.egui(ui, pos, input, names);
+                changed |= elem.egui(ui, id, input, names);
             });
         }
         if let Some(pos) = to_delete {
             changed.shader = true;
             self.remove(pos);
         } else if let Some(pos) = to_move_up {
-            self.storage.swap(pos, pos - 1);
-            self.names.swap(pos, pos - 1);
+            self.swap(pos, pos - 1);
         } else if let Some(pos) = to_move_down {
-            self.storage.swap(pos, pos + 1);
-            self.names.swap(pos, pos + 1);
+            self.swap(pos, pos + 1);
         }
         if ui
             .add(Button::new("Add").text_color(Color32::GREEN))
@@ -220,9 +308,37 @@ impl<T: StorageElem> StorageWithNames<T> {
             .iter()
             .enumerate()
             .map(|(pos, x)| {
-                x.errors_count(pos, data, &self.names)
+                x.errors_count(self.ids[pos], data, &self.names)
                     + self.names[..pos].contains(&self.names[pos]) as usize
             })
             .sum()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(json: &str) -> Result<StorageWithNames<String>, String> {
+        serde_json::from_str(json).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn ids_are_checked() {
+        let storage = load(
+            r#"{"ids": [3, 0], "names": ["a", "b", "c"], "storage": ["", "", ""], "next_id": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(storage.ids, vec![EntityId(3), EntityId(0), EntityId(4)]);
+        assert_eq!(storage.next_id, 5);
+
+        let errors = [
+            r#"{"ids": [0, 1], "names": ["a", "b"], "storage": [""], "next_id": 2}"#,
+            r#"{"ids": [0, 1], "names": ["a"], "storage": [""], "next_id": 2}"#,
+            r#"{"ids": [1, 1], "names": ["a", "b"], "storage": ["", ""], "next_id": 2}"#,
+        ];
+        for json in errors.iter() {
+            assert!(load(json).is_err(), "{}", json);
+        }
+    }
+}
@@ -5,6 +5,7 @@ use crate::gui::uniform::*;
 use egui::*;
 
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextureName(pub String);
@@ -20,7 +21,20 @@ impl Default for TextureName {
     }
 }
 
-impl TextureName {}
+impl TextureName {
+    /// Paths where texture file is searched: relative to current directory, relative to directory of scene file, and by file name near scene file, because scene can be loaded from another directory that has its textures near it.
+    pub fn candidates(&self, scene_dir: Option<&Path>) -> Vec<PathBuf> {
+        let path = Path::new(&self.0);
+        let mut result = vec![path.to_owned()];
+        if let Some(dir) = scene_dir {
+            result.push(dir.join(path));
+            if let Some(file_name) = path.file_name() {
+                result.push(dir.join(file_name));
+            }
+        }
+        result
+    }
+}
 
 impl StorageElem for TextureName {
     type GetType = TextureName;
@@ -38,7 +52,7 @@ impl StorageElem for TextureName {
     fn egui(
         &mut self,
         ui: &mut Ui,
-        _: usize,
+        _: EntityId,
         texture_errors: &mut Self::Input,
         _: &[String],
     ) -> WhatChanged {
@@ -56,7 +70,7 @@ impl StorageElem for TextureName {
         result
     }
 
-    fn errors_count(&self, _: usize, texture_errors: &Self::Input, _: &[String]) -> usize {
+    fn errors_count(&self, _: EntityId, texture_errors: &Self::Input, _: &[String]) -> usize {
         texture_errors.0.get(&self.0).is_some() as usize
     }
 }
@@ -158,6 +158,15 @@ impl ParametrizeOrNot {
             No(f) => Some(*f),
         }
     }
+
+    /// GLSL expression with value of parameter: constant, or uniform converted to float.
+    pub fn code(&self) -> String {
+        use ParametrizeOrNot::*;
+        match self {
+            Yes(f) => format!("float({}_u)", f.0),
+            No(f) => format!("{:e}", f),
+        }
+    }
 }
 
 impl Default for Formula {
@@ -615,7 +624,13 @@ impl StorageElem for AnyUniformComboBox {
         }
     }
 
-    fn egui(&mut self, ui: &mut Ui, _: usize, data: &mut Self::Input, _: &[String]) -> WhatChanged {
+    fn egui(
+        &mut self,
+        ui: &mut Ui,
+        _: EntityId,
+        data: &mut Self::Input,
+        _: &[String],
+    ) -> WhatChanged {
         let mut changed =
             WhatChanged::from_uniform(egui_combo_label(ui, "Type:", 45., &mut self.0));
         ui.separator();
@@ -623,7 +638,7 @@ impl StorageElem for AnyUniformComboBox {
         changed
     }
 
-    fn errors_count(&self, _: usize, formulas_cache: &Self::Input, _: &[String]) -> usize {
+    fn errors_count(&self, _: EntityId, formulas_cache: &Self::Input, _: &[String]) -> usize {
         match &self.0 {
             AnyUniform::Formula(text) => formulas_cache.has_errors(&text.0) as usize,
             _ => 0,
@@ -0,0 +1,525 @@
+use crate::gui::animation::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gui::common::GENERATED_LINE;
+use crate::gui::matrix::*;
+use crate::gui::object::*;
+use crate::gui::scene::Scene;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gui::scene::ShaderOptions;
+use crate::gui::storage::*;
+use crate::gui::texture::TextureName;
+use crate::gui::uniform::*;
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StorageKind {
+    Uniforms,
+    Matrices,
+    Objects,
+    Textures,
+    Materials,
+    Library,
+    AnimationStages,
+}
+
+/// Problem in scene that can be found without compiling shader.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    DuplicateName {
+        storage: StorageKind,
+        name: String,
+    },
+    /// `entity` is name of element in `storage` that refers to matrix `name`.
+    UnknownMatrix {
+        storage: StorageKind,
+        entity: String,
+        name: String,
+    },
+    /// Object refers to material `name`.
+    UnknownMaterial {
+        entity: String,
+        name: String,
+    },
+    /// Parametrized matrix or primitive refers to uniform `name`.
+    UnknownUniform {
+        storage: StorageKind,
+        entity: String,
+        name: String,
+    },
+    MatrixRecursion {
+        matrix: String,
+    },
+    FormulaParse {
+        storage: StorageKind,
+        entity: String,
+        formula: String,
+    },
+    MissingTexture {
+        texture: String,
+        path: String,
+    },
+    /// Animation stage refers to element that doesn't exist in `storage`.
+    AnimationStageUnknownId {
+        stage: String,
+        storage: StorageKind,
+        id: EntityId,
+    },
+    /// Identifiers are repeated, or their count differs from count of elements.
+    BrokenIds {
+        storage: StorageKind,
+    },
+    /// Error in generated GLSL code, `source` is entity that produced it, `line` is local line of user code.
+    Glsl {
+        source: String,
+        line: Option<usize>,
+        message: String,
+    },
+}
+
+impl Display for StorageKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use StorageKind::*;
+        let name = match self {
+            Uniforms => "uniform",
+            Matrices => "matrix",
+            Objects => "object",
+            Textures => "texture",
+            Materials => "material",
+            Library => "library",
+            AnimationStages => "animation stage",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use Diagnostic::*;
+        match self {
+            DuplicateName { storage, name } => {
+                write!(f, "{} name '{}' already used", storage, name)
+            }
+            UnknownMatrix {
+                storage,
+                entity,
+                name,
+            } => write!(f, "{} '{}': matrix '{}' not found", storage, entity, name),
+            UnknownMaterial { entity, name } => {
+                write!(f, "object '{}': material '{}' not found", entity, name)
+            }
+            UnknownUniform {
+                storage,
+                entity,
+                name,
+            } => write!(f, "{} '{}': uniform '{}' not found", storage, entity, name),
+            MatrixRecursion { matrix } => write!(f, "matrix '{}' has recursion", matrix),
+            FormulaParse {
+                storage,
+                entity,
+                formula,
+            } => write!(
+                f,
+                "{} '{}': can't parse formula '{}'",
+                storage, entity, formula
+            ),
+            MissingTexture { texture, path } => {
+                write!(f, "texture '{}': file '{}' not found", texture, path)
+            }
+            AnimationStageUnknownId { stage, storage, id } => write!(
+                f,
+                "animation stage '{}': {} with id {} not found",
+                stage, storage, id.0
+            ),
+            BrokenIds { storage } => write!(f, "{} identifiers are broken", storage),
+            Glsl {
+                source,
+                line: Some(line),
+                message,
+            } => write!(f, "{}: line {}: {}", source, line, message),
+            Glsl {
+                source,
+                line: None,
+                message,
+            } => write!(f, "{}: {}", source, message),
+        }
+    }
+}
+
+struct Validator<'a> {
+    scene: &'a Scene,
+    scene_dir: Option<&'a Path>,
+    formulas_cache: FormulasCache,
+    result: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    fn duplicates<T>(&mut self, storage: StorageKind, elems: &StorageWithNames<T>) {
+        let unique_ids = elems.ids.iter().collect::<BTreeSet<_>>();
+        if elems.ids.len() != elems.names.len()
+            || elems.storage.len() != elems.names.len()
+            || unique_ids.len() != elems.ids.len()
+            || elems.ids.iter().any(|id| id.0 >= elems.next_id)
+        {
+            self.result.push(Diagnostic::BrokenIds { storage });
+        }
+        for (pos, name) in elems.names.iter().enumerate() {
+            if elems.names[..pos].contains(name) {
+                self.result.push(Diagnostic::DuplicateName {
+                    storage,
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    fn matrix_name(&mut self, storage: StorageKind, entity: &str, name: &str) {
+        if !self.scene.matrices.names.iter().any(|x| x == name) {
+            self.result.push(Diagnostic::UnknownMatrix {
+                storage,
+                entity: entity.to_owned(),
+                name: name.to_owned(),
+            });
+        }
+    }
+
+    fn parameter(&mut self, storage: StorageKind, entity: &str, param: &ParametrizeOrNot) {
+        if let ParametrizeOrNot::Yes(name) = param {
+            if !self.scene.uniforms.names.contains(&name.0) {
+                self.result.push(Diagnostic::UnknownUniform {
+                    storage,
+                    entity: entity.to_owned(),
+                    name: name.0.clone(),
+                });
+            }
+        }
+    }
+
+    fn uniform(&mut self, storage: StorageKind, entity: &str, uniform: &AnyUniform) {
+        if let AnyUniform::Formula(formula) = uniform {
+            if !self.formulas_cache.compile(&formula.0) {
+                self.result.push(Diagnostic::FormulaParse {
+                    storage,
+                    entity: entity.to_owned(),
+                    formula: formula.0.clone(),
+                });
+            }
+        }
+    }
+
+    fn matrix(&mut self, storage: StorageKind, entity: &str, matrix: &Matrix) {
+        use Matrix::*;
+        match matrix {
+            Mul { to, what } => {
+                self.matrix_name(storage, entity, to);
+                self.matrix_name(storage, entity, what);
+            }
+            Teleport {
+                first_portal,
+                second_portal,
+                what,
+            } => {
+                self.matrix_name(storage, entity, first_portal);
+                self.matrix_name(storage, entity, second_portal);
+                self.matrix_name(storage, entity, what);
+            }
+            Simple { .. } => {}
+            Parametrized {
+                offset,
+                rotate,
+                mirror,
+                scale,
+            } => {
+                for param in [offset, rotate, mirror]
+                    .iter()
+                    .flat_map(|v| vec![&v.x, &v.y, &v.z])
+                    .chain(std::iter::once(scale))
+                {
+                    self.parameter(storage, entity, param);
+                }
+            }
+        }
+    }
+
+    fn object(&mut self, entity: &str, object: &Object) {
+        use Object::*;
+        let kind = match object {
+            DebugMatrix(name) => {
+                self.matrix_name(StorageKind::Objects, entity, &name.0);
+                return;
+            }
+            Flat { kind, .. } | Complex { kind, .. } => kind,
+            Primitive {
+                matrix,
+                shape,
+                material,
+            } => {
+                self.matrix_name(StorageKind::Objects, entity, &matrix.0);
+                if !self.scene.materials.names.contains(&material.0) {
+                    self.result.push(Diagnostic::UnknownMaterial {
+                        entity: entity.to_owned(),
+                        name: material.0.clone(),
+                    });
+                }
+                for (_, _, param) in shape.parameters() {
+                    self.parameter(StorageKind::Objects, entity, param);
+                }
+                return;
+            }
+        };
+        match kind {
+            ObjectType::Simple(name) => self.matrix_name(StorageKind::Objects, entity, &name.0),
+            ObjectType::Portal(first, second) => {
+                self.matrix_name(StorageKind::Objects, entity, &first.0);
+                self.matrix_name(StorageKind::Objects, entity, &second.0);
+            }
+        }
+    }
+
+    fn animation_stage(&mut self, name: &str, stage: &AnimationStage) {
+        use Animation::*;
+
+        let unknown_uniforms = stage
+            .uniforms
+            .keys()
+            .filter(|id| !self.scene.uniforms.ids.contains(id))
+            .map(|id| (StorageKind::Uniforms, *id));
+        let unknown_matrices = stage
+            .matrices
+            .keys()
+            .filter(|id| !self.scene.matrices.ids.contains(id))
+            .map(|id| (StorageKind::Matrices, *id));
+        for (storage, id) in unknown_uniforms.chain(unknown_matrices).collect::<Vec<_>>() {
+            self.result.push(Diagnostic::AnimationStageUnknownId {
+                stage: name.to_owned(),
+                storage,
+                id,
+            });
+        }
+
+        for uniform in stage.uniforms.values() {
+            if let Changed(x) | ChangedAndToUser(x) = uniform {
+                self.uniform(StorageKind::AnimationStages, name, x);
+            }
+        }
+        for matrix in stage.matrices.values() {
+            if let Changed(x) | ChangedAndToUser(x) = matrix {
+                self.matrix(StorageKind::AnimationStages, name, x);
+            }
+        }
+    }
+
+    fn texture(&mut self, name: &str, texture: &TextureName) {
+        // On the web there is no way to check textures without loading them.
+        if cfg!(not(target_arch = "wasm32"))
+            && !texture
+                .candidates(self.scene_dir)
+                .iter()
+                .any(|path| path.exists())
+        {
+            self.result.push(Diagnostic::MissingTexture {
+                texture: name.to_owned(),
+                path: texture.0.clone(),
+            });
+        }
+    }
+
+    fn validate(mut self) -> Vec<Diagnostic> {
+        let scene = self.scene;
+
+        self.duplicates(StorageKind::Uniforms, &scene.uniforms);
+        self.duplicates(StorageKind::Matrices, &scene.matrices);
+        self.duplicates(StorageKind::Objects, &scene.objects);
+        self.duplicates(StorageKind::Textures, &scene.textures);
+        self.duplicates(StorageKind::Materials, &scene.materials);
+        self.duplicates(StorageKind::Library, &scene.library);
+        self.duplicates(StorageKind::AnimationStages, &scene.animation_stages);
+
+        for (name, uniform) in scene.uniforms.iter() {
+            self.uniform(StorageKind::Uniforms, name, &uniform.0);
+        }
+
+        for (name, matrix) in scene.matrices.iter() {
+            self.matrix(StorageKind::Matrices, name, &matrix.0);
+        }
+        for name in scene.matrices.names_iter() {
+            if let GetEnum::Recursion =
+                scene
+                    .matrices
+                    .get(name, &scene.uniforms, &self.formulas_cache)
+            {
+                self.result.push(Diagnostic::MatrixRecursion {
+                    matrix: name.clone(),
+                });
+            }
+        }
+
+        for (name, object) in scene.objects.iter() {
+            self.object(name, &object.0);
+        }
+
+        for (name, texture) in scene.textures.iter() {
+            self.texture(name, texture);
+        }
+
+        for (name, stage) in scene.animation_stages.iter() {
+            self.animation_stage(name, stage);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Err((_, _, errors)) = scene.validate_shader(&ShaderOptions::default()) {
+            for (identifier, errors) in errors.0 {
+                for (line, message) in errors {
+                    self.result.push(Diagnostic::Glsl {
+                        source: scene.error_source(identifier),
+                        line: Some(line).filter(|x| *x != GENERATED_LINE && *x != usize::MAX),
+                        message,
+                    });
+                }
+            }
+        }
+
+        self.result
+    }
+}
+
+impl Scene {
+    /// Finds errors that are detectable without GPU: broken references, duplicate names, matrix recursion, bad formulas, missing texture files, inconsistent animation stages and errors of generated shader found by naga, see `Scene::validate_shader`.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        self.validate_in(None)
+    }
+
+    /// Same as `validate`, but texture files are also searched near scene file, like they are searched when scene is loaded from `scene_dir`.
+    pub fn validate_in(&self, scene_dir: Option<&Path>) -> Vec<Diagnostic> {
+        Validator {
+            scene: self,
+            scene_dir,
+            formulas_cache: Default::default(),
+            result: Vec::new(),
+        }
+        .validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(json: &str) -> Scene {
+        Scene::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn bundled_scenes_are_valid() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map(|x| x == "json").unwrap_or(false) {
+                let scene = scene(&std::fs::read_to_string(&path).unwrap());
+                assert_eq!(scene.validate_in(Some(&dir)), vec![], "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn textures_near_scene() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut scene = crate::scene_files::embedded_scene("monoportal").unwrap();
+        scene.textures.storage[0].0 = "monoportal.png".to_owned();
+        assert_eq!(scene.validate_in(Some(&dir)), vec![]);
+
+        let other_dir = dir.join("snapshots");
+        assert_eq!(
+            scene.validate_in(Some(&other_dir)),
+            vec![Diagnostic::MissingTexture {
+                texture: scene.textures.names[0].clone(),
+                path: "monoportal.png".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn broken_scene() {
+        let mut scene = scene(
+            r#"{
+                "description_en": "", "description_ru": "",
+                "cam": { "look_at": [0, 0, 0], "alpha": 0, "beta": 1, "r": 3 },
+                "uniforms": {
+                    "names": ["a", "a", "f"],
+                    "storage": [{ "Bool": true }, { "Bool": false }, { "Formula": "1 +" }]
+                },
+                "matrices": {
+                    "names": ["id", "loop1", "loop2", "param"],
+                    "storage": [
+                        { "Simple": { "offset": [0, 0, 0], "scale": 1, "rotate": [0, 0, 0], "mirror": [false, false, false] } },
+                        { "Mul": { "to": "loop2", "what": "id" } },
+                        { "Mul": { "to": "loop1", "what": "id" } },
+                        { "Parametrized": {
+                            "offset": { "x": { "Yes": "nope" }, "y": { "No": 0 }, "z": { "No": 0 } },
+                            "rotate": { "x": { "No": 0 }, "y": { "No": 0 }, "z": { "No": 0 } },
+                            "mirror": { "x": { "No": 0 }, "y": { "No": 0 }, "z": { "No": 0 } },
+                            "scale": { "No": 1 }
+                        } }
+                    ]
+                },
+                "objects": {
+                    "names": ["debug"],
+                    "storage": [{ "DebugMatrix": "missing" }]
+                },
+                "textures": { "names": ["t"], "storage": ["no/such/texture.png"] },
+                "materials": { "names": [], "storage": [] },
+                "library": { "names": [], "storage": [] },
+                "user_uniforms": { "uniforms": [], "matrices": [] },
+                "animation_stages": {
+                    "names": ["stage"],
+                    "storage": [{ "uniforms": [], "matrices": ["Remains", "Remains", "Remains", "Remains"] }]
+                },
+                "current_stage": 0
+            }"#,
+        );
+        scene.animation_stages.storage[0]
+            .matrices
+            .insert(EntityId(10), Animation::Remains);
+        let diagnostics = scene.validate();
+        use Diagnostic::*;
+        let expected = vec![
+            DuplicateName {
+                storage: StorageKind::Uniforms,
+                name: "a".to_owned(),
+            },
+            FormulaParse {
+                storage: StorageKind::Uniforms,
+                entity: "f".to_owned(),
+                formula: "1 +".to_owned(),
+            },
+            UnknownUniform {
+                storage: StorageKind::Matrices,
+                entity: "param".to_owned(),
+                name: "nope".to_owned(),
+            },
+            MatrixRecursion {
+                matrix: "loop1".to_owned(),
+            },
+            MatrixRecursion {
+                matrix: "loop2".to_owned(),
+            },
+            UnknownMatrix {
+                storage: StorageKind::Objects,
+                entity: "debug".to_owned(),
+                name: "missing".to_owned(),
+            },
+            MissingTexture {
+                texture: "t".to_owned(),
+                path: "no/such/texture.png".to_owned(),
+            },
+            AnimationStageUnknownId {
+                stage: "stage".to_owned(),
+                storage: StorageKind::Matrices,
+                id: EntityId(10),
+            },
+        ];
+        assert_eq!(diagnostics, expected);
+    }
+}
@@ -5,4 +5,20 @@ pub mod gui;
 
 pub mod code_generation;
 
+pub mod dead_code_elimination;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod glsl_validation;
+
+pub mod bvh;
+
 pub mod shader_error_parser;
+
+pub mod cpu_render;
+
+pub mod scene_files;
+
+pub mod autosave;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod wgsl;
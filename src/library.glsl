@@ -1,8 +1,3 @@
-#version 100
-// Version can't be changed to upper versions because of WebGL.
-
-precision highp float;
-
 // ---------------------------------------------------------------------------
 // Scalar math ---------------------------------------------------------------
 // ---------------------------------------------------------------------------
@@ -251,6 +246,45 @@ bool nearer(SceneIntersection result, SceneIntersection current) {
     return nearer(result, current.hit);
 }
 
+// ---------------------------------------------------------------------------
+// Bounding volumes ----------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Distance along normalized ray to sphere, 0 when ray starts inside it, and -1 when ray misses it.
+float sphere_bounds(Ray r, vec3 center, float radius) {
+    vec3 oc = r.o.xyz - center;
+    float b = dot(oc, r.d.xyz);
+    float c = dot(oc, oc) - radius * radius;
+    if (c <= 0.) {
+        return 0.;
+    }
+    float h = b * b - c;
+    if (b > 0. || h < 0.) {
+        return -1.;
+    }
+    return -b - sqrt(h);
+}
+
+// Distance along normalized ray to axis-aligned box, 0 when ray starts inside it, and -1 when ray misses it.
+float box_bounds(Ray r, vec3 box_min, vec3 box_max) {
+    vec3 inv = 1. / r.d.xyz;
+    vec3 t1 = (box_min - r.o.xyz) * inv;
+    vec3 t2 = (box_max - r.o.xyz) * inv;
+    vec3 t_min = min(t1, t2);
+    vec3 t_max = max(t1, t2);
+    float near = max(max(t_min.x, t_min.y), max(t_min.z, 0.));
+    float far = min(min(t_max.x, t_max.y), t_max.z);
+    if (far < near) {
+        return -1.;
+    }
+    return near;
+}
+
+// Can object with bounding volume at `distance` along transformed ray with length `len` be nearer than current intersection.
+bool bounds_nearer(SceneIntersection i, float distance, float len) {
+    return distance >= 0. && (!i.hit.hit || distance / len < i.hit.t);
+}
+
 // Get capsule normal, thanks iq: https://www.shadertoy.com/view/Xt3SzX
 vec3 cap_normal(vec3 pos, vec3 a, vec3 b, float radius) {
     vec3  ba = b - a;
@@ -327,6 +361,267 @@ SceneIntersection debug_intersect(Ray r) {
     return i;
 }
 
+// ---------------------------------------------------------------------------
+// Primitives ----------------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Intersections with primitives in their own coordinates, ray direction must be normalized. Normals are directed outside.
+
+// Intersect ray with sphere with center at origin.
+SurfaceIntersection sphere_intersect(Ray r, float radius) {
+    vec3 o = r.o.xyz;
+    vec3 d = r.d.xyz;
+    float b = dot(o, d);
+    float h = b * b - dot(o, o) + radius * radius;
+    if (h < 0.) {
+        return intersection_none;
+    }
+    h = sqrt(h);
+    float t = -b - h;
+    if (t < 0.) {
+        t = -b + h;
+    }
+    if (t < 0.) {
+        return intersection_none;
+    }
+    vec3 pos = o + d * t;
+    float u = atan(pos.y, pos.x);
+    float v = atan(length(pos.xy), pos.z);
+    return SurfaceIntersection(true, t, u, v, normalize(pos));
+}
+
+// Intersect ray with box from `-size` to `size`.
+SurfaceIntersection box_intersect(Ray r, vec3 size) {
+    vec3 inv = 1. / r.d.xyz;
+    vec3 t1 = (-size - r.o.xyz) * inv;
+    vec3 t2 = (size - r.o.xyz) * inv;
+    vec3 t_min = min(t1, t2);
+    vec3 t_max = max(t1, t2);
+    float near = max(max(t_min.x, t_min.y), t_min.z);
+    float far = min(min(t_max.x, t_max.y), t_max.z);
+    if (far < near || far < 0.) {
+        return intersection_none;
+    }
+    float t = near < 0. ? far : near;
+    vec3 pos = r.o.xyz + r.d.xyz * t;
+    vec3 p = abs(pos / size);
+    if (p.x >= p.y && p.x >= p.z) {
+        return SurfaceIntersection(true, t, pos.y, pos.z, vec3(sign(pos.x), 0., 0.));
+    } else if (p.y >= p.z) {
+        return SurfaceIntersection(true, t, pos.x, pos.z, vec3(0., sign(pos.y), 0.));
+    } else {
+        return SurfaceIntersection(true, t, pos.x, pos.y, vec3(0., 0., sign(pos.z)));
+    }
+}
+
+// Intersect ray with disk with center at origin in plane `z = 0`, normal is directed along `z`.
+SurfaceIntersection disk_intersect(Ray r, float radius) {
+    float t = -r.o.z / r.d.z;
+    vec4 pos = r.o + r.d * t;
+    if (t < 0. || dot(pos.xy, pos.xy) > radius * radius) {
+        return intersection_none;
+    }
+    return SurfaceIntersection(true, t, pos.x, pos.y, vec3(0., 0., 1.));
+}
+
+// Intersection with side of cylinder at `t`, when it's between `z = 0` and `z = height`.
+SurfaceIntersection cylinder_side(Ray r, float t, float height) {
+    vec4 pos = r.o + r.d * t;
+    if (t < 0. || !between(0., pos.z, height)) {
+        return intersection_none;
+    }
+    return SurfaceIntersection(true, t, atan(pos.y, pos.x), pos.z, normalize(vec3(pos.xy, 0.)));
+}
+
+// Intersect ray with closed cylinder around `z` axis from `z = 0` to `z = height`.
+SurfaceIntersection cylinder_intersect(Ray r, float radius, float height) {
+    SurfaceIntersection result = intersection_none;
+    SurfaceIntersection hit = disk_intersect(r, radius);
+    hit.n = -hit.n;
+    if (nearer(result, hit)) {
+        result = hit;
+    }
+    hit = disk_intersect(Ray(r.o - vec4(0., 0., height, 0.), r.d), radius);
+    if (nearer(result, hit)) {
+        result = hit;
+    }
+
+    vec2 o = r.o.xy;
+    vec2 d = r.d.xy;
+    float a = dot(d, d);
+    float b = dot(o, d);
+    float h = b * b - a * (dot(o, o) - radius * radius);
+    if (a > 0. && h >= 0.) {
+        h = sqrt(h);
+        hit = cylinder_side(r, (-b - h) / a, height);
+        if (nearer(result, hit)) {
+            result = hit;
+        }
+        hit = cylinder_side(r, (-b + h) / a, height);
+        if (nearer(result, hit)) {
+            result = hit;
+        }
+    }
+    return result;
+}
+
+// Intersection with side of cone at `t`, when it's between `z = 0` and `z = height`.
+SurfaceIntersection cone_side(Ray r, float t, float radius, float height) {
+    vec4 pos = r.o + r.d * t;
+    if (t < 0. || !between(0., pos.z, height)) {
+        return intersection_none;
+    }
+    vec3 n = normalize(vec3(pos.xy, sqr(radius / height) * (height - pos.z)));
+    return SurfaceIntersection(true, t, atan(pos.y, pos.x), pos.z, n);
+}
+
+// Intersect ray with closed cone around `z` axis with base at `z = 0` and apex at `z = height`.
+SurfaceIntersection cone_intersect(Ray r, float radius, float height) {
+    SurfaceIntersection result = intersection_none;
+    SurfaceIntersection hit = disk_intersect(r, radius);
+    hit.n = -hit.n;
+    if (nearer(result, hit)) {
+        result = hit;
+    }
+
+    float k = sqr(radius / height);
+    vec3 o = vec3(r.o.xy, height - r.o.z);
+    vec3 d = vec3(r.d.xy, -r.d.z);
+    float a = dot(d.xy, d.xy) - k * d.z * d.z;
+    float b = dot(o.xy, d.xy) - k * o.z * d.z;
+    float c = dot(o.xy, o.xy) - k * o.z * o.z;
+    float h = b * b - a * c;
+    if (a != 0. && h >= 0.) {
+        h = sqrt(h);
+        hit = cone_side(r, (-b - h) / a, radius, height);
+        if (nearer(result, hit)) {
+            result = hit;
+        }
+        hit = cone_side(r, (-b + h) / a, radius, height);
+        if (nearer(result, hit)) {
+            result = hit;
+        }
+    }
+    return result;
+}
+
+// Distance to torus around `z` axis with center at origin, or -1 when ray misses it, thanks iq: https://www.shadertoy.com/view/4sBGDy
+float torus_distance(Ray r, float major_radius, float minor_radius) {
+    vec3 ro = r.o.xyz;
+    vec3 rd = r.d.xyz;
+    float po = 1.;
+    float Ra2 = major_radius * major_radius;
+    float ra2 = minor_radius * minor_radius;
+    float m = dot(ro, ro);
+    float n = dot(ro, rd);
+
+    // Bounding sphere.
+    float h = n * n - m + sqr(major_radius + minor_radius);
+    if (h < 0.) {
+        return -1.;
+    }
+
+    // Quartic equation.
+    float k = (m - ra2 - Ra2) / 2.;
+    float k3 = n;
+    float k2 = n * n + Ra2 * rd.z * rd.z + k;
+    float k1 = k * n + Ra2 * ro.z * rd.z;
+    float k0 = k * k + Ra2 * ro.z * ro.z - Ra2 * ra2;
+
+    // Prevent |c1| from being too close to zero.
+    if (abs(k3 * (k3 * k3 - k2) + k1) < 0.01) {
+        po = -1.;
+        float tmp = k1;
+        k1 = k3;
+        k3 = tmp;
+        k0 = 1. / k0;
+        k1 = k1 * k0;
+        k2 = k2 * k0;
+        k3 = k3 * k0;
+    }
+
+    float c2 = 2. * k2 - 3. * k3 * k3;
+    float c1 = k3 * (k3 * k3 - k2) + k1;
+    float c0 = k3 * (k3 * (-3. * k3 * k3 + 4. * k2) - 8. * k1) + 4. * k0;
+    c2 /= 3.;
+    c1 *= 2.;
+    c0 /= 3.;
+
+    float Q = c2 * c2 + c0;
+    float R = 3. * c0 * c2 - c2 * c2 * c2 - c1 * c1;
+    h = R * R - Q * Q * Q;
+    float z = 0.;
+    if (h < 0.) {
+        // 4 intersections.
+        float sQ = sqrt(Q);
+        z = 2. * sQ * cos(acos(R / (sQ * Q)) / 3.);
+    } else {
+        // 2 intersections.
+        float sQ = pow(sqrt(h) + abs(R), 1. / 3.);
+        z = sign(R) * abs(sQ + Q / sQ);
+    }
+    z = c2 - z;
+
+    float d1 = z - 3. * c2;
+    float d2 = z * z - 3. * c0;
+    if (abs(d1) < 1.0e-4) {
+        if (d2 < 0.) {
+            return -1.;
+        }
+        d2 = sqrt(d2);
+    } else {
+        if (d1 < 0.) {
+            return -1.;
+        }
+        d1 = sqrt(d1 / 2.);
+        d2 = c1 / d1;
+    }
+
+    float result = 1e20;
+    h = d1 * d1 - z + d2;
+    if (h > 0.) {
+        h = sqrt(h);
+        float t1 = -d1 - h - k3;
+        float t2 = -d1 + h - k3;
+        t1 = po < 0. ? 2. / t1 : t1;
+        t2 = po < 0. ? 2. / t2 : t2;
+        if (t1 > 0.) {
+            result = t1;
+        }
+        if (t2 > 0.) {
+            result = min(result, t2);
+        }
+    }
+    h = d1 * d1 - z - d2;
+    if (h > 0.) {
+        h = sqrt(h);
+        float t1 = d1 - h - k3;
+        float t2 = d1 + h - k3;
+        t1 = po < 0. ? 2. / t1 : t1;
+        t2 = po < 0. ? 2. / t2 : t2;
+        if (t1 > 0.) {
+            result = min(result, t1);
+        }
+        if (t2 > 0.) {
+            result = min(result, t2);
+        }
+    }
+    return result < 1e20 ? result : -1.;
+}
+
+// Intersect ray with torus around `z` axis with center at origin.
+SurfaceIntersection torus_intersect(Ray r, float major_radius, float minor_radius) {
+    float t = torus_distance(r, major_radius, minor_radius);
+    if (t < 0.) {
+        return intersection_none;
+    }
+    vec3 pos = r.o.xyz + r.d.xyz * t;
+    float u = atan(pos.y, pos.x);
+    float v = atan(pos.z, length(pos.xy) - major_radius);
+    vec3 n = pos * (dot(pos, pos) - sqr(minor_radius) - sqr(major_radius) * vec3(1., 1., -1.));
+    return SurfaceIntersection(true, t, u, v, normalize(n));
+}
+
 // ---------------------------------------------------------------------------
 // Code for current scene ----------------------------------------------------
 // ---------------------------------------------------------------------------
@@ -4,7 +4,11 @@ use std::f32::consts::PI;
 use egui_macroquad::Egui;
 
 use macroquad::prelude::*;
-use portal::gui::{common::*, scene::*, texture::*};
+#[cfg(not(target_arch = "wasm32"))]
+use portal::gui::format::SceneFormat;
+use portal::gui::{common::*, description::*, history::*, prefab::*, scene::*, texture::*};
+use portal::autosave::*;
+use portal::scene_files::*;
 
 use egui::{DragValue, Ui};
 
@@ -91,23 +95,13 @@ impl RotateAroundCam {
     }
 
     fn get_matrix(&self) -> Mat4 {
-        let pos = Vec3::new(
-            self.beta.sin() * self.alpha.cos(),
-            self.beta.cos(),
-            self.beta.sin() * self.alpha.sin(),
-        ) * self.r
-            + self.look_at;
-
-        let k = (self.look_at - pos).normalize();
-        let i = k.cross(Vec3::new(0., 1., 0.)).normalize();
-        let j = k.cross(i).normalize();
-
-        Mat4::from_cols(
-            Vec4::new(i.x, i.y, i.z, 0.),
-            Vec4::new(j.x, j.y, j.z, 0.),
-            Vec4::new(k.x, k.y, k.z, 0.),
-            Vec4::new(pos.x, pos.y, pos.z, 1.),
-        )
+        let cam = CamSettings {
+            look_at: ::glam::Vec3::new(self.look_at.x, self.look_at.y, self.look_at.z),
+            alpha: self.alpha,
+            beta: self.beta,
+            r: self.r,
+        };
+        Mat4::from_cols_array(&cam.get_matrix().to_cols_array())
     }
 
     fn set_cam(&mut self, s: &CamSettings) {
@@ -253,106 +247,167 @@ struct Window {
     camera_settings_opened: bool,
     render_options_opened: bool,
     about_opened: bool,
+    history_opened: bool,
+    merge_opened: bool,
+    merge_window: MergeWindow,
     import_window: Option<String>,
     import_window_errors: Option<String>,
 
     error_message: Option<(String, String)>,
 
     data: Data,
+    history: History,
+    autosave: Autosave,
+    /// Unsaved scene of previous session, user is asked whether to restore it.
+    recovered: Option<Scene>,
 
     offset_after_material: f32,
     render_depth: i32,
 
-    available_scenes: Vec<(String, String, String)>,
+    available_scenes: Vec<SceneFile>,
+    /// Id of loaded scene, it's used as base for shareable link.
+    scene_id: Option<String>,
+    share_link: Option<String>,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    scenes_dir: std::path::PathBuf,
+    #[cfg(not(target_arch = "wasm32"))]
+    scene_path: Option<std::path::PathBuf>,
+    #[cfg(not(target_arch = "wasm32"))]
+    save_window: Option<String>,
+    /// Time when generated shader is checked by naga. Check parses the whole shader, so it's done when edits stop, not on every keystroke.
+    #[cfg(not(target_arch = "wasm32"))]
+    validate_at: Option<f64>,
+    #[cfg(not(target_arch = "wasm32"))]
+    save_window_errors: Option<String>,
 }
 
 impl Window {
     async fn new() -> Self {
-        let available_scenes: Vec<(String, String, String)> = vec![
-            ("Empty", "empty", include_str!("../scenes/empty.json")),
-            ("Room", "room", include_str!("../scenes/room.json")),
-            (
-                "Monoportal",
-                "monoportal",
-                include_str!("../scenes/monoportal.json"),
-            ),
-            // (
-            //     "Monoportal offset",
-            //     "monoportal_offset",
-            //     include_str!("../scenes/monoportal_offset.json"),
-            // ),
-            (
-                "Mobius portal",
-                "mobius",
-                include_str!("../scenes/mobius.json"),
-            ),
-            (
-                "Mobius monoportal",
-                "mobius_monoportal",
-                include_str!("../scenes/mobius_monoportal.json"),
-            ),
-            // ("Misc", "misc", include_str!("../scenes/misc.json")),
-        ]
-        .into_iter()
-        .map(|(a, b, c)| (a.to_owned(), b.to_owned(), c.to_owned()))
-        .collect();
-
-        let start1 = "--scene=";
-        let start2 = "-s=";
-
-        let default_scene = PROGRAM_PARAMETERS
-            .iter()
-            .find(|s| s.starts_with(start1) || s.starts_with(start2))
-            .and_then(|s| {
-                let mut s = &s[..];
-                if s.starts_with(start1) {
-                    s = &s[start1.len()..];
-                } else if s.starts_with(start2) {
-                    s = &s[start2.len()..];
-                }
+        let mut available_scenes = embedded_scenes();
 
-                available_scenes.iter().position(|(_, path, _)| path == s)
-            })
+        #[cfg(not(target_arch = "wasm32"))]
+        let scenes_dir = std::path::PathBuf::from(
+            program_parameter(&["--scenes-dir="]).unwrap_or_else(|| "scenes".to_owned()),
+        );
+        #[cfg(not(target_arch = "wasm32"))]
+        match scan_scenes_dir(&scenes_dir) {
+            Ok(scenes) if !scenes.is_empty() => available_scenes = scenes,
+            Ok(_) => {}
+            Err(err) => eprintln!("{}, using embedded scenes", err),
+        }
+
+        let default_scene = program_parameter(&["--scene=", "-s="])
+            .and_then(|s| find_scene(&mut available_scenes, &s))
             .unwrap_or(0);
 
         let mut data = Default::default();
 
-        let mut scene: Scene = serde_json::from_str(&available_scenes[default_scene].2).unwrap();
-        // let mut scene: Scene = serde_json::from_str::<OldScene>(&available_scenes[default_scene].2) .unwrap() .into();
+        let file = &available_scenes[default_scene];
+        let mut import_window = None;
+        let mut import_window_errors = None;
+        let (mut scene, mut scene_id, mut history_description) = match parse_scene(file) {
+            Ok(scene) => (scene, Some(file.id.clone()), format!("Load {}", file.name)),
+            Err(err) => {
+                crate::miniquad::error!("can't load scene {}: {}", file.name, err);
+                // Broken file is opened in import window, so it can be fixed there.
+                import_window = Some(file.content.clone());
+                import_window_errors = Some(err);
+                let scene = embedded_scene("empty").expect("embedded scenes are valid");
+                (scene, None, "Load Empty".to_owned())
+            }
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let scene_path = file.path.clone().filter(|_| scene_id.is_some());
+        if let Some(link) = program_parameter(&["--link=", "-l="]) {
+            match Scene::from_link(&link, embedded_scene) {
+                Ok(linked) => {
+                    scene = linked;
+                    scene_id = None;
+                    history_description = "Open link".to_owned();
+                }
+                Err(err) => eprintln!("can't open link: {}", err),
+            }
+        }
         scene.init(&mut data);
 
         data.reload_textures = true;
 
-        let material = scene.get_new_material().unwrap_or_else(|err| {
-            println!("code:\n{}\n\nmessage:\n{}", add_line_numbers(&err.0), err.1);
-            dbg!(&err);
-            crate::miniquad::error!("code:\n{}\n\nmessage:\n{}", add_line_numbers(&err.0), err.1);
-            std::process::exit(1)
-        });
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut autosave = Autosave::new(
+            program_parameter(&["--autosave="])
+                .map(std::path::PathBuf::from)
+                .unwrap_or_else(default_autosave_path),
+            &scene,
+        );
+        #[cfg(target_arch = "wasm32")]
+        let mut autosave = Autosave::new(&scene);
+        let recovered = match autosave.recover() {
+            Some(Ok(scene)) => Some(scene),
+            Some(Err(err)) => {
+                eprintln!("{}", err);
+                None
+            }
+            None => None,
+        };
+
+        let mut error_message = None;
+        let material = match scene.get_new_material(&data.shader_options) {
+            Ok(material) => material,
+            Err(err) => {
+                crate::miniquad::error!("scene can't be compiled:\n{}", err.1);
+                // Window can't exist without material, so empty scene is drawn until errors are
+                // fixed in editor, instead of exiting and losing the scene.
+                data.errors = err.2;
+                data.show_error_window = true;
+                error_message = Some((err.0, err.1));
+                fallback_material(&data.shader_options)
+            }
+        };
         scene.set_uniforms(material, &mut data, &scene.uniforms);
+        let history = History::new(&scene, &history_description);
         let mut result = Window {
-            should_recompile: false,
+            should_recompile: error_message.is_some(),
             scene,
             cam: RotateAroundCam::new(),
 
             material,
 
             control_scene_opened: true,
-            edit_scene_opened: false,
+            edit_scene_opened: error_message.is_some(),
             camera_settings_opened: false,
             render_options_opened: false,
             about_opened: false,
-            import_window: None,
-            import_window_errors: None,
+            history_opened: false,
+            merge_opened: false,
+            merge_window: Default::default(),
+            import_window,
+            import_window_errors,
 
-            error_message: None,
+            error_message,
 
             data,
+            history,
+            autosave,
+            recovered,
 
             offset_after_material: 0.005,
             render_depth: 100,
 
             available_scenes,
+            scene_id,
+            share_link: None,
+
+            #[cfg(not(target_arch = "wasm32"))]
+            scenes_dir,
+            #[cfg(not(target_arch = "wasm32"))]
+            scene_path,
+            #[cfg(not(target_arch = "wasm32"))]
+            save_window: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            validate_at: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            save_window_errors: None,
         };
         result.cam.set_cam(&result.scene.cam);
         result.reload_textures().await;
@@ -364,7 +419,16 @@ impl Window {
             self.data.reload_textures = false;
             self.data.texture_errors.0.clear();
             for (name, path) in self.scene.textures.iter() {
-                match macroquad::file::load_file(&path.0).await {
+                #[allow(unused_mut)]
+                let mut result = macroquad::file::load_file(&path.0).await;
+                #[cfg(not(target_arch = "wasm32"))]
+                if result.is_err() {
+                    let scene_dir = self.scene_path.as_ref().and_then(|x| x.parent());
+                    if let Some(found) = path.candidates(scene_dir).iter().find(|x| x.exists()) {
+                        result = macroquad::file::load_file(&found.to_string_lossy()).await;
+                    }
+                }
+                match result {
                     Ok(bytes) => {
                         let context = unsafe { get_internal_gl().quad_context };
 
@@ -389,19 +453,20 @@ impl Window {
             use egui::menu;
             menu::bar(ui, |ui| {
                 menu::menu(ui, "🗋 Load", |ui| {
-                    for (name, _, text) in &self.available_scenes {
-                        if ui.button(name).clicked() {
-                            let s = text;
-                            // let old: OldScene = serde_json::from_str(&s).unwrap();
-                            // *self = old.into();
-                            self.scene = serde_json::from_str(&s).unwrap();
-                            self.scene.init(&mut self.data);
-                            self.material.delete();
-                            self.material = self.scene.get_new_material().unwrap();
-                            changed.uniform = true;
-                            self.data.reload_textures = true;
-                            self.cam.set_cam(&self.scene.cam);
+                    let mut to_load = None;
+                    for (pos, file) in self.available_scenes.iter().enumerate() {
+                        #[allow(unused_mut)]
+                        let mut response = ui.button(&file.name);
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if let Some(path) = &file.path {
+                            response = response.on_hover_text(path.display().to_string());
                         }
+                        if response.clicked() {
+                            to_load = Some(pos);
+                        }
+                    }
+                    if let Some(pos) = to_load {
+                        changed |= self.load_scene(pos);
                     }
                     ui.separator();
                     if ui.button("Import...").clicked() {
@@ -409,6 +474,22 @@ impl Window {
                             self.import_window = Some("".to_owned());
                         }
                     }
+                    if ui.button("Merge scene...").clicked() {
+                        self.merge_opened = true;
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Save...").clicked() && self.save_window.is_none() {
+                        let path = self
+                            .scene_path
+                            .clone()
+                            .unwrap_or_else(|| {
+                                self.scenes_dir.join(format!(
+                                    "untitled.{}",
+                                    self.data.export_format.extension()
+                                ))
+                            });
+                        self.save_window = Some(path.display().to_string());
+                    }
                 });
                 if ui.button("☑ Control scene").clicked() {
                     self.control_scene_opened = true;
@@ -416,6 +497,19 @@ impl Window {
                 if ui.button("✏ Edit scene").clicked() {
                     self.edit_scene_opened = true;
                 }
+                if ui.button("🔗 Share").clicked() {
+                    let base = self
+                        .scene_id
+                        .as_ref()
+                        .and_then(|id| Some((id, embedded_scene(id).ok()?)));
+                    self.share_link = Some(
+                        self.scene
+                            .to_link(base.as_ref().map(|(id, base)| (&id[..], base))),
+                    );
+                }
+                if ui.button("⟲ History").clicked() {
+                    self.history_opened = true;
+                }
                 if ui.button("📸 Camera settings").clicked() {
                     self.camera_settings_opened = true;
                 }
@@ -443,17 +537,26 @@ impl Window {
                 self.scene
                     .egui(ui, &mut self.data, &mut self.should_recompile);
 
+            if changed1.uniform || changed1.shader {
+                self.history.push(&self.scene, changed1.clone(), get_time());
+            }
+
             changed |= changed1;
 
             if changed.shader {
                 self.should_recompile = true;
+
+                // Errors that can be found without GPU are shown before recompilation.
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    self.validate_at = Some(get_time() + VALIDATION_DELAY);
+                }
             }
 
             if let Some(material) = material {
                 match material {
                     Ok(material) => {
-                        self.material.delete();
-                        self.material = material;
+                        self.set_material(material);
                         self.error_message = None;
                     }
                     Err(err) => {
@@ -541,6 +644,16 @@ First, predefined library is included, then uniforms, then user library, then in
                 self.data.to_export = None;
             }
             self.edit_scene_opened = edit_scene_opened;
+
+            // Matrices can be edited only when they are uniforms. Shader is the same when there is
+            // nothing to fold.
+            if self.data.shader_options.fold_static_matrices == edit_scene_opened {
+                self.data.shader_options.fold_static_matrices = !edit_scene_opened;
+                if !self.scene.static_matrices().is_empty() {
+                    self.recompile();
+                    changed.uniform = true;
+                }
+            }
         }
 
         {
@@ -556,17 +669,22 @@ First, predefined library is included, then uniforms, then user library, then in
                                 .text_style(egui::TextStyle::Monospace),
                         );
                         if ui.button("Recompile").clicked() {
-                            match serde_json::from_str::<Scene>(content) {
+                            match Scene::from_text(content) {
                                 Ok(scene) => {
                                     self.scene = scene;
                                     self.scene.init(&mut self.data);
                                     self.cam.set_cam(&self.scene.cam);
+                                    self.history = History::new(&self.scene, "Import");
+                                    self.scene_id = None;
+                                    #[cfg(not(target_arch = "wasm32"))]
+                                    {
+                                        self.scene_path = None;
+                                    }
                                     changed.uniform = true;
                                     self.data.reload_textures = true;
-                                    match self.scene.get_new_material() {
+                                    match self.scene.get_new_material(&self.data.shader_options) {
                                         Ok(material) => {
-                                            self.material.delete();
-                                            self.material = material;
+                                            self.set_material(material);
                                         },
                                         Err(_) => {
                                             self.should_recompile = true;
@@ -575,7 +693,7 @@ First, predefined library is included, then uniforms, then user library, then in
                                     }
                                 },
                                 Err(err) => {
-                                    self.import_window_errors = Some(err.to_string());
+                                    self.import_window_errors = Some(err);
                                 }
                             }
                         }
@@ -594,24 +712,208 @@ First, predefined library is included, then uniforms, then user library, then in
             }
         }
 
+        {
+            let mut opened = self.share_link.is_some();
+            if let Some(link) = &self.share_link {
+                egui::Window::new("Share scene")
+                    .open(&mut opened)
+                    .default_width(500.)
+                    .show(ctx, |ui| {
+                        ui.label("Link for web version:");
+                        let mut url = format!("{}?link={}", WEB_URL, link);
+                        ui.add(
+                            egui::TextEdit::multiline(&mut url)
+                                .text_style(egui::TextStyle::Monospace),
+                        );
+                        ui.label("Parameter for native version:");
+                        let mut parameter = format!("--link={}", link);
+                        ui.add(
+                            egui::TextEdit::multiline(&mut parameter)
+                                .text_style(egui::TextStyle::Monospace),
+                        );
+                        ui.label(
+                            "Scene, camera and current animation stage are stored in the link.",
+                        );
+                    });
+            }
+            if !opened {
+                self.share_link = None;
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut opened = self.save_window.is_some();
+            let mut save_window = self.save_window.clone();
+            if let Some(path) = &mut save_window {
+                egui::Window::new("Save scene")
+                    .open(&mut opened)
+                    .show(ctx, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Path:");
+                            ui.text_edit_singleline(path);
+                        });
+                        if ui.button("Save").clicked() {
+                            let path = std::path::PathBuf::from(&path[..]);
+                            match save_scene_file(&self.scene, &path) {
+                                Ok(()) => {
+                                    self.autosave.set_clean(&self.scene);
+                                    self.scene_path = Some(path);
+                                    self.save_window_errors = None;
+                                    if let Ok(scenes) = scan_scenes_dir(&self.scenes_dir) {
+                                        if !scenes.is_empty() {
+                                            self.available_scenes = scenes;
+                                        }
+                                    }
+                                }
+                                Err(err) => {
+                                    self.save_window_errors = Some(err);
+                                }
+                            }
+                        }
+                        if let Some(err) = &self.save_window_errors {
+                            ui.horizontal_wrapped_for_text(egui::TextStyle::Body, |ui| {
+                                ui.add(egui::Label::new("Error: ").text_color(egui::Color32::RED));
+                                ui.label(err);
+                            });
+                        } else if let Some(path) = &self.scene_path {
+                            ui.label(format!("Saved to {}", path.display()));
+                        }
+                    });
+                self.save_window = save_window;
+            }
+            if !opened {
+                self.save_window = None;
+                self.save_window_errors = None;
+            }
+        }
+
+        {
+            let mut merge_opened = self.merge_opened;
+            let mut merge_changed = WhatChanged::default();
+            egui::Window::new("Merge scene")
+                .open(&mut merge_opened)
+                .scroll(true)
+                .show(ctx, |ui| {
+                    merge_changed = self.merge_window.egui(
+                        ui,
+                        &mut self.scene,
+                        &self.available_scenes,
+                        &mut self.data.formulas_cache,
+                    );
+                });
+            self.merge_opened = merge_opened;
+
+            if merge_changed.uniform || merge_changed.shader {
+                self.history
+                    .push(&self.scene, merge_changed.clone(), get_time());
+                self.should_recompile |= merge_changed.shader;
+                changed |= merge_changed;
+            }
+        }
+
+        {
+            let mut history_opened = self.history_opened;
+            let mut restored = None;
+            egui::Window::new("History")
+                .open(&mut history_opened)
+                .scroll(true)
+                .show(ctx, |ui| {
+                    restored = self.history.egui(ui);
+                });
+            self.history_opened = history_opened;
+
+            if !ctx.wants_keyboard_input()
+                && (is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl))
+                && is_key_pressed(KeyCode::Z)
+            {
+                if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+                    restored = self.history.redo();
+                } else {
+                    restored = self.history.undo();
+                }
+            }
+
+            if let Some((scene, changed1)) = restored {
+                changed |= self.restore_scene(scene, changed1);
+            }
+        }
+
+        if let Some(recovered) = self.recovered.take() {
+            let mut restore = false;
+            let mut discard = false;
+            egui::Window::new("Restore session")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label("Scene from previous session was not saved.");
+                    ui.horizontal(|ui| {
+                        restore = ui.button("Restore").clicked();
+                        discard = ui.button("Discard").clicked();
+                    });
+                });
+            if restore {
+                changed |= self.restore_scene(recovered, WhatChanged::from_shader(true));
+                self.cam.set_cam(&self.scene.cam);
+                self.history = History::new(&self.scene, "Restore session");
+                self.scene_id = None;
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    self.scene_path = None;
+                }
+                self.autosave.resume();
+            } else if discard {
+                self.autosave.discard();
+            } else {
+                self.recovered = Some(recovered);
+            }
+        }
+        self.autosave.update(&self.scene, get_time());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if matches!(self.validate_at, Some(time) if get_time() >= time) {
+            self.validate_at = None;
+            // Shader is already recompiled, and errors of compilation are more precise.
+            if self.should_recompile {
+                self.data.errors = match self.scene.validate_shader(&self.data.shader_options) {
+                    Ok(()) => Default::default(),
+                    Err((_, _, errors)) => errors,
+                };
+            }
+        }
+
         {
             let mut control_scene_opened = self.control_scene_opened;
             egui::Window::new("Control scene")
                 .open(&mut control_scene_opened)
                 .scroll(true)
                 .show(ctx, |ui| {
-                    ui.collapsing("Description", |ui| {
-                        ui.horizontal(|ui| {
-                            ui.selectable_value(&mut self.data.read_ru, false, "Eng");
-                            ui.selectable_value(&mut self.data.read_ru, true, "Rus");
+                    let descriptions = &self.scene.descriptions;
+                    let locale = &mut self.data.locale;
+                    if let Some((shown, text)) = descriptions.get(locale) {
+                        ui.collapsing("Description", |ui| {
+                            if descriptions.locales().count() > 1 {
+                                ui.horizontal(|ui| {
+                                    for available in descriptions.locales() {
+                                        if ui
+                                            .selectable_label(
+                                                available == shown,
+                                                locale_name(available),
+                                            )
+                                            .clicked()
+                                        {
+                                            *locale = available.to_owned();
+                                        }
+                                    }
+                                });
+                            }
+                            egui::experimental::easy_mark(ui, text);
                         });
-                        if self.data.read_ru {
-                            egui::experimental::easy_mark(ui, &self.scene.description_ru);
-                        } else {
-                            egui::experimental::easy_mark(ui, &self.scene.description_en);
-                        }
-                    });
-                    changed |= self.scene.control_egui(ui, &mut self.data);
+                    }
+                    let changed1 = self.scene.control_egui(ui, &mut self.data);
+                    if changed1.uniform || changed1.shader {
+                        self.history.push(&self.scene, changed1.clone(), get_time());
+                    }
+                    changed |= changed1;
                 });
             self.control_scene_opened = control_scene_opened;
         }
@@ -636,6 +938,12 @@ First, predefined library is included, then uniforms, then user library, then in
                     changed |= self.cam.egui(ui);
                 });
             self.camera_settings_opened = camera_settings_opened;
+
+            // Panini projection is compiled only when it's used.
+            if self.data.shader_options.panini != self.cam.use_panini_projection {
+                self.data.shader_options.panini = self.cam.use_panini_projection;
+                self.recompile();
+            }
         }
 
         {
@@ -690,6 +998,80 @@ First, predefined library is included, then uniforms, then user library, then in
         return is_something_changed;
     }
 
+    fn load_scene(&mut self, pos: usize) -> WhatChanged {
+        let file = self.available_scenes[pos].clone();
+        match parse_scene(&file) {
+            Ok(scene) => {
+                self.scene = scene;
+                self.scene.init(&mut self.data);
+                match self.scene.get_new_material(&self.data.shader_options) {
+                    Ok(material) => {
+                        self.set_material(material);
+                        self.should_recompile = false;
+                        self.error_message = None;
+                    }
+                    Err(err) => {
+                        self.should_recompile = true;
+                        self.error_message = Some((err.0, err.1));
+                        self.data.errors = err.2;
+                    }
+                }
+                self.data.reload_textures = true;
+                self.cam.set_cam(&self.scene.cam);
+                self.history = History::new(&self.scene, &format!("Load {}", file.name));
+                self.autosave.set_clean(&self.scene);
+                self.scene_id = Some(file.id.clone());
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    self.scene_path = file.path;
+                }
+                WhatChanged::from_uniform(true)
+            }
+            Err(err) => {
+                self.import_window = Some(file.content.clone());
+                self.import_window_errors = Some(err);
+                WhatChanged::default()
+            }
+        }
+    }
+
+    /// Replaces scene by snapshot from history, recompiling shader if the snapshot differs in code, or in matrices that are folded into code.
+    fn restore_scene(&mut self, scene: Scene, changed: WhatChanged) -> WhatChanged {
+        self.scene = scene;
+        self.scene.init(&mut self.data);
+        if changed.shader || self.data.shader_options.fold_static_matrices {
+            self.recompile();
+        }
+        WhatChanged::from_uniform(true)
+    }
+
+    /// Replaces material by newly compiled one. Texture of packed uniforms is kept only while new material uses it.
+    fn set_material(&mut self, material: macroquad::material::Material) {
+        self.material.delete();
+        self.material = material;
+        if !self.data.shader_options.pack_uniforms {
+            if let Some(texture) = self.data.packed_uniforms_texture.take() {
+                texture.delete();
+            }
+        }
+    }
+
+    fn recompile(&mut self) {
+        match self.scene.get_new_material(&self.data.shader_options) {
+            Ok(material) => {
+                self.set_material(material);
+                self.should_recompile = false;
+                self.error_message = None;
+                self.data.reload_textures = true;
+            }
+            Err(err) => {
+                self.should_recompile = true;
+                self.error_message = Some((err.0, err.1));
+                self.data.errors = err.2;
+            }
+        }
+    }
+
     fn set_uniforms(&mut self) {
         self.cam.get_cam(&mut self.scene.cam);
         self.material
@@ -699,10 +1081,6 @@ First, predefined library is included, then uniforms, then user library, then in
             .set_uniform("_view_angle", self.cam.view_angle);
         self.material
             .set_uniform("_panini_param", self.cam.panini_param);
-        self.material.set_uniform(
-            "_use_panini_projection",
-            self.cam.use_panini_projection as i32,
-        );
         self.material
             .set_uniform("_ray_tracing_depth", self.render_depth);
         self.material
@@ -718,6 +1096,55 @@ First, predefined library is included, then uniforms, then user library, then in
     }
 }
 
+/// Material of empty scene, or material that only fills window by background color when even empty scene can't be compiled.
+fn fallback_material(options: &ShaderOptions) -> Material {
+    let empty = embedded_scene("empty")
+        .and_then(|scene| scene.get_new_material(options).map_err(|err| err.1));
+    match empty {
+        Ok(material) => material,
+        Err(err) => {
+            crate::miniquad::error!("empty scene can't be compiled:\n{}", err);
+            load_material(
+                PLAIN_VERTEX_SHADER,
+                PLAIN_FRAGMENT_SHADER,
+                Default::default(),
+            )
+            .expect("shader without uniforms is compiled by every GPU")
+        }
+    }
+}
+
+const PLAIN_VERTEX_SHADER: &str = "#version 100
+attribute vec3 position;
+uniform mat4 Model;
+uniform mat4 Projection;
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+}
+";
+
+const PLAIN_FRAGMENT_SHADER: &str = "#version 100
+void main() {
+    gl_FragColor = vec4(0.6, 0.6, 0.6, 1.);
+}
+";
+
+/// Seconds without shader edits after which generated shader is checked, see `Window::validate_at`.
+#[cfg(not(target_arch = "wasm32"))]
+const VALIDATION_DELAY: f64 = 0.5;
+
+const WEB_URL: &str = "https://optozorax.github.io/portal/";
+
+/// Value of `--name=value` parameter, in web version parameters are taken from URL.
+fn program_parameter(prefixes: &[&str]) -> Option<String> {
+    PROGRAM_PARAMETERS.iter().find_map(|s| {
+        prefixes
+            .iter()
+            .find_map(|prefix| s.strip_prefix(prefix))
+            .map(|s| s.to_owned())
+    })
+}
+
 fn window_conf() -> Conf {
     Conf {
         window_title: "Portal Explorer".to_owned(),
@@ -726,8 +1153,353 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
+#[cfg(not(target_arch = "wasm32"))]
+const RENDER_USAGE: &str = "\
+Usage: portal render --scene=<scene.json> [options]
+
+Renders scene by the same shader as editor into offscreen buffer and writes it to PNG file. Window is
+opened while rendering, because OpenGL context can't be created without it.
+
+Options:
+    --output=<file.png>     Output file, default is scene path with `.png` extension
+    --width=<pixels>        Default is 500, as scene thumbnails
+    --height=<pixels>       Default is 500
+    --depth=<n>             Ray tracing depth, default is 100
+    --offset=<f>            Offset after material, default is 0.005
+    --view-angle=<degrees>  Default is 90
+    --panini=<f>            Use panini projection with this parameter
+    --look-at=<x>,<y>,<z>   Override camera from scene
+    --alpha=<degrees>
+    --beta=<degrees>
+    --r=<f>";
+
+/// Scene and settings of `portal render`, they are parsed before window is opened, so wrong arguments are reported without it.
+#[cfg(not(target_arch = "wasm32"))]
+struct OfflineRender {
+    scene: Scene,
+    scene_path: std::path::PathBuf,
+    output_path: std::path::PathBuf,
+    settings: portal::cpu_render::RenderSettings,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_render_args(args: &[String]) -> Result<OfflineRender, String> {
+    use portal::cpu_render::*;
+    use std::path::PathBuf;
+
+    let mut scene_path = None;
+    let mut output_path = None;
+    let mut settings = RenderSettings {
+        width: 500,
+        height: 500,
+        ..Default::default()
+    };
+    let mut cam_look_at = None;
+    let mut cam_alpha = None;
+    let mut cam_beta = None;
+    let mut cam_r = None;
+
+    fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+        value
+            .parse()
+            .map_err(|_| format!("can't parse value `{}` of `--{}`", value, name))
+    }
+
+    for arg in args {
+        let (name, value) = match (arg.starts_with("--"), arg.find('=')) {
+            (true, Some(pos)) => (&arg[2..pos], &arg[pos + 1..]),
+            _ => return Err(format!("unknown argument `{}`\n\n{}", arg, RENDER_USAGE)),
+        };
+        match name {
+            "scene" => scene_path = Some(PathBuf::from(value)),
+            "output" => output_path = Some(PathBuf::from(value)),
+            "width" => settings.width = parse(name, value)?,
+            "height" => settings.height = parse(name, value)?,
+            "depth" => settings.ray_tracing_depth = parse(name, value)?,
+            "offset" => settings.offset_after_material = parse(name, value)?,
+            "view-angle" => settings.view_angle = deg2rad(parse(name, value)?),
+            "panini" => {
+                settings.use_panini_projection = true;
+                settings.panini_param = parse(name, value)?;
+            }
+            "look-at" => {
+                let coords = value
+                    .split(',')
+                    .map(|x| parse(name, x.trim()))
+                    .collect::<Result<Vec<f32>, _>>()?;
+                if coords.len() != 3 {
+                    return Err(format!(
+                        "`--look-at` must have 3 coordinates, got `{}`",
+                        value
+                    ));
+                }
+                cam_look_at = Some(::glam::Vec3::new(coords[0], coords[1], coords[2]));
+            }
+            "alpha" => cam_alpha = Some(deg2rad(parse(name, value)?)),
+            "beta" => cam_beta = Some(deg2rad(parse(name, value)?)),
+            "r" => cam_r = Some(parse(name, value)?),
+            _ => return Err(format!("unknown argument `{}`\n\n{}", arg, RENDER_USAGE)),
+        }
+    }
+
+    let scene_path =
+        scene_path.ok_or_else(|| format!("`--scene` is required\n\n{}", RENDER_USAGE))?;
+    let output_path = output_path.unwrap_or_else(|| scene_path.with_extension("png"));
+    if settings.width == 0 || settings.height == 0 {
+        return Err("resolution must be non-zero".to_owned());
+    }
+    if settings.width > u16::MAX as usize || settings.height > u16::MAX as usize {
+        return Err(format!("resolution must be at most {}", u16::MAX));
+    }
+
+    let scene_text = std::fs::read_to_string(&scene_path)
+        .map_err(|e| format!("{}: {}", scene_path.display(), e))?;
+    let scene =
+        Scene::from_text(&scene_text).map_err(|e| format!("{}: {}", scene_path.display(), e))?;
+
+    let mut cam = scene.cam.clone();
+    cam.look_at = cam_look_at.unwrap_or(cam.look_at);
+    cam.alpha = cam_alpha.unwrap_or(cam.alpha);
+    cam.beta = clamp(
+        cam_beta.unwrap_or(cam.beta),
+        RotateAroundCam::BETA_MIN,
+        RotateAroundCam::BETA_MAX,
+    );
+    cam.r = cam_r.unwrap_or(cam.r);
+    settings.camera = cam.get_matrix();
+
+    Ok(OfflineRender {
+        scene,
+        scene_path,
+        output_path,
+        settings,
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn offline_render(args: &[String]) -> Result<(), String> {
+    let render = parse_render_args(args)?;
+    let conf = Conf {
+        window_title: "Portal Explorer render".to_owned(),
+        ..Default::default()
+    };
+    macroquad::Window::from_config(conf, async move {
+        match render_offscreen(render).await {
+            Ok(path) => println!("{}", path.display()),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Draws scene by generated shader into render target, as `Window::draw` does on screen, and writes result to PNG file.
+#[cfg(not(target_arch = "wasm32"))]
+async fn render_offscreen(render: OfflineRender) -> Result<std::path::PathBuf, String> {
+    let OfflineRender {
+        mut scene,
+        scene_path,
+        output_path,
+        settings,
+    } = render;
+    let error = |e: String| format!("{}: {}", scene_path.display(), e);
+
+    let mut data: Data = Default::default();
+    data.shader_options.panini = settings.use_panini_projection;
+    scene.init(&mut data);
+    let material = scene
+        .get_new_material(&data.shader_options)
+        .map_err(|err| error(err.1))?;
+    scene.set_uniforms(material, &mut data, &scene.uniforms);
+
+    let context = unsafe { get_internal_gl().quad_context };
+    for (name, path) in scene.textures.iter() {
+        let found = path
+            .candidates(scene_path.parent())
+            .into_iter()
+            .find(|x| x.exists())
+            .ok_or_else(|| error(format!("texture `{}` not found at `{}`", name, path.0)))?;
+        let bytes =
+            std::fs::read(&found).map_err(|e| error(format!("{}: {}", found.display(), e)))?;
+        let texture = Texture2D::from_file_with_format(context, &bytes[..], None);
+        material.set_texture(&TextureName::name(name), texture);
+    }
+
+    let (width, height) = (settings.width as f32, settings.height as f32);
+    let camera = Mat4::from_cols_array(&settings.camera.to_cols_array());
+    material.set_uniform("_resolution", (width, height));
+    material.set_uniform("_camera", camera);
+    material.set_uniform("_view_angle", settings.view_angle);
+    material.set_uniform("_panini_param", settings.panini_param);
+    material.set_uniform("_ray_tracing_depth", settings.ray_tracing_depth);
+    material.set_uniform("_offset_after_material", settings.offset_after_material);
+
+    let target = render_target(settings.width as u32, settings.height as u32);
+    set_camera(&Camera2D {
+        render_target: Some(target),
+        ..Camera2D::from_display_rect(Rect::new(0., 0., width, height))
+    });
+    gl_use_material(material);
+    draw_rectangle(0., 0., width, height, WHITE);
+    gl_use_default_material();
+    set_default_camera();
+    next_frame().await;
+
+    // Rows of OpenGL texture go from bottom to top.
+    let image = target.texture.get_texture_data();
+    let row = settings.width * 4;
+    let pixels = image
+        .bytes
+        .chunks(row)
+        .rev()
+        .flatten()
+        .copied()
+        .collect::<Vec<u8>>();
+    save_png(&output_path, settings.width, settings.height, &pixels)?;
+    Ok(output_path)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_png(
+    path: &std::path::Path,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Checks scenes without opening a window, see `Scene::validate`, prints diagnostics and fails if any scene has them.
+#[cfg(not(target_arch = "wasm32"))]
+fn validate(paths: &[String]) -> Result<(), String> {
+    if paths.is_empty() {
+        return Err("Usage: portal validate <scene.json>...".to_owned());
+    }
+
+    let mut failed = 0;
+    for path in paths {
+        let diagnostics = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Scene::from_text(&text))
+            .map(|scene| {
+                scene
+                    .validate_in(std::path::Path::new(path).parent())
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(|err| vec![err]);
+        for diagnostic in &diagnostics {
+            println!("{}: {}", path, diagnostic);
+        }
+        failed += !diagnostics.is_empty() as usize;
+    }
+
+    if failed > 0 {
+        Err(format!("{} of {} scenes have errors", failed, paths.len()))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_scene(path: &str) -> Result<Scene, String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Scene::from_text(&text))
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Prints changes between two scenes entity by entity.
+#[cfg(not(target_arch = "wasm32"))]
+fn diff(args: &[String]) -> Result<(), String> {
+    let (a, b) = match args {
+        [a, b] => (read_scene(a)?, read_scene(b)?),
+        _ => return Err("Usage: portal diff <old scene> <new scene>".to_owned()),
+    };
+    for change in a.diff(&b) {
+        println!("{}", change);
+    }
+    Ok(())
+}
+
+/// Three-way merge of scenes, can be used as git merge driver: `portal merge %O %A %B`. Result is written to `ours` unless `--output=` is given. Git passes temporary files without extension, so result is written in format of `ours` unless output has known extension.
+#[cfg(not(target_arch = "wasm32"))]
+fn merge(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "Usage: portal merge <base> <ours> <theirs> [--output=<scene>]";
+    let (paths, output): (Vec<_>, Vec<_>) = args.iter().partition(|x| !x.starts_with("--"));
+    let output = match &output[..] {
+        [] => None,
+        [output] if output.starts_with("--output=") => Some(&output["--output=".len()..]),
+        _ => return Err(USAGE.to_owned()),
+    };
+    let (base, ours, theirs) = match &paths[..] {
+        [base, ours, theirs] => (base, ours, theirs),
+        _ => return Err(USAGE.to_owned()),
+    };
+
+    let (merged, conflicts) =
+        Scene::three_way_merge(&read_scene(base)?, &read_scene(ours)?, &read_scene(theirs)?)?;
+    let format = match output
+        .and_then(|x| std::path::Path::new(x).extension())
+        .and_then(|x| x.to_str())
+        .and_then(SceneFormat::from_extension)
+    {
+        Some(format) => format,
+        None => std::fs::read_to_string(ours)
+            .map(|text| SceneFormat::detect(&text))
+            .map_err(|e| format!("{}: {}", ours, e))?,
+    };
+    let output = output.unwrap_or(ours);
+    std::fs::write(output, merged.to_text(format)).map_err(|e| format!("{}: {}", output, e))?;
+
+    for conflict in &conflicts {
+        eprintln!("conflict: {}", conflict);
+    }
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} conflicts, version from `{}` is kept for them",
+            conflicts.len(),
+            ours
+        ))
+    }
+}
+
+fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let result = match args.first().map(|x| &x[..]) {
+            Some("render") => Some(offline_render(&args[1..])),
+            Some("validate") => Some(validate(&args[1..])),
+            Some("diff") => Some(diff(&args[1..])),
+            Some("merge") => Some(merge(&args[1..])),
+            _ => None,
+        };
+        if let Some(result) = result {
+            if let Err(err) = result {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
+    macroquad::Window::from_config(window_conf(), amain());
+}
+
+async fn amain() {
     // color_backtrace::install();
 
     let mut window = Window::new().await;
@@ -776,9 +1548,16 @@ async fn main() {
             );
         }
 
-        egui.ui(|ctx| {
-            ui_changed_image = window.process_mouse_and_keys(ctx);
-        });
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            egui.ui(|ctx| {
+                ui_changed_image = window.process_mouse_and_keys(ctx);
+            });
+        }));
+        if let Err(panic) = result {
+            // Edits after the last autosave would be lost otherwise.
+            window.autosave.save(&window.scene);
+            std::panic::resume_unwind(panic);
+        }
 
         window.reload_textures().await;
 
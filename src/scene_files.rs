@@ -0,0 +1,206 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gui::format::SceneFormat;
+use crate::gui::scene::Scene;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+
+/// Scene that can be chosen in `Load` menu.
+#[derive(Debug, Clone)]
+pub struct SceneFile {
+    /// Shown in menu.
+    pub name: String,
+
+    /// Used in `--scene=` parameter and in links.
+    pub id: String,
+
+    pub content: String,
+
+    /// File from which scene is loaded, `None` for scenes embedded into binary.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub path: Option<PathBuf>,
+}
+
+impl SceneFile {
+    fn embedded(name: &str, id: &str, content: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            id: id.to_owned(),
+            content: content.to_owned(),
+            #[cfg(not(target_arch = "wasm32"))]
+            path: None,
+        }
+    }
+}
+
+/// Scenes that are compiled into binary, they are the only ones available in web version.
+pub fn embedded_scenes() -> Vec<SceneFile> {
+    vec![
+        SceneFile::embedded("Empty", "empty", include_str!("../scenes/empty.json")),
+        SceneFile::embedded("Room", "room", include_str!("../scenes/room.json")),
+        SceneFile::embedded(
+            "Monoportal",
+            "monoportal",
+            include_str!("../scenes/monoportal.json"),
+        ),
+        // SceneFile::embedded(
+        //     "Monoportal offset",
+        //     "monoportal_offset",
+        //     include_str!("../scenes/monoportal_offset.json"),
+        // ),
+        SceneFile::embedded(
+            "Mobius portal",
+            "mobius",
+            include_str!("../scenes/mobius.json"),
+        ),
+        SceneFile::embedded(
+            "Mobius monoportal",
+            "mobius_monoportal",
+            include_str!("../scenes/mobius_monoportal.json"),
+        ),
+        // SceneFile::embedded("Misc", "misc", include_str!("../scenes/misc.json")),
+    ]
+}
+
+/// Returns embedded scene by its id, these scenes are used as base for shareable links.
+pub fn embedded_scene(id: &str) -> Result<Scene, String> {
+    embedded_scenes()
+        .iter()
+        .find(|x| x.id == id)
+        .ok_or_else(|| format!("unknown embedded scene `{}`", id))
+        .and_then(parse_scene)
+}
+
+/// `mobius_monoportal` → `Mobius monoportal`.
+#[cfg(not(target_arch = "wasm32"))]
+fn name_from_id(id: &str) -> String {
+    let name = id.replace('_', " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_scene_file(path: &Path) -> Result<SceneFile, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let id = path
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(SceneFile {
+        name: name_from_id(&id),
+        id,
+        content,
+        path: Some(path.to_owned()),
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn scene_format(path: &Path) -> Option<SceneFormat> {
+    SceneFormat::from_extension(path.extension()?.to_str()?)
+}
+
+/// Returns all scene files from directory, sorted by name.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn scan_scenes_dir(dir: &Path) -> Result<Vec<SceneFile>, String> {
+    let mut paths = std::fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| scene_format(path).is_some())
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.iter().map(|path| load_scene_file(path)).collect()
+}
+
+/// Format is chosen by extension, JSON is used for unknown extensions.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_scene_file(scene: &Scene, path: &Path) -> Result<(), String> {
+    let content = scene.to_text(scene_format(path).unwrap_or_default());
+    std::fs::write(path, content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Finds scene by `--scene=` parameter, which is id of available scene or, in native version, path to scene file.
+pub fn find_scene(scenes: &mut Vec<SceneFile>, param: &str) -> Option<usize> {
+    if let Some(pos) = scenes.iter().position(|x| x.id == param) {
+        return Some(pos);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let path = Path::new(param);
+        if let Some(pos) = scenes.iter().position(|x| x.path.as_deref() == Some(path)) {
+            return Some(pos);
+        }
+        match load_scene_file(path) {
+            Ok(file) => {
+                scenes.push(file);
+                return Some(scenes.len() - 1);
+            }
+            Err(err) => eprintln!("can't load scene: {}", err),
+        }
+    }
+
+    None
+}
+
+pub fn parse_scene(file: &SceneFile) -> Result<Scene, String> {
+    Scene::from_text(&file.content).map_err(|err| format!("{}: {}", file.name, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_scenes_are_in_dir() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let scanned = scan_scenes_dir(&dir).unwrap();
+        for embedded in embedded_scenes() {
+            let file = scanned.iter().find(|x| x.id == embedded.id).unwrap();
+            assert_eq!(file.content, embedded.content);
+            parse_scene(file).unwrap();
+        }
+        let mobius = scanned
+            .iter()
+            .find(|x| x.id == "mobius_monoportal")
+            .unwrap();
+        assert_eq!(mobius.name, "Mobius monoportal");
+    }
+
+    #[test]
+    fn find_by_id_or_path() {
+        let mut scenes = embedded_scenes();
+        let len = scenes.len();
+        assert_eq!(find_scene(&mut scenes, "room"), Some(1));
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/misc.json");
+        let pos = find_scene(&mut scenes, path.to_str().unwrap());
+        assert_eq!(pos, Some(len));
+        assert_eq!(find_scene(&mut scenes, path.to_str().unwrap()), Some(len));
+        assert_eq!(scenes[len].id, "misc");
+
+        assert_eq!(find_scene(&mut scenes, "no/such/scene.json"), None);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let scene = embedded_scene("monoportal").unwrap();
+        for name in &["portal_save_and_load.json", "portal_save_and_load.yaml"] {
+            let path = std::env::temp_dir().join(name);
+            save_scene_file(&scene, &path).unwrap();
+            let file = load_scene_file(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(
+                SceneFormat::detect(&file.content),
+                scene_format(&path).unwrap()
+            );
+            assert_eq!(
+                serde_json::to_value(&scene).unwrap(),
+                serde_json::to_value(parse_scene(&file).unwrap()).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Translation of generated shader to WGSL. Shader is generated for GLSL 4.50, converted to form that Vulkan requires, and then parsed and validated by naga, see `parse_glsl`, and written as WGSL.
+
+use crate::code_generation::StringStorage;
+use crate::glsl_validation::parse_glsl;
+
+/// Converts shader with loose uniforms to GLSL 4.50 for Vulkan: non-texture uniforms are moved to one uniform block, textures are split into texture and sampler, inputs and outputs get locations. Every line of code stays at its place, so line numbers and sources of lines are kept, only declarations are added at the start.
+pub fn to_vulkan_glsl(mut code: StringStorage) -> StringStorage {
+    let mut uniforms = String::new();
+    let mut textures = String::new();
+    let mut binding = 1;
+    let mut input_location = 0;
+    let mut output_location = 0;
+
+    let mut lines = Vec::new();
+    for line in code.storage.lines() {
+        let declaration = line.split("//").next().unwrap_or_default().trim();
+        let words = declaration
+            .trim_end_matches(';')
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let line = match &words[..] {
+            ["uniform", "sampler2D", name] => {
+                textures += &format!(
+                    "layout(set = 0, binding = {}) uniform texture2D {}_texture;\n",
+                    binding, name
+                );
+                textures += &format!(
+                    "layout(set = 0, binding = {}) uniform sampler {}_sampler;\n",
+                    binding + 1,
+                    name
+                );
+                binding += 2;
+                format!("#define {0} sampler2D({0}_texture, {0}_sampler)", name)
+            }
+            ["uniform", kind, name] => {
+                uniforms += &format!("    {} {};\n", kind, name);
+                String::new()
+            }
+            ["in", _, _] if !line.starts_with(' ') => {
+                input_location += 1;
+                format!("layout(location = {}) {}", input_location - 1, line)
+            }
+            ["out", _, _] if !line.starts_with(' ') => {
+                output_location += 1;
+                format!("layout(location = {}) {}", output_location - 1, line)
+            }
+            _ => line.to_owned(),
+        };
+        lines.push(line);
+    }
+
+    let mut result = StringStorage::default();
+    result.add_string("#version 450\n");
+    if !uniforms.is_empty() {
+        result.add_string(format!(
+            "layout(set = 0, binding = 0) uniform Uniforms {{\n{}}};\n",
+            uniforms
+        ));
+    }
+    result.add_string(textures);
+    code.storage = lines.join("\n") + "\n";
+    result.add_string_storage(code);
+    result
+}
+
+/// Translates fragment shader in GLSL 4.50 for Vulkan to WGSL. Errors are returned with line numbers of GLSL code.
+pub fn glsl_to_wgsl(code: &str) -> Result<String, Vec<(usize, String)>> {
+    use naga::back::wgsl::{write_string, WriterFlags};
+
+    let (module, info) = parse_glsl(code)?;
+    write_string(&module, &info, WriterFlags::empty())
+        .map_err(|error| vec![(usize::MAX, error.to_string())])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vulkan_declarations() {
+        let mut code = StringStorage::default();
+        code.add_string("uniform float a; // comment\nin vec2 uv;\nout vec4 color;\n");
+        code.add_string("uniform sampler2D t;\nvoid f(in vec2 x, out vec4 y) {}\n");
+        let code = to_vulkan_glsl(code);
+        assert_eq!(
+            code.storage,
+            "#version 450
+layout(set = 0, binding = 0) uniform Uniforms {
+    float a;
+};
+layout(set = 0, binding = 1) uniform texture2D t_texture;
+layout(set = 0, binding = 2) uniform sampler t_sampler;
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+#define t sampler2D(t_texture, t_sampler)
+void f(in vec2 x, out vec4 y) {}
+"
+        );
+    }
+
+    #[test]
+    fn errors_have_lines() {
+        let code = "#version 450
+layout(location = 0) out vec4 color;
+void main() {
+    color = vec4(undeclared);
+}
+";
+        let errors = glsl_to_wgsl(code).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 4);
+        assert!(glsl_to_wgsl(&code.replace("undeclared", "1.")).is_ok());
+    }
+
+    #[test]
+    fn embedded_scenes_are_translated() {
+        use crate::gui::scene::{Scene, ShaderOptions};
+        use crate::scene_files::*;
+
+        for file in embedded_scenes() {
+            let scene: Scene = parse_scene(&file).unwrap();
+            for panini in &[false, true] {
+                for pack_uniforms in &[false, true] {
+                    let options = ShaderOptions {
+                        panini: *panini,
+                        pack_uniforms: *pack_uniforms,
+                        ..Default::default()
+                    };
+                    match scene.generate_wgsl(&options) {
+                        Ok(code) => assert!(code.contains("@fragment"), "{}", file.id),
+                        Err((_, message, _)) => panic!("{}, {:?}: {}", file.id, options, message),
+                    }
+                }
+            }
+        }
+    }
+}
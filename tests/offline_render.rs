@@ -0,0 +1,37 @@
+//! `portal render` needs OpenGL context, so on Linux this test is skipped when there is no display, use `xvfb-run cargo test` on such machines.
+
+use std::path::Path;
+use std::process::Command;
+
+fn has_display() -> bool {
+    !cfg!(target_os = "linux") || std::env::var_os("DISPLAY").is_some()
+}
+
+#[test]
+fn bundled_scene_is_rendered() {
+    if !has_display() {
+        eprintln!("skipped: no display to create OpenGL context");
+        return;
+    }
+
+    let scene = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/monoportal.json");
+    let output = std::env::temp_dir().join(format!("portal_render_{}.png", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_portal"))
+        .arg("render")
+        .arg(format!("--scene={}", scene.display()))
+        .arg(format!("--output={}", output.display()))
+        .args(&["--width=64", "--height=48"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let decoder = png::Decoder::new(std::fs::File::open(&output).unwrap());
+    let (info, mut reader) = decoder.read_info().unwrap();
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    std::fs::remove_file(&output).unwrap();
+    assert_eq!((info.width, info.height), (64, 48));
+    // Portals and walls are drawn, not only background.
+    let first = &pixels[..4];
+    assert!(pixels.chunks(4).any(|x| x != first));
+}